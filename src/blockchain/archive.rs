@@ -0,0 +1,138 @@
+//! export and import the blocks of a local blockchain as a tar archive
+//!
+//! The archive holds the epoch packs, their indexes, the loose blocks
+//! and the tags, with their paths relative to the blockchain directory.
+//! The configuration is included so the network of the archive can be
+//! checked on import, it does not replace the local configuration.
+//!
+//! On import the archive is unpacked in a staging directory and its
+//! blocks are verified (see `Blockchain::verify_links`) before anything
+//! is moved into the storage. Only the packs and blocks missing from the
+//! storage are added, and a tag is only moved forward: when the local
+//! tag points to an ancestor of the archive's.
+//!
+
+use std::{fs, io::{Read, Write}, path::Path};
+use cardano_storage::tag;
+use tar;
+use utils::cancel::CancelToken;
+
+use super::{Blockchain, Error, Result};
+
+/// the directories of the storage that are archived
+const ARCHIVED_DIRECTORIES : [&'static str; 5] = ["pack", "index", "epoch", "blob", "tag"];
+
+/// the directory of the storage holding the tags
+const TAG_DIRECTORY : &'static str = "tag";
+
+/// the directory, within the blockchain directory, where the archive is
+/// unpacked while being verified
+const IMPORT_DIRECTORY : &'static str = "import.tmp";
+
+/// list the files of the given directory and its sub-directories
+fn list_files(dir: &Path, files: &mut Vec<::std::path::PathBuf>) -> Result<()> {
+    if ! dir.is_dir() { return Ok(()); }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl Blockchain {
+    /// write the storage of the blockchain in the given tar archive, the
+    /// number of archived files is returned.
+    pub fn export<W: Write>(&self, output: W) -> Result<usize> {
+        let mut files = vec![self.storage_config.get_config_file()];
+        for directory in ARCHIVED_DIRECTORIES.iter() {
+            list_files(&self.dir.join(directory), &mut files)?;
+        }
+
+        let mut builder = tar::Builder::new(output);
+        for file in files.iter() {
+            let name = file.strip_prefix(&self.dir).expect("archived file outside of the blockchain");
+            builder.append_path_with_name(file, name)?;
+        }
+        builder.into_inner()?.flush()?;
+        Ok(files.len())
+    }
+
+    /// import the blocks and the tags of the given tar archive, the
+    /// number of imported files is returned (the files already in the
+    /// storage and the tags left behind are not counted).
+    ///
+    /// fails with `Error::IncompatibleArchive` if the archive is of
+    /// another network, and with `Error::InvalidArchive` if some of its
+    /// blocks are invalid. The storage is left untouched in both cases.
+    pub fn import<R: Read>(&self, input: R) -> Result<usize> {
+        let staging_dir = self.dir.join(IMPORT_DIRECTORY);
+        if staging_dir.exists() { fs::remove_dir_all(&staging_dir)?; }
+        tar::Archive::new(input).unpack(&staging_dir)?;
+
+        let result = self.import_staging(&staging_dir);
+        fs::remove_dir_all(&staging_dir)?;
+        let imported = result?;
+
+        // the blocks of the archive are not in the bloom filter yet
+        self.rebuild_index()?;
+        Ok(imported)
+    }
+
+    fn import_staging(&self, staging_dir: &Path) -> Result<usize> {
+        let config_file = self.storage_config.get_config_file();
+        let config_name = config_file.strip_prefix(&self.dir).expect("config file outside of the blockchain");
+        let staging_config_file = staging_dir.join(config_name);
+        let config = super::read_config(&staging_config_file)?.config;
+        if config.genesis_prev != self.config.genesis_prev || config.protocol_magic != self.config.protocol_magic {
+            return Err(Error::IncompatibleArchive);
+        }
+
+        let staging = Blockchain::load_from(staging_dir.to_path_buf(), self.name.clone())?;
+        let report = staging.verify_links(&CancelToken::new())?;
+        if ! report.is_valid() {
+            return Err(Error::InvalidArchive { invalid_blocks: report.invalid_blocks.len() });
+        }
+
+        let mut files = Vec::new();
+        for directory in ARCHIVED_DIRECTORIES.iter() {
+            list_files(&staging_dir.join(directory), &mut files)?;
+        }
+        let mut imported = 0;
+        for file in files.iter() {
+            let name = file.strip_prefix(staging_dir).expect("unpacked file outside of the staging directory");
+            let is_tag = name.starts_with(TAG_DIRECTORY);
+            if is_tag && ! self.moves_tag_forward(&staging, name)? { continue; }
+            if ! is_tag && self.dir.join(name).exists() { continue; }
+
+            let destination = self.dir.join(name);
+            if let Some(parent) = destination.parent() { fs::create_dir_all(parent)?; }
+            fs::rename(file, destination)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
+    /// the tag of the given file of the archive is new to the storage, or
+    /// the local tag points to an ancestor of the archive's one
+    ///
+    /// the ancestry is checked in the staging storage: it holds the
+    /// whole chain of the archive's tag.
+    fn moves_tag_forward(&self, staging: &Blockchain, name: &Path) -> Result<bool> {
+        let tag_name = name.strip_prefix(TAG_DIRECTORY).expect("tag outside of the tag directory");
+        let tag_name = tag_name.to_string_lossy();
+        let local = match tag::read_hash(self.storage(), &tag_name) {
+            None => return Ok(true),
+            Some(hash) => hash,
+        };
+        match tag::read_hash(staging.storage(), &tag_name) {
+            None => Ok(false),
+            Some(ref staged) if staged == &local => Ok(false),
+            Some(staged) => staging.is_ancestor_of(&local, &staged),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! bloom filter of the block hashes present in the local storage
+//!
+//! The filter is used to short-circuit lookups of blocks we definitely
+//! do not have. A match may be a false positive, the storage still needs
+//! to be queried to confirm the block is present.
+//!
+//! The block hashes are already uniformly distributed, so the positions
+//! in the filter are derived directly from the bytes of the hash.
+//!
+
+use cardano::block::HeaderHash;
+use storage_units::utils::{serialize, tmpfile::{TmpFile}};
+use std::{fs, io::{self, Read, Write}, path::{Path, PathBuf}, sync::{Mutex}};
+
+const MAGIC : &'static [u8] = b"BLM1";
+
+/// number of bits per expected element, gives roughly 1% false positives
+const BITS_PER_ELEMENT : usize = 10;
+const NR_HASHES : u32 = 7;
+
+pub struct BloomFilter {
+    nr_hashes: u32,
+    bits: Vec<u64>,
+}
+impl BloomFilter {
+    /// create an empty filter sized for the given number of hashes
+    pub fn new(capacity: usize) -> Self {
+        let nr_bits = ::std::cmp::max(capacity, 1024) * BITS_PER_ELEMENT;
+        BloomFilter {
+            nr_hashes: NR_HASHES,
+            bits: vec![0; (nr_bits + 63) / 64],
+        }
+    }
+
+    fn positions<'a>(&'a self, hash: &HeaderHash) -> impl Iterator<Item = usize> + 'a {
+        let bytes = hash.as_ref();
+        let mut h1 = 0u64;
+        let mut h2 = 0u64;
+        for i in 0..8 {
+            h1 = (h1 << 8) | bytes[i] as u64;
+            h2 = (h2 << 8) | bytes[8 + i] as u64;
+        }
+        let nr_bits = self.bits.len() as u64 * 64;
+        (0..self.nr_hashes as u64).map(move |i| {
+            (h1.wrapping_add(i.wrapping_mul(h2)) % nr_bits) as usize
+        })
+    }
+
+    pub fn insert(&mut self, hash: &HeaderHash) {
+        let positions : Vec<usize> = self.positions(hash).collect();
+        for pos in positions {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+    }
+
+    /// `false` if the hash is definitely not in the filter
+    pub fn contains(&self, hash: &HeaderHash) -> bool {
+        self.positions(hash).all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// load the filter from the given file, `None` if the file does not exist
+    pub fn read_from<P: AsRef<Path>>(path: P) -> io::Result<Option<Self>> {
+        let mut file = match fs::File::open(path) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(file) => file,
+        };
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a bloom filter file"));
+        }
+        let nr_hashes = serialize::utils::read_u32(&mut file)?;
+        let nr_words = serialize::utils::read_u64(&mut file)? as usize;
+        let mut bits = Vec::with_capacity(nr_words);
+        for _ in 0..nr_words {
+            bits.push(serialize::utils::read_u64(&mut file)?);
+        }
+
+        Ok(Some(BloomFilter { nr_hashes, bits }))
+    }
+
+    /// save the filter (atomically) in the given file
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let dir = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        let mut tmpfile = TmpFile::create(dir)?;
+        tmpfile.write_all(MAGIC)?;
+        serialize::utils::write_u32(&mut tmpfile, self.nr_hashes)?;
+        serialize::utils::write_u64(&mut tmpfile, self.bits.len() as u64)?;
+        for word in self.bits.iter() {
+            serialize::utils::write_u64(&mut tmpfile, *word)?;
+        }
+        tmpfile.render_permanent(&path.to_path_buf())
+    }
+}
+
+/// the bloom filter of a blockchain, loaded from the disk on first use
+///
+/// While blocks are being written (see `suspend`) the file is removed
+/// so an interrupted process never leaves a filter missing some blocks.
+/// A missing filter is equivalent to a filter matching every hash.
+pub struct LazyBloomFilter {
+    path: PathBuf,
+    filter: Mutex<Option<Option<BloomFilter>>>,
+}
+impl LazyBloomFilter {
+    pub fn new(path: PathBuf) -> Self {
+        LazyBloomFilter { path, filter: Mutex::new(None) }
+    }
+
+    fn with_filter<F, T>(&self, f: F) -> T
+        where F: FnOnce(&mut Option<BloomFilter>) -> T
+    {
+        let mut filter = self.filter.lock().unwrap();
+        if filter.is_none() {
+            *filter = Some(BloomFilter::read_from(&self.path).unwrap_or_else(|err| {
+                warn!("ignoring the bloom filter {:?}: {}", self.path, err);
+                None
+            }));
+        }
+        f(filter.as_mut().unwrap())
+    }
+
+    /// `false` if the hash is definitely not stored
+    pub fn might_contain(&self, hash: &HeaderHash) -> bool {
+        self.with_filter(|filter| {
+            match filter {
+                None => true,
+                Some(filter) => filter.contains(hash),
+            }
+        })
+    }
+
+    /// take the filter out before adding new blocks to the storage
+    ///
+    /// the filter needs to be given back with `resume` once the blocks
+    /// are written.
+    pub fn suspend(&self) -> io::Result<Option<BloomFilter>> {
+        let filter = self.with_filter(|filter| filter.take());
+        if filter.is_some() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(filter)
+    }
+
+    /// set (and save) the filter of the blockchain
+    pub fn resume(&self, filter: BloomFilter) -> io::Result<()> {
+        filter.write_to(&self.path)?;
+        *self.filter.lock().unwrap() = Some(Some(filter));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use cryptoxide::blake2b::Blake2b;
+
+    fn hash(i: u32) -> HeaderHash {
+        let mut out = [0u8; 32];
+        Blake2b::blake2b(&mut out, &[(i >> 24) as u8, (i >> 16) as u8, (i >> 8) as u8, i as u8], &[]);
+        HeaderHash::from(out)
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::new(1000);
+        for i in 0..1000 { filter.insert(&hash(i)); }
+        for i in 0..1000 { assert!(filter.contains(&hash(i))); }
+    }
+
+    #[test]
+    fn few_false_positives() {
+        let mut filter = BloomFilter::new(1000);
+        for i in 0..1000 { filter.insert(&hash(i)); }
+        let false_positives = (1000..11000).filter(|i| filter.contains(&hash(*i))).count();
+        assert!(false_positives < 300, "too many false positives: {}", false_positives);
+    }
+}
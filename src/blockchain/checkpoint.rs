@@ -0,0 +1,83 @@
+//! start a local blockchain from a trusted checkpoint
+//!
+//! A checkpoint is the epoch boundary block of an epoch. The blockchain
+//! created from a checkpoint starts at this block instead of the genesis
+//! of the network: the block becomes the `genesis` of the configuration
+//! and its epoch the `epoch_start`, the checkpoint itself is recorded in
+//! the configuration file (see `Blockchain::checkpoint`). The local tip and the remotes' tips
+//! then start at the checkpoint (see `Blockchain::load_tip`), the first
+//! sync downloads the blocks from the checkpoint onward.
+//!
+//! The checkpoint is trusted, nothing before it is downloaded or verified.
+//!
+
+use std::{fmt, str::FromStr, path::PathBuf};
+use cardano::block::{EpochId, HeaderHash};
+
+use super::{config, Blockchain, Config, Result};
+
+/// the epoch boundary block of `epoch`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub epoch: EpochId,
+    pub hash: HeaderHash,
+}
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.epoch, self.hash)
+    }
+}
+impl FromStr for Checkpoint {
+    type Err = &'static str;
+    /// parse `<epoch>:<hash>`
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let epoch = parts.next().and_then(|epoch| epoch.parse().ok())
+            .ok_or("invalid checkpoint epoch, expected `<epoch>:<hash>'")?;
+        let hash = parts.next().and_then(|hash| HeaderHash::from_str(hash).ok())
+            .ok_or("invalid checkpoint hash, expected `<epoch>:<hash>'")?;
+        Ok(Checkpoint { epoch, hash })
+    }
+}
+
+impl Blockchain {
+    /// create a new blockchain starting from the given checkpoint
+    /// rather than from the genesis of the network.
+    ///
+    /// `genesis_prev` is kept, it identifies the network (its genesis
+    /// data and time parameters).
+    pub fn new_from_checkpoint(root_dir: PathBuf, name: String, mut config: Config, checkpoint: &Checkpoint)
+        -> Result<Self>
+    {
+        config.genesis = checkpoint.hash.clone();
+        config.epoch_start = checkpoint.epoch;
+        let dir = config::directory(root_dir, &name);
+        Blockchain::create(dir, name, config, Some(checkpoint.clone()))
+    }
+
+    /// the blockchain does not start from the genesis of the network
+    /// (see `new_from_checkpoint`), the parent of its first block is
+    /// not stored.
+    pub fn is_from_checkpoint(&self) -> bool {
+        self.checkpoint.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const HASH : &'static str = "89d9b5a5b8ddc8d7e5a6795e9774d97faf1efea59b2caf7eaf9f8c5b32059df4";
+
+    #[test]
+    fn parse_checkpoint() {
+        let checkpoint : Checkpoint = format!("42:{}", HASH).parse().unwrap();
+        assert_eq!(checkpoint.epoch, 42);
+        assert_eq!(checkpoint.hash, HeaderHash::from_str(HASH).unwrap());
+        assert_eq!(format!("{}", checkpoint), format!("42:{}", HASH));
+
+        assert!(HASH.parse::<Checkpoint>().is_err());
+        assert!(format!("x:{}", HASH).parse::<Checkpoint>().is_err());
+        assert!("42:abcd".parse::<Checkpoint>().is_err());
+    }
+}
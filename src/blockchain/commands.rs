@@ -4,14 +4,46 @@ use std::io::{Write};
 use exe_common::config::net::Config;
 use cardano_storage as storage;
 
-use utils::term::Term;
+use utils::{term::Term, cancel::CancelToken};
 
 use super::peer;
-use super::Blockchain;
+use super::protocol::Protocol;
+use super::{Blockchain, Error};
 use super::parse_genesis_data;
 use super::genesis_data;
 use cardano::{self, block::{RawBlock}};
 
+/// report the error, along with what to do about it (see `Error::hint`),
+/// and exit with the code of the error (see `Error::exit_code`)
+pub fn fail_with(term: &mut Term, err: Error) -> ! {
+    term.report_error(&err);
+    if let Some(hint) = err.hint() {
+        term.info(&format!("hint: {}\n", hint)).unwrap();
+    }
+    ::std::process::exit(err.exit_code())
+}
+
+/// load the blockchain to modify it (see `Blockchain::load`), or exit
+/// with the diagnostic of the error
+///
+/// the storage is opened right away so `Blockchain::storage` cannot fail
+/// afterward.
+pub fn load(term: &mut Term, root_dir: PathBuf, name: String) -> Blockchain {
+    let blockchain = Blockchain::load(root_dir, name).unwrap_or_else(|err| fail_with(term, err));
+    if let Err(err) = blockchain.open_storage() { fail_with(term, err) }
+    blockchain
+}
+
+/// load the blockchain to only read it (see `Blockchain::load_read_only`),
+/// or exit with the diagnostic of the error
+///
+/// the storage is opened right away, as with `load`.
+pub fn load_read_only(term: &mut Term, root_dir: PathBuf, name: String) -> Blockchain {
+    let blockchain = Blockchain::load_read_only(root_dir, name).unwrap_or_else(|err| fail_with(term, err));
+    if let Err(err) = blockchain.open_storage() { fail_with(term, err) }
+    blockchain
+}
+
 /// function to create and initialize a given new blockchain
 ///
 /// It will mainly create the subdirectories needed for the storage
@@ -26,12 +58,19 @@ pub fn new( mut term: Term
           , root_dir: PathBuf
           , name: String
           , config: Config
+          , checkpoint: Option<super::checkpoint::Checkpoint>
           )
 {
-    let blockchain = Blockchain::new(root_dir, name.clone(), config);
-    blockchain.save();
+    let blockchain = match checkpoint {
+        None => Blockchain::new(root_dir, name.clone(), config),
+        Some(ref checkpoint) => Blockchain::new_from_checkpoint(root_dir, name.clone(), config, checkpoint),
+    }.unwrap_or_else(|e| fail_with(&mut term, e));
+    blockchain.save().unwrap_or_else(|e| fail_with(&mut term, e));
 
     term.success(&format!("local blockchain `{}' created.\n", &name)).unwrap();
+    if let Some(checkpoint) = checkpoint {
+        term.info(&format!("the blocks will be fetched from the checkpoint {}\n", checkpoint)).unwrap();
+    }
 }
 
 pub fn list( mut term: Term
@@ -50,11 +89,17 @@ pub fn list( mut term: Term
             panic!("invalid utf8... {:?}", err)
         });
 
-        let blockchain = Blockchain::load(root_dir.clone(), name);
+        let blockchain = match Blockchain::load_read_only(root_dir.clone(), name.clone()) {
+            Ok(blockchain) => blockchain,
+            Err(err) => {
+                term.error(&format!("{}: {}\n", name, err)).unwrap();
+                continue;
+            },
+        };
 
         term.info(&blockchain.name).unwrap();
         if detailed {
-            let (tip, _is_genesis) = blockchain.load_tip();
+            let (tip, _is_genesis) = blockchain.load_tip().unwrap_or_else(|e| fail_with(&mut term, e));
             let tag_path = blockchain.dir.join("tag").join(super::LOCAL_BLOCKCHAIN_TIP_TAG);
             let metadata = ::std::fs::metadata(tag_path).unwrap();
             let now = ::std::time::SystemTime::now();
@@ -75,7 +120,7 @@ pub fn destroy( mut term: Term
               , name: String
               )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load(&mut term, root_dir, name);
 
     writeln!(term, "You are about to destroy the local blockchain {}.
 This means that all the blocks downloaded will be deleted and that the attached
@@ -106,13 +151,20 @@ pub fn remote_add( mut term: Term
                  , name: String
                  , remote_alias: String
                  , remote_endpoint: String
+                 , protocol: Option<Protocol>
                  )
 {
-    let mut blockchain = Blockchain::load(root_dir, name);
-    blockchain.add_peer(remote_alias.clone(), remote_endpoint);
-    blockchain.save();
+    let protocol = protocol.unwrap_or_else(|| Protocol::guess(&remote_endpoint));
+    let remote_endpoint = protocol.endpoint(&remote_endpoint).unwrap_or_else(|err| {
+        term.error(&format!("{}\n", err)).unwrap();
+        ::std::process::exit(1)
+    });
+
+    let mut blockchain = load(&mut term, root_dir, name);
+    blockchain.add_peer(remote_alias.clone(), remote_endpoint, protocol);
+    blockchain.save().unwrap_or_else(|e| fail_with(&mut term, e));
 
-    term.success(&format!("remote `{}' node added to blockchain `{}'\n", remote_alias, blockchain.name)).unwrap();
+    term.success(&format!("remote `{}' node ({}) added to blockchain `{}'\n", remote_alias, protocol, blockchain.name)).unwrap();
 }
 
 /// remove the given peer from the blockchain
@@ -126,9 +178,9 @@ pub fn remote_rm( mut term: Term
                 , remote_alias: String
                 )
 {
-    let mut blockchain = Blockchain::load(root_dir, name);
+    let mut blockchain = load(&mut term, root_dir, name);
     blockchain.remove_peer(remote_alias.clone());
-    blockchain.save();
+    blockchain.save().unwrap_or_else(|e| fail_with(&mut term, e));
 
     term.success(&format!("remote `{}' node removed from blockchain `{}'\n", remote_alias, blockchain.name)).unwrap();
 }
@@ -139,15 +191,25 @@ pub fn remote_fetch( mut term: Term
                    , peers: Vec<String>
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load(&mut term, root_dir, name);
 
     for np in blockchain.peers() {
         if peers.is_empty() || peers.contains(&np.name().to_owned()) {
             term.info(&format!("fetching blocks from peer: {}\n", np.name())).unwrap();
 
-            let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
+            let peer = peer::Peer::prepare(&blockchain, np.name().to_owned())
+                .unwrap_or_else(|e| fail_with(&mut term, e));
 
-            peer.connect(&mut term).unwrap().sync(&mut term);
+            // the handshake failure is reported by `connect`
+            let mut connected_peer = match peer.connect(&mut term) {
+                Err(()) => continue,
+                Ok(connected_peer) => connected_peer,
+            };
+            if let Err(err) = connected_peer.probe_tip() {
+                term.warn(&format!("skipping peer {}, its tip cannot be fetched: {}\n", np.name(), err)).unwrap();
+                continue;
+            }
+            connected_peer.sync(&mut term).unwrap_or_else(|e| fail_with(&mut term, e));
         }
     }
 }
@@ -159,23 +221,44 @@ pub enum RemoteDetail {
     Remote
 }
 
+/// list the remotes of the blockchain, with `RemoteDetail::Remote` the
+/// remotes are queried for their tips: the remotes not answering within
+/// `timeout` are reported as such (see `peer::query_remote_tips`)
 pub fn remote_ls( mut term: Term
                 , root_dir: PathBuf
                 , name: String
                 , detailed: RemoteDetail
+                , timeout: ::std::time::Duration
                 )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load_read_only(&mut term, root_dir, name);
+
+    let mut remote_tips : ::std::collections::BTreeMap<String, super::Result<_>> = if detailed >= RemoteDetail::Remote {
+        let names : Vec<String> = blockchain.peers().map(|np| np.name().to_owned()).collect();
+        peer::query_remote_tips(&blockchain, &names, timeout).into_iter().collect()
+    } else {
+        ::std::collections::BTreeMap::new()
+    };
 
     for np in blockchain.peers() {
-        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
-        let (tip, _is_genesis) = peer.load_local_tip();
+        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned())
+            .unwrap_or_else(|e| fail_with(&mut term, e));
 
         term.info(&format!("{}", peer.name)).unwrap();
         term.simply(" (").unwrap();
         term.success(&format!("{}", peer.config)).unwrap();
+        term.simply(", ").unwrap();
+        term.success(&format!("{}", peer.protocol())).unwrap();
         term.simply(")\n").unwrap();
 
+        let (tip, _is_genesis) = match peer.load_local_tip() {
+            Ok(tip) => tip,
+            Err(err) => {
+                term.error(&format!(" * cannot load the local tip: {}\n", err)).unwrap();
+                continue;
+            }
+        };
+
         if detailed >= RemoteDetail::Local {
             let tag_path = blockchain.dir.join("tag").join(&peer.tag);
             let metadata = ::std::fs::metadata(tag_path).unwrap();
@@ -194,9 +277,14 @@ pub fn remote_ls( mut term: Term
             term.success(&format!("{}", tip.date)).unwrap();
             term.simply("\n").unwrap();
 
-            if detailed >= RemoteDetail::Remote {
-                let mut connected_peer = peer.connect(&mut term).unwrap();
-                let remote_tip = connected_peer.query_tip();
+            if let Some(remote_tip) = remote_tips.remove(&peer.name) {
+                let remote_tip = match remote_tip {
+                    Ok(remote_tip) => remote_tip,
+                    Err(err) => {
+                        term.error(&format!(" * cannot query the remote tip: {}\n", err)).unwrap();
+                        continue;
+                    }
+                };
                 let block_diff = remote_tip.date - tip.date;
 
                 term.simply(" * remote tip hash: ").unwrap();
@@ -226,22 +314,22 @@ pub fn log( mut term: Term
           , from: Option<String>
           )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load_read_only(&mut term, root_dir, name);
 
     let from = if let Some(hash_hex) = from {
         let hash = super::config::parse_block_hash(&mut term, &hash_hex);
 
-        if storage::block_location(&blockchain.storage, &hash).is_none() {
+        if storage::block_location(blockchain.storage(), &hash).is_none() {
             term.error(&format!("block hash `{}' is not present in the local blockchain\n", hash_hex)).unwrap();
             ::std::process::exit(1);
         }
 
         hash
     } else {
-        blockchain.load_tip().0.hash
+        blockchain.load_tip().unwrap_or_else(|e| fail_with(&mut term, e)).0.hash
     };
 
-    for block in storage::block::iter::ReverseIter::from(&blockchain.storage, from).unwrap() {
+    for block in storage::block::iter::ReverseIter::from(blockchain.storage(), from).unwrap() {
         use utils::pretty::Pretty;
 
         block.pretty(&mut term, 0).unwrap();
@@ -254,30 +342,28 @@ pub fn forward( mut term: Term
               , to: Option<String>
               )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load(&mut term, root_dir, name);
+    forward_tip(&mut term, &blockchain, to)
+}
 
+/// forward the local tip of the blockchain, already loaded (and locked),
+/// to the given block or to the best of the remotes' tips (see
+/// `Blockchain::best_tip`)
+fn forward_tip(term: &mut Term, blockchain: &Blockchain, to: Option<String>) {
     let hash = if let Some(hash_hex) = to {
-        let hash = super::config::parse_block_hash(&mut term, &hash_hex);
+        let hash = super::config::parse_block_hash(term, &hash_hex);
 
-        if storage::block_location(&blockchain.storage, &hash).is_none() {
+        if storage::block_location(blockchain.storage(), &hash).is_none() {
             term.error(&format!("block hash `{}' is not present in the local blockchain\n", hash_hex)).unwrap();
             ::std::process::exit(1);
         }
 
         hash
     } else {
-        let initial_tip = blockchain.load_tip().0;
-
-        let tip = blockchain.peers().map(|np| {
-            peer::Peer::prepare(&blockchain, np.name().to_owned()).load_local_tip().0
-        }).fold(initial_tip, |current_tip, tip| {
-            if tip.date > current_tip.date {
-                tip
-            } else {
-                current_tip
-            }
-        });
-
+        let (tip, errors) = blockchain.best_tip().unwrap_or_else(|e| fail_with(term, e));
+        for (name, err) in errors {
+            term.warn(&format!("ignoring remote `{}': {}\n", name, err)).unwrap();
+        }
         tip.hash
     };
 
@@ -286,29 +372,402 @@ pub fn forward( mut term: Term
     blockchain.save_tip(&hash)
 }
 
+/// sync from the best peer (a native peer before the HTTP bridges, the
+/// highest tip, then the lowest latency), falling back to the next one
+/// if it fails, then forward the local tip. `pinned` restricts the sync
+/// to the given peer. Once `cancel` is cancelled, the sync stops at the
+/// last consistent block.
 pub fn pull( mut term: Term
            , root_dir: PathBuf
            , name: String
+           , pinned: Option<String>
+           , cancel: CancelToken
            )
 {
-    let blockchain = Blockchain::load(root_dir.clone(), name.clone());
+    let blockchain = load(&mut term, root_dir.clone(), name.clone());
 
-    for np in blockchain.peers() {
-        if ! np.is_native() { continue; }
-        term.info(&format!("fetching blocks from peer: {}\n", np.name())).unwrap();
+    let names : Vec<String> = match pinned {
+        Some(alias) => {
+            if ! blockchain.peers().any(|np| np.name() == alias) {
+                term.error(&format!("no remote `{}' in the blockchain `{}'\n", alias, name)).unwrap();
+                ::std::process::exit(1);
+            }
+            vec![alias]
+        },
+        None => blockchain.sync_peers(),
+    };
+
+    let sync_config = peer::SyncConfig { cancel, ..peer::SyncConfig::default() };
+    let mut synced = false;
+    for probe in peer::probe_peers(&blockchain, &mut term, &names, peer::DEFAULT_TIP_PROBE_TIMEOUT) {
+        term.info(&format!("fetching blocks from peer: {} ({}, tip {}, probed in {})\n",
+            probe.name, probe.protocol, probe.tip.date, format_duration(probe.latency))).unwrap();
+
+        let peer = peer::Peer::prepare(&blockchain, probe.name.clone())
+            .unwrap_or_else(|e| fail_with(&mut term, e));
+        let connected_peer = match peer.connect(&mut term) {
+            Err(()) => continue,
+            Ok(connected_peer) => connected_peer,
+        };
+        match connected_peer.sync_with(&mut term, &sync_config) {
+            Ok(_) => { synced = true; break; },
+            Err(super::Error::Cancelled) => fail_with(&mut term, super::Error::Cancelled),
+            Err(err) => {
+                term.warn(&format!("sync with {} failed, trying the next peer: {}\n", probe.name, err)).unwrap();
+            }
+        }
+    }
+
+    if ! synced {
+        term.error("no peer could be synced with\n").unwrap();
+        ::std::process::exit(1);
+    }
+
+    // the blockchain is still locked, it is not loaded again
+    forward_tip(&mut term, &blockchain, None)
+}
+
+/// fetch the missing loose blocks of the local blockchain from the
+/// first peer able to serve them, the native peers first
+pub fn repair( mut term: Term
+             , root_dir: PathBuf
+             , name: String
+             )
+{
+    let blockchain = load(&mut term, root_dir, name);
+
+    let gaps = blockchain.find_gaps().unwrap_or_else(|e| fail_with(&mut term, e));
+    if gaps.is_empty() {
+        term.success("no missing blocks\n").unwrap();
+        return;
+    }
+    for (start, end) in gaps.iter() {
+        term.warn(&format!("blocks missing between {} and {}\n", start, end)).unwrap();
+    }
+
+    for alias in blockchain.sync_peers() {
+        let peer = peer::Peer::prepare(&blockchain, alias.clone())
+            .unwrap_or_else(|e| fail_with(&mut term, e));
+        let mut connected_peer = match peer.connect(&mut term) {
+            Err(()) => continue,
+            Ok(connected_peer) => connected_peer,
+        };
+        match blockchain.repair_gaps(&mut connected_peer) {
+            Err(err) => {
+                term.warn(&format!("cannot fetch the missing blocks from {}: {}\n", alias, err)).unwrap();
+            },
+            Ok(nr_blocks) => {
+                term.success(&format!("{} blocks fetched from {}\n", nr_blocks, alias)).unwrap();
+                return;
+            }
+        }
+    }
+
+    term.error("no peer could provide the missing blocks\n").unwrap();
+    ::std::process::exit(1);
+}
+
+/// move the local tip back to the given block, or to the block the
+/// first peer (the native peers first) forked from
+pub fn rollback( mut term: Term
+               , root_dir: PathBuf
+               , name: String
+               , hash_str: Option<&str>
+               )
+{
+    let blockchain = load(&mut term, root_dir, name);
+
+    let hash = match hash_str {
+        Some(hash_str) => super::config::parse_block_hash(&mut term, hash_str),
+        None => {
+            let mut fork = None;
+            for alias in blockchain.sync_peers() {
+                let peer = peer::Peer::prepare(&blockchain, alias.clone())
+                    .unwrap_or_else(|e| fail_with(&mut term, e));
+                let mut connected_peer = match peer.connect(&mut term) {
+                    Err(()) => continue,
+                    Ok(connected_peer) => connected_peer,
+                };
+                match connected_peer.find_fork() {
+                    Err(err) => {
+                        term.warn(&format!("cannot look for a fork with {}: {}\n", alias, err)).unwrap();
+                    },
+                    Ok(None) => {},
+                    Ok(Some(block)) => {
+                        term.info(&format!("{} forked after {} ({})\n", alias, block.hash, block.date)).unwrap();
+                        fork = Some(block.hash);
+                        break;
+                    }
+                }
+            }
+            match fork {
+                None => {
+                    term.success("no fork found, the local tip is left untouched\n").unwrap();
+                    return;
+                },
+                Some(hash) => hash,
+            }
+        }
+    };
+
+    let tip = blockchain.rollback_to(&hash).unwrap_or_else(|e| fail_with(&mut term, e));
+    term.success(&format!("local tip rolled back to {} ({})\n", tip.hash, tip.date)).unwrap();
+}
+
+/// write the blocks and the tags of the blockchain in a tar archive
+pub fn export( mut term: Term
+             , root_dir: PathBuf
+             , name: String
+             , output: PathBuf
+             )
+{
+    let blockchain = load_read_only(&mut term, root_dir, name);
+
+    let file = ::std::fs::File::create(&output).unwrap_or_else(|e| fail_with(&mut term, e.into()));
+    let nr_files = blockchain.export(::std::io::BufWriter::new(file)).unwrap_or_else(|e| fail_with(&mut term, e));
+    term.success(&format!("{} files exported to {:?}\n", nr_files, output)).unwrap();
+}
+
+/// import the blocks and the tags of a tar archive (see `export`)
+pub fn import( mut term: Term
+             , root_dir: PathBuf
+             , name: String
+             , input: PathBuf
+             )
+{
+    let blockchain = load(&mut term, root_dir, name);
+
+    let file = ::std::fs::File::open(&input).unwrap_or_else(|e| fail_with(&mut term, e.into()));
+    let nr_files = blockchain.import(::std::io::BufReader::new(file)).unwrap_or_else(|e| fail_with(&mut term, e));
+    term.success(&format!("{} files imported from {:?}\n", nr_files, input)).unwrap();
+}
+
+/// write the decoded blocks between the given blocks (by default from the
+/// genesis to the local tip) in the given file, or in the standard output
+pub fn export_blocks( mut term: Term
+                    , root_dir: PathBuf
+                    , name: String
+                    , from: Option<&str>
+                    , to: Option<&str>
+                    , format: super::export_blocks::Format
+                    , output: Option<PathBuf>
+                    )
+{
+    let blockchain = load_read_only(&mut term, root_dir, name);
+
+    let from = match from {
+        None => blockchain.config.genesis.clone(),
+        Some(hash_str) => super::config::parse_block_hash(&mut term, hash_str),
+    };
+    let to = match to {
+        None => blockchain.load_tip().unwrap_or_else(|e| fail_with(&mut term, e)).0.hash,
+        Some(hash_str) => super::config::parse_block_hash(&mut term, hash_str),
+    };
+
+    match output {
+        None => {
+            let stdout = ::std::io::stdout();
+            blockchain.export_blocks(from, to, format, stdout.lock()).unwrap_or_else(|e| fail_with(&mut term, e));
+        },
+        Some(output) => {
+            let file = ::std::fs::File::create(&output).unwrap_or_else(|e| fail_with(&mut term, e.into()));
+            let nr_blocks = blockchain.export_blocks(from, to, format, ::std::io::BufWriter::new(file))
+                .unwrap_or_else(|e| fail_with(&mut term, e));
+            term.success(&format!("{} blocks exported to {:?}\n", nr_blocks, output)).unwrap();
+        },
+    }
+}
+
+#[derive(Serialize)]
+struct RemoteStatus {
+    name: String,
+    #[serde(flatten)]
+    stats: super::peer_stats::PeerStats,
+}
+
+/// print the metrics of the remotes, as recorded by the last syncs
+pub fn peer_status( mut term: Term
+                  , root_dir: PathBuf
+                  , name: String
+                  )
+{
+    let blockchain = load_read_only(&mut term, root_dir, name);
+
+    let statuses : Vec<RemoteStatus> = blockchain.peers().map(|np| {
+        let stats = blockchain.load_peer_stats(np.name()).unwrap_or_else(|e| fail_with(&mut term, e));
+        RemoteStatus { name: np.name().to_owned(), stats }
+    }).collect();
+
+    if term.is_json() {
+        term.json(&statuses).unwrap();
+        return;
+    }
+
+    let now = ::std::time::SystemTime::now();
+    for status in statuses {
+        let stats = &status.stats;
+        term.info(&status.name).unwrap();
+        if stats.failures > 0 {
+            term.error(if stats.last_sync.is_none() { " (dead)" } else { " (failing)" }).unwrap();
+        }
+        term.simply("\n").unwrap();
+
+        term.simply("   * last sync:  ").unwrap();
+        match stats.last_sync {
+            None => term.warn("never").unwrap(),
+            Some(last_sync) => {
+                let since = ::std::time::Duration::new(now.duration_since(last_sync).map(|d| d.as_secs()).unwrap_or(0), 0);
+                term.info(&format!("{} ({} ago)", format_systemtime(last_sync), format_duration(since))).unwrap()
+            },
+        }
+        term.simply("\n").unwrap();
+        if let Some(latency) = stats.latency {
+            term.simply("   * latency:    ").unwrap();
+            term.info(&format!("{}ms", latency.as_secs() * 1000 + latency.subsec_millis() as u64)).unwrap();
+            term.simply("\n").unwrap();
+        }
+        if let (Some(date), Some(height)) = (&stats.tip_date, stats.tip_height) {
+            term.simply("   * tip:        ").unwrap();
+            term.success(&format!("{} (slot {})", date, height)).unwrap();
+            term.simply("\n").unwrap();
+        }
+        term.simply("   * failures:   ").unwrap();
+        term.simply(&format!("{} since the last sync, {} in total", stats.failures, stats.total_failures)).unwrap();
+        term.simply("\n").unwrap();
+    }
+}
+
+/// build the address and transaction indexes of the blockchain (see
+/// `index`), up to the local tip
+pub fn index_build( mut term: Term
+                  , root_dir: PathBuf
+                  , name: String
+                  )
+{
+    let blockchain = load(&mut term, root_dir, name);
+
+    term.info("indexing the transactions of the blockchain, this may take some time\n").unwrap();
+    let report = blockchain.build_tx_index().unwrap_or_else(|e| fail_with(&mut term, e));
+    term.success(&format!("{} transactions and {} addresses indexed, up to block {}\n",
+        report.transactions, report.addresses, report.tip)).unwrap();
+}
+
+/// exit if the indexes are not built, warn if they are behind the local tip
+fn ensure_indexed(term: &mut Term, blockchain: &Blockchain) {
+    match blockchain.indexed_tip().unwrap_or_else(|e| fail_with(term, e)) {
+        None => {
+            term.error(&format!("Blockchain `{}' is not indexed, run `blockchain index build' first\n", blockchain.name)).unwrap();
+            ::std::process::exit(1);
+        },
+        Some(tip) => {
+            if tip != blockchain.load_tip().unwrap_or_else(|e| fail_with(term, e)).0.hash {
+                term.warn("the index is behind the local tip, run `blockchain index build' to update it\n").unwrap();
+            }
+        }
+    }
+}
 
-        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
+#[derive(Serialize)]
+struct TxLocationReport {
+    txid: String,
+    block: String,
+    date: String,
+    position: u32,
+}
+
+/// print the block containing the given transaction, from the indexes
+pub fn query_tx( mut term: Term
+               , root_dir: PathBuf
+               , name: String
+               , txid: cardano::tx::TxId
+               )
+{
+    let blockchain = load_read_only(&mut term, root_dir, name);
+    ensure_indexed(&mut term, &blockchain);
+
+    let location = match blockchain.query_tx(&txid).unwrap_or_else(|e| fail_with(&mut term, e)) {
+        None => {
+            term.error(&format!("transaction `{}' not found in the index\n", txid)).unwrap();
+            ::std::process::exit(1);
+        },
+        Some(location) => location,
+    };
 
-        peer.connect(&mut term).unwrap().sync(&mut term);
+    if term.is_json() {
+        term.json(&TxLocationReport {
+            txid: format!("{}", txid),
+            block: format!("{}", location.block),
+            date: location.date,
+            position: location.position,
+        }).unwrap();
+        return;
     }
 
-    forward(term, root_dir, name, None)
+    writeln!(term, "block: {}", location.block).unwrap();
+    writeln!(term, "date: {}", location.date).unwrap();
+    writeln!(term, "position: {}", location.position).unwrap();
+}
+
+#[derive(Serialize)]
+struct AddressTxReport {
+    txid: String,
+    block: String,
+    /// `input` for a transaction spending from the address, `output` for
+    /// a transaction paying to it
+    direction: &'static str,
+}
+
+/// print the transactions of the given address, from the indexes
+pub fn query_address( mut term: Term
+                    , root_dir: PathBuf
+                    , name: String
+                    , address: cardano::address::ExtendedAddr
+                    )
+{
+    let blockchain = load_read_only(&mut term, root_dir, name);
+    ensure_indexed(&mut term, &blockchain);
+
+    let refs = blockchain.query_address(&address).unwrap_or_else(|e| fail_with(&mut term, e));
+    let reports : Vec<AddressTxReport> = refs.into_iter().map(|txref| AddressTxReport {
+        txid: format!("{}", txref.txid),
+        block: format!("{}", txref.block),
+        direction: match txref.direction {
+            super::history::Direction::Input  => "input",
+            super::history::Direction::Output => "output",
+        },
+    }).collect();
+
+    if term.is_json() {
+        term.json(&reports).unwrap();
+        return;
+    }
+
+    if reports.is_empty() {
+        term.info("no transaction for this address\n").unwrap();
+    }
+    for report in reports {
+        writeln!(term, "{} {} ({})", report.txid, report.direction, report.block).unwrap();
+    }
+}
+
+/// remove the loose copies of the packed blocks
+pub fn prune( mut term: Term
+            , root_dir: PathBuf
+            , name: String
+            , keep_epochs: u64
+            )
+{
+    let blockchain = load(&mut term, root_dir, name);
+
+    let report = blockchain.prune(keep_epochs).unwrap_or_else(|e| fail_with(&mut term, e));
+    term.success(&format!("{} loose blocks removed from {} packed epochs, {} bytes reclaimed\n",
+        report.blocks, report.epochs, report.reclaimed)).unwrap();
 }
 
 fn get_block(mut term: &mut Term, blockchain: &Blockchain, hash_str: &str) -> RawBlock
 {
     let hash = super::config::parse_block_hash(&mut term, &hash_str);
-    let block_location = match storage::block_location(&blockchain.storage, &hash) {
+    let block_location = match storage::block_location(blockchain.storage(), &hash) {
         None => {
             term.error(&format!("block hash `{}' is not present in the local blockchain\n", hash_str)).unwrap();
             ::std::process::exit(1);
@@ -318,7 +777,7 @@ fn get_block(mut term: &mut Term, blockchain: &Blockchain, hash_str: &str) -> Ra
 
     debug!("blk location: {:?}", block_location);
 
-    match storage::block_read_location(&blockchain.storage, &block_location, &hash) {
+    match storage::block_read_location(blockchain.storage(), &block_location, &hash) {
         None        => {
             // this is a bug, we have a block location available for this hash
             // but we were not able to read the block.
@@ -336,7 +795,7 @@ pub fn cat( mut term: Term
           , debug: bool
           )
 {
-    let blockchain = Blockchain::load(root_dir.clone(), name.clone());
+    let blockchain = load_read_only(&mut term, root_dir.clone(), name.clone());
     let rblk = get_block(&mut term, &blockchain, hash_str);
 
     if no_parse {
@@ -359,11 +818,17 @@ pub fn status( mut term: Term
          , name: String
          )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load_read_only(&mut term, root_dir, name);
+
+    if term.is_json() {
+        let report = status_report(&blockchain).unwrap_or_else(|e| fail_with(&mut term, e));
+        term.json(&report).unwrap();
+        return;
+    }
 
     term.warn("Blockchain:\n").unwrap();
     {
-        let (tip, _is_genesis) = blockchain.load_tip();
+        let (tip, _is_genesis) = blockchain.load_tip().unwrap_or_else(|e| fail_with(&mut term, e));
         let tag_path = blockchain.dir.join("tag").join(super::LOCAL_BLOCKCHAIN_TIP_TAG);
         let metadata = ::std::fs::metadata(tag_path).unwrap();
         let now = ::std::time::SystemTime::now();
@@ -384,14 +849,22 @@ pub fn status( mut term: Term
 
     term.warn("Peers:\n").unwrap();
     for (idx, np) in blockchain.peers().enumerate() {
-        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
-        let (tip, _is_genesis) = peer.load_local_tip();
+        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned())
+            .unwrap_or_else(|e| fail_with(&mut term, e));
 
         term.info(&format!(" {}. {}", idx + 1, peer.name)).unwrap();
         term.simply(" (").unwrap();
         term.success(&format!("{}", peer.config)).unwrap();
         term.simply(")\n").unwrap();
 
+        let (tip, _is_genesis) = match peer.load_local_tip() {
+            Ok(tip) => tip,
+            Err(err) => {
+                term.error(&format!("     * cannot load the local tip: {}\n", err)).unwrap();
+                continue;
+            }
+        };
+
         let tag_path = blockchain.dir.join("tag").join(&peer.tag);
         let metadata = ::std::fs::metadata(tag_path).unwrap();
         let now = ::std::time::SystemTime::now();
@@ -411,13 +884,60 @@ pub fn status( mut term: Term
     }
 }
 
+/// the tip of the local blockchain or of one of its peers, for `status`
+#[derive(Serialize)]
+struct TipStatus {
+    hash: String,
+    date: String,
+    /// the last time the tip has been updated
+    updated: String,
+}
+
+#[derive(Serialize)]
+struct PeerStatus {
+    name: String,
+    config: String,
+    /// `None` if the local tip of the peer cannot be loaded
+    tip: Option<TipStatus>,
+}
+
+/// the output of `status` in JSON
+#[derive(Serialize)]
+struct StatusReport {
+    tip: TipStatus,
+    peers: Vec<PeerStatus>,
+}
+
+fn tip_status(blockchain: &Blockchain, tag: &str, tip: &::exe_common::network::api::BlockRef) -> TipStatus {
+    let metadata = ::std::fs::metadata(blockchain.dir.join("tag").join(tag)).unwrap();
+    TipStatus {
+        hash: format!("{}", tip.hash),
+        date: format!("{}", tip.date),
+        updated: format_systemtime(metadata.modified().unwrap()),
+    }
+}
+
+fn status_report(blockchain: &Blockchain) -> super::Result<StatusReport> {
+    let (tip, _is_genesis) = blockchain.load_tip()?;
+    let peers = blockchain.peers().map(|np| {
+        let peer = peer::Peer::prepare(&blockchain, np.name().to_owned())?;
+        let tip = peer.load_local_tip().ok().map(|(tip, _is_genesis)| tip_status(blockchain, &peer.tag, &tip));
+        Ok(PeerStatus { name: peer.name.clone(), config: format!("{}", peer.config), tip })
+    }).collect::<super::Result<_>>()?;
+
+    Ok(StatusReport {
+        tip: tip_status(blockchain, super::LOCAL_BLOCKCHAIN_TIP_TAG, &tip),
+        peers,
+    })
+}
+
 pub fn verify_block( mut term: Term
                    , root_dir: PathBuf
                    , name: String
                    , hash_str: &str
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load_read_only(&mut term, root_dir, name);
     let hash = super::config::parse_block_hash(&mut term, &hash_str);
     let rblk = get_block(&mut term, &blockchain, hash_str);
     match rblk.decode() {
@@ -449,9 +969,9 @@ pub fn verify_chain( mut term: Term
                    , name: String
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = load_read_only(&mut term, root_dir, name);
 
-    let tip = blockchain.load_tip().0;
+    let tip = blockchain.load_tip().unwrap_or_else(|e| fail_with(&mut term, e)).0;
     let num_blocks = tip.date.slot_number();
 
     let progress = term.progress_bar(num_blocks as u64);
@@ -468,19 +988,48 @@ pub fn verify_chain( mut term: Term
     let mut bad_blocks = 0;
     let mut nr_blocks = 0;
     let mut chain_state = cardano::block::ChainState::new(&genesis_data);
-
-    for res in blockchain.iter_to_tip(blockchain.config.genesis.clone()).unwrap() {
-        let (_raw_blk, blk) = res.unwrap();
+    let mut previous_date = None;
+    let mut previous_hash = None;
+
+    let blocks = blockchain.iter_to_tip(blockchain.config.genesis.clone()).unwrap_or_else(|e| {
+        progress.finish();
+        fail_with(&mut term, e)
+    });
+    for res in blocks {
+        let (_raw_blk, blk) = match res {
+            Ok(block) => block,
+            Err(err) => {
+                progress.finish();
+                match previous_hash {
+                    None => term.error(&format!("Cannot read the block {}\n", blockchain.config.genesis)).unwrap(),
+                    Some(ref hash) => term.error(&format!("Cannot read the block following {}\n", hash)).unwrap(),
+                }
+                fail_with(&mut term, err.into())
+            }
+        };
         nr_blocks += 1;
         let hash = blk.get_header().compute_hash();
+        let date = blk.get_header().get_blockdate();
+        let mut invalid = false;
         match chain_state.verify_block(&hash, &blk) {
             Ok(()) => {},
             Err(err) => {
-                bad_blocks += 1;
-                term.error(&format!("Block {} ({}) is invalid: {:?}", hash, blk.get_header().get_blockdate(), err)).unwrap();
+                invalid = true;
+                term.error(&format!("Block {} ({}) is invalid: {:?}", hash, date, err)).unwrap();
+                term.simply("\n\n").unwrap();
+            }
+        }
+        // the same checks of the dates as `verify --links-only`
+        if let Some(previous) = previous_date.take() {
+            if ! super::verify::date_follows(&previous, &date) {
+                invalid = true;
+                term.error(&format!("Block {} ({}) is invalid: its date does not follow the date of its parent ({})", hash, date, previous)).unwrap();
                 term.simply("\n\n").unwrap();
             }
         }
+        if invalid { bad_blocks += 1; }
+        previous_date = Some(date);
+        previous_hash = Some(hash);
         progress.inc(1);
     }
 
@@ -500,3 +1049,49 @@ pub fn verify_chain( mut term: Term
     term.success(&format!("All {} blocks are valid", nr_blocks)).unwrap();
     term.simply("\n").unwrap();
 }
+
+pub fn verify_links( mut term: Term
+                   , root_dir: PathBuf
+                   , name: String
+                   , quarantine: bool
+                   , cancel: CancelToken
+                   )
+{
+    // the quarantine moves the local tip back
+    let blockchain = if quarantine {
+        load(&mut term, root_dir, name)
+    } else {
+        load_read_only(&mut term, root_dir, name)
+    };
+
+    #[cfg(feature = "parallel")]
+    let report = blockchain.verify_links_parallel(&cancel);
+    #[cfg(not(feature = "parallel"))]
+    let report = blockchain.verify_links(&cancel);
+
+    let report = report.unwrap_or_else(|e| fail_with(&mut term, e));
+
+    for invalid in report.invalid_blocks.iter() {
+        match invalid.date {
+            None => term.error(&format!("Block {} is invalid: {:?}", invalid.hash, invalid.reason)).unwrap(),
+            Some(ref date) => term.error(&format!("Block {} ({}) is invalid: {:?}", invalid.hash, date, invalid.reason)).unwrap(),
+        }
+        term.simply("\n\n").unwrap();
+    }
+
+    if let Some(first) = report.first_invalid() {
+        term.error(&format!("{} out of {} blocks are invalid", report.invalid_blocks.len(), report.nr_blocks)).unwrap();
+        term.simply("\n").unwrap();
+        term.error(&format!("the first invalid block is {}", first.hash)).unwrap();
+        term.simply("\n").unwrap();
+        if quarantine {
+            let quarantined = blockchain.quarantine(&report).unwrap_or_else(|e| fail_with(&mut term, e));
+            term.warn(&format!("{} blocks moved to {:?}", quarantined.len(), blockchain.dir.join(super::verify::QUARANTINE_DIRECTORY))).unwrap();
+            term.simply("\n").unwrap();
+        }
+        ::std::process::exit(1);
+    }
+
+    term.success(&format!("All {} blocks are valid", report.nr_blocks)).unwrap();
+    term.simply("\n").unwrap();
+}
@@ -0,0 +1,182 @@
+use std::{fmt, error};
+use std::path::PathBuf;
+
+use cardano_storage as storage;
+use cardano::block::HeaderHash;
+
+use super::{iter, network};
+
+/// blockchain errors
+#[derive(Debug)]
+pub enum Error {
+    /// the same peer alias appears more than once in the blockchain's
+    /// configuration. The remote tags are keyed by the alias so the
+    /// peers would clobber each other.
+    DuplicatePeer(String),
+
+    /// there is no peer of the given alias in the blockchain's
+    /// configuration
+    UnknownPeer(String),
+
+    /// the configuration file of the blockchain is missing
+    ConfigNotFound(PathBuf),
+
+    /// the configuration file of the blockchain cannot be parsed (a
+    /// truncated file for example)
+    InvalidConfig(PathBuf, ::serde_yaml::Error),
+
+    /// the blockchain has been created by a later version, its layout is
+    /// not supported (see `STORAGE_VERSION`)
+    IncompatibleStorageVersion(String),
+
+    /// the tag does not point to a block of the storage
+    CorruptTag(String, Box<Error>),
+
+    /// the blockchain directory is locked by another process (see `lock`)
+    Locked(PathBuf),
+
+    /// the block is not present in the storage
+    BlockNotFound(HeaderHash),
+
+    /// the block is not the local tip nor one of its ancestors
+    NotAnAncestor(HeaderHash),
+
+    /// the block is in a packed (stable) epoch, the local tip cannot be
+    /// rolled back to it
+    StableBlock(HeaderHash),
+
+    /// the archive is of another network (see `Blockchain::import`)
+    IncompatibleArchive,
+
+    /// some of the blocks of the archive are invalid
+    InvalidArchive { invalid_blocks: usize },
+
+    /// there is no genesis data known for the `genesis_prev` of the
+    /// configuration (this is the case of the custom networks)
+    GenesisDataNotFound(HeaderHash),
+
+    /// the genesis data is missing some parameters or cannot be parsed
+    InvalidGenesisData(::serde_json::Error),
+
+    /// the peer advertised a tip it does not deliver, the block it
+    /// sends for the tip has a different hash
+    InvalidRemoteTip { advertised: HeaderHash, found: HeaderHash },
+
+    NetworkError(network::Error),
+
+    /// the remote has not answered within the given time
+    Timeout(::std::time::Duration),
+
+    /// the operation has been cancelled (see `utils::cancel::CancelToken`)
+    Cancelled,
+
+    StorageError(storage::Error),
+
+    IoError(::std::io::Error),
+
+    CborError(::cbor_event::Error),
+
+    IteratorError(iter::Error),
+}
+impl From<network::Error> for Error {
+    fn from(e: network::Error) -> Self { Error::NetworkError(e) }
+}
+impl From<storage::Error> for Error {
+    fn from(e: storage::Error) -> Self { Error::StorageError(e) }
+}
+impl From<::std::io::Error> for Error {
+    fn from(e: ::std::io::Error) -> Self { Error::IoError(e) }
+}
+impl From<::cbor_event::Error> for Error {
+    fn from(e: ::cbor_event::Error) -> Self { Error::CborError(e) }
+}
+impl From<iter::Error> for Error {
+    fn from(e: iter::Error) -> Self { Error::IteratorError(e) }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// what to do about the error, for the errors loading the blockchain
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Error::ConfigNotFound(_) => Some("check the name of the blockchain with `cardano-cli blockchain list', or create it with `cardano-cli blockchain new'"),
+            Error::InvalidConfig(..) => Some("the configuration file has been truncated or edited, restore it or create the blockchain again"),
+            Error::IncompatibleStorageVersion(_) => Some("the blockchain has been created by a later version of cardano-cli, upgrade it"),
+            Error::CorruptTag(tag, _) if tag == super::LOCAL_BLOCKCHAIN_TIP_TAG => Some("set the local tip again with `cardano-cli blockchain forward'"),
+            Error::CorruptTag(..) => Some("fetch the blocks of the remote again with `cardano-cli blockchain pull'"),
+            Error::UnknownPeer(_) => Some("list the remotes of the blockchain with `cardano-cli blockchain remote-ls'"),
+            Error::Locked(_) => Some("retry once the other command is done, or without `--no-wait' to wait for it"),
+            _ => None,
+        }
+    }
+
+    /// the exit code of the command failing with the error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ConfigNotFound(_)             => 2,
+            Error::InvalidConfig(..)             => 3,
+            Error::CorruptTag(..)                => 3,
+            Error::IncompatibleStorageVersion(_) => 4,
+            Error::Locked(_)                     => 5,
+            _                                    => 1,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DuplicatePeer(alias) => write!(f, "Peer `{}' is declared more than once", alias),
+            Error::UnknownPeer(alias)   => write!(f, "No remote `{}' in the blockchain", alias),
+            Error::ConfigNotFound(path) => write!(f, "Blockchain configuration not found: {:?}", path),
+            Error::InvalidConfig(path, _) => write!(f, "Invalid blockchain configuration: {:?}", path),
+            Error::IncompatibleStorageVersion(version) => write!(f, "Unsupported blockchain storage version `{}' (version {} at most)", version, super::STORAGE_VERSION),
+            Error::CorruptTag(tag, _)   => write!(f, "Tag `{}' does not point to a valid block", tag),
+            Error::Locked(path)         => write!(f, "Blockchain {:?} is in use by another process", path),
+            Error::BlockNotFound(hash)  => write!(f, "Block {} not found in the local storage", hash),
+            Error::NotAnAncestor(hash) => write!(f, "Block {} is not an ancestor of the local tip", hash),
+            Error::StableBlock(hash)   => write!(f, "Block {} is in a packed epoch, it cannot be rolled back to", hash),
+            Error::IncompatibleArchive => write!(f, "The archive is of another network"),
+            Error::InvalidArchive { invalid_blocks } => write!(f, "The archive contains {} invalid blocks", invalid_blocks),
+            Error::GenesisDataNotFound(hash) => write!(f, "No genesis data known for the genesis {}", hash),
+            Error::InvalidGenesisData(_) => write!(f, "Invalid genesis data"),
+            Error::InvalidRemoteTip { advertised, found } => write!(f, "Remote advertised the tip {} but sent the block {}", advertised, found),
+            Error::NetworkError(_)      => write!(f, "Network error"),
+            Error::Timeout(timeout)     => write!(f, "No answer within {} seconds", timeout.as_secs()),
+            Error::Cancelled            => write!(f, "Operation cancelled"),
+            Error::StorageError(_)      => write!(f, "Storage error"),
+            Error::IoError(_)           => write!(f, "I/O Error"),
+            Error::CborError(_)         => write!(f, "Encoding error (CBOR)"),
+            Error::IteratorError(_)     => write!(f, "Error while iterating through the blocks"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<& error::Error> {
+        match self {
+            Error::DuplicatePeer(_)       => None,
+            Error::UnknownPeer(_)         => None,
+            Error::ConfigNotFound(_)      => None,
+            Error::InvalidConfig(_, ref err) => Some(err),
+            Error::IncompatibleStorageVersion(_) => None,
+            Error::CorruptTag(_, ref err) => Some(err.as_ref()),
+            Error::Locked(_)              => None,
+            Error::BlockNotFound(_)       => None,
+            Error::NotAnAncestor(_)       => None,
+            Error::StableBlock(_)         => None,
+            Error::IncompatibleArchive    => None,
+            Error::InvalidArchive { .. }  => None,
+            Error::GenesisDataNotFound(_) => None,
+            Error::InvalidGenesisData(ref err) => Some(err),
+            Error::InvalidRemoteTip { .. } => None,
+            Error::NetworkError(ref err)  => Some(err),
+            Error::Timeout(_)             => None,
+            Error::Cancelled              => None,
+            Error::StorageError(ref err)  => Some(err),
+            Error::IoError(ref err)       => Some(err),
+            Error::CborError(ref err)     => Some(err),
+            Error::IteratorError(ref err) => Some(err),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! export the decoded blocks of a range, for analysis with other tools
+//!
+//! The blocks are written one after the other, as they are read:
+//!
+//! * `json`: one JSON object per line per block (JSON Lines), with the
+//!   header and the inputs, outputs and fee of every transaction;
+//! * `cbor-hex`: one line per block, the hash then the block as stored;
+//! * `csv`: one line per transaction, after a header line.
+//!
+//! The fee of a transaction is only known if all of its inputs spend
+//! outputs exported before it, it is left empty otherwise (an export not
+//! starting from the genesis). The values of the unspent outputs are kept
+//! in memory along the export, the outputs are keyed by their `TxoPointer`
+//! to keep them small.
+//!
+
+use std::{fmt, io::Write, str::FromStr, collections::BTreeMap};
+use cardano::{block::{Block, BlockDate, HeaderHash, RawBlock}, tx::TxoPointer, util::hex};
+use cardano_storage as storage;
+use serde_json;
+
+use super::{Blockchain, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    CborHex,
+    Csv,
+}
+impl FromStr for Format {
+    type Err = &'static str;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "json"     => Ok(Format::Json),
+            "cbor-hex" => Ok(Format::CborHex),
+            "csv"      => Ok(Format::Csv),
+            _          => Err("invalid export format, expected `json', `cbor-hex' or `csv'"),
+        }
+    }
+}
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Json    => write!(f, "json"),
+            Format::CborHex => write!(f, "cbor-hex"),
+            Format::Csv     => write!(f, "csv"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedInput {
+    txid: String,
+    index: u32,
+}
+
+#[derive(Serialize)]
+struct ExportedOutput {
+    address: String,
+    /// in lovelace
+    value: u64,
+}
+
+#[derive(Serialize)]
+struct ExportedTransaction {
+    txid: String,
+    inputs: Vec<ExportedInput>,
+    outputs: Vec<ExportedOutput>,
+    /// in lovelace, `None` if some inputs are not known
+    fee: Option<u64>,
+}
+impl ExportedTransaction {
+    fn output_total(&self) -> u64 {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedBlock {
+    hash: String,
+    parent: String,
+    epoch: u64,
+    /// `None` for the epoch boundary blocks
+    slot: Option<u16>,
+    transactions: Vec<ExportedTransaction>,
+}
+
+/// the values of the outputs exported so far and not spent yet, to
+/// compute the fees
+struct Outputs(BTreeMap<TxoPointer, u64>);
+impl Outputs {
+    fn export_block(&mut self, block: &Block) -> ExportedBlock {
+        let header = block.get_header();
+        let (epoch, slot) = match header.get_blockdate() {
+            BlockDate::Genesis(epoch) => (epoch, None),
+            BlockDate::Normal(date) => (date.epoch, Some(date.slotid)),
+        };
+
+        let mut transactions = Vec::new();
+        if let Some(txs) = block.get_transactions() {
+            for txaux in txs.iter() {
+                let tx = &txaux.tx;
+                let id = tx.id();
+                let txid = format!("{}", id);
+
+                let mut input_total = Some(0u64);
+                let inputs = tx.inputs.iter().map(|txin| {
+                    let value = self.0.remove(txin);
+                    input_total = input_total.and_then(|total| value.map(|value| total + value));
+                    ExportedInput { txid: format!("{}", txin.id), index: txin.index }
+                }).collect();
+                let outputs : Vec<ExportedOutput> = tx.outputs.iter().enumerate().map(|(index, txout)| {
+                    let value = u64::from(txout.value);
+                    self.0.insert(TxoPointer { id: id.clone(), index: index as u32 }, value);
+                    ExportedOutput { address: format!("{}", txout.address), value: value }
+                }).collect();
+
+                let mut exported = ExportedTransaction { txid: txid, inputs: inputs, outputs: outputs, fee: None };
+                let output_total = exported.output_total();
+                exported.fee = input_total.map(|input_total| input_total.saturating_sub(output_total));
+                transactions.push(exported);
+            }
+        }
+
+        ExportedBlock {
+            hash: format!("{}", header.compute_hash()),
+            parent: format!("{}", header.get_previous_header()),
+            epoch: epoch,
+            slot: slot,
+            transactions: transactions,
+        }
+    }
+}
+
+fn write_block<W: Write>(output: &mut W, format: Format, outputs: &mut Outputs, raw_block: &RawBlock, block: &Block) -> Result<()> {
+    match format {
+        Format::CborHex => {
+            writeln!(output, "{} {}", block.get_header().compute_hash(), hex::encode(raw_block.as_ref()))?;
+        },
+        Format::Json => {
+            let exported = outputs.export_block(block);
+            serde_json::to_writer(&mut *output, &exported).map_err(|err| {
+                ::std::io::Error::new(::std::io::ErrorKind::InvalidData, err)
+            })?;
+            writeln!(output, "")?;
+        },
+        Format::Csv => {
+            let exported = outputs.export_block(block);
+            let slot = exported.slot.map(|slot| format!("{}", slot)).unwrap_or_default();
+            for (position, tx) in exported.transactions.iter().enumerate() {
+                writeln!(output, "{},{},{},{},{},{},{},{},{}",
+                    exported.hash, exported.epoch, slot, position, tx.txid,
+                    tx.inputs.len(), tx.outputs.len(), tx.output_total(),
+                    tx.fee.map(|fee| format!("{}", fee)).unwrap_or_default()
+                )?;
+            }
+        },
+    }
+    Ok(())
+}
+
+impl Blockchain {
+    /// write the blocks from `from` to `to` (both included) in the given
+    /// format, the number of blocks written is returned
+    ///
+    /// fails with `Error::BlockNotFound` before writing anything if `from`
+    /// or `to` is not in the local storage.
+    pub fn export_blocks<W: Write>(&self, from: HeaderHash, to: HeaderHash, format: Format, mut output: W) -> Result<usize> {
+        for hash in [&from, &to].iter() {
+            if storage::block_location(self.storage(), hash).is_none() {
+                return Err(Error::BlockNotFound((*hash).clone()));
+            }
+        }
+
+        if format == Format::Csv {
+            writeln!(output, "block,epoch,slot,position,txid,inputs,outputs,output_total,fee")?;
+        }
+
+        let mut outputs = Outputs(BTreeMap::new());
+        let mut nr_blocks = 0;
+        for res in self.iter(from, to)? {
+            let (raw_block, block) = res?;
+            write_block(&mut output, format, &mut outputs, &raw_block, &block)?;
+            nr_blocks += 1;
+        }
+        output.flush()?;
+        Ok(nr_blocks)
+    }
+}
@@ -0,0 +1,100 @@
+//! find and fill the gaps in the loose blocks of the local blockchain
+//!
+//! The packed epochs are complete by construction, only the loose
+//! blocks (after the last packed epoch) can be missing. They are walked
+//! back from the local tip, following the parent of every block, up to
+//! the first missing one: the blocks before it cannot be reached.
+//!
+
+use cardano::block::{HeaderHash, Block};
+use cardano_storage::{self as storage, BlockLocation};
+use exe_common::network::api::BlockRef;
+
+use super::{Blockchain, Error, Result, iter, peer::ConnectedPeer};
+
+fn block_ref(block: &Block) -> BlockRef {
+    let header = block.get_header();
+    BlockRef {
+        hash: header.compute_hash(),
+        parent: header.get_previous_header(),
+        date: header.get_blockdate(),
+    }
+}
+
+impl Blockchain {
+    /// the last block of the last packed epoch, `None` if no epoch is packed
+    fn last_packed_block(&self) -> Result<Option<HeaderHash>> {
+        let mut epoch_id = self.config.epoch_start;
+        while storage::epoch::epoch_read_pack(&self.storage_config, epoch_id).is_ok() {
+            epoch_id += 1;
+        }
+        if epoch_id == self.config.epoch_start { return Ok(None); }
+
+        let mut last = None;
+        for raw_block in iter::epoch::Iter::new(&self.storage_config, epoch_id - 1)? {
+            last = Some(raw_block?);
+        }
+        match last {
+            None => Ok(None),
+            Some(raw_block) => Ok(Some(raw_block.decode()?.get_header().compute_hash())),
+        }
+    }
+
+    /// find the ranges of missing blocks between the genesis and the
+    /// local tip
+    ///
+    /// every range is given as the last block present before the gap (or
+    /// the genesis) and the first block present after it. The walk stops
+    /// at the first missing block, so there is at most one range: the
+    /// blocks between the last packed epoch and the gap are not checked
+    /// (they are downloaded again by `repair_gaps`).
+    pub fn find_gaps(&self) -> Result<Vec<(HeaderHash, HeaderHash)>> {
+        let (tip, is_genesis) = self.load_tip()?;
+        if is_genesis { return Ok(Vec::new()); }
+
+        let genesis = self.config.genesis.clone();
+        let mut child = None;
+        let mut cur_hash = tip.hash;
+        loop {
+            match storage::block_location(self.storage(), &cur_hash) {
+                None => {
+                    let start = self.last_packed_block()?.unwrap_or(genesis);
+                    return Ok(child.map(|child| vec![(start, child)]).unwrap_or_default());
+                },
+                // the packed epochs are complete
+                Some(BlockLocation::Loose) => {},
+                Some(_) => return Ok(Vec::new()),
+            }
+
+            if cur_hash == genesis { return Ok(Vec::new()); }
+            let block = match self.get_block(&cur_hash) {
+                None => return Err(Error::BlockNotFound(cur_hash)),
+                Some(raw_block) => raw_block.decode()?,
+            };
+            child = Some(cur_hash);
+            cur_hash = block.get_header().get_previous_header();
+        }
+    }
+
+    /// fetch the missing blocks (see `find_gaps`) from the given peer,
+    /// the number of downloaded blocks is returned.
+    pub fn repair_gaps(&self, peer: &mut ConnectedPeer) -> Result<usize> {
+        let mut nr_blocks = 0;
+        for (start, end) in self.find_gaps()? {
+            let end = match self.get_block(&end) {
+                None => return Err(Error::BlockNotFound(end)),
+                Some(raw_block) => block_ref(&raw_block.decode()?),
+            };
+            let (start, inclusive) = if start == self.config.genesis && ! self.block_exists(&start) {
+                (self.genesis_ref().0, true)
+            } else {
+                match self.get_block(&start) {
+                    None => return Err(Error::BlockNotFound(start)),
+                    Some(raw_block) => (block_ref(&raw_block.decode()?), false),
+                }
+            };
+            nr_blocks += peer.fetch_range(&start, inclusive, &end)?;
+        }
+        Ok(nr_blocks)
+    }
+}
@@ -0,0 +1,138 @@
+//! health of a local blockchain, to back liveness and readiness probes
+//!
+//! Every check is performed even if a previous one failed, a broken
+//! storage is reported as unhealthy rather than failing.
+//!
+//! The peers are probed concurrently, each from its own thread: a peer
+//! which does not answer (a dropped SYN, a stalled handshake) is given up
+//! on after the probe timeout, the check does not hang. The thread of
+//! such a peer is left to run until the system gives up the connection.
+//!
+
+use std::{thread, sync::mpsc};
+use std::time::{Duration, Instant, SystemTime};
+
+use super::{Blockchain, LOCAL_BLOCKCHAIN_TIP_TAG};
+use super::protocol::{self, Protocol, Target};
+
+/// the local tip is considered behind if older than this (30 slots on mainnet)
+pub const DEFAULT_MAX_TIP_AGE : Duration = Duration::from_secs(600);
+
+/// the time given to the peers to accept the connection and complete the
+/// handshake of their protocol
+pub const DEFAULT_PROBE_TIMEOUT : Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthStatus {
+    /// the storage can be opened
+    pub storage: bool,
+    /// the local tip can be read from the storage
+    pub tip: bool,
+    /// the age of the local tip, `None` if it cannot be computed
+    pub tip_age: Option<Duration>,
+    /// the local tip is younger than the given threshold
+    pub caught_up: bool,
+    /// at least one of the peers accepted the connection
+    pub peer_reachable: bool,
+}
+impl HealthStatus {
+    pub fn is_healthy(&self) -> bool {
+        self.storage && self.tip && self.caught_up && self.peer_reachable
+    }
+}
+
+impl Blockchain {
+    /// check the health of the blockchain, see `health_with`
+    pub fn health(&self) -> HealthStatus {
+        self.health_with(DEFAULT_MAX_TIP_AGE, DEFAULT_PROBE_TIMEOUT)
+    }
+
+    /// check the health of the blockchain: the storage can be opened, the
+    /// local tip is readable and is not older than `max_tip_age`, and
+    /// one of the peers is reachable within `probe_timeout`.
+    pub fn health_with(&self, max_tip_age: Duration, probe_timeout: Duration) -> HealthStatus {
+        let storage = self.open_storage().is_ok();
+        let tip = if storage { self.load_tip_from_tag(LOCAL_BLOCKCHAIN_TIP_TAG).ok() } else { None };
+
+        let tip_age = tip.as_ref().and_then(|(tip, _)| {
+            let tip_time = self.slot_start(&tip.date).ok()?;
+            SystemTime::now().duration_since(tip_time).ok()
+        });
+
+        let targets = self.peers().map(|np| {
+            (self.protocol(np.name()), Target::new(self, np.name(), np.peer()))
+        }).collect();
+        let peer_reachable = any_reachable(targets, probe_timeout);
+
+        HealthStatus {
+            storage,
+            tip: tip.is_some(),
+            tip_age,
+            caught_up: tip_age.map(|age| age <= max_tip_age).unwrap_or(false),
+            peer_reachable,
+        }
+    }
+}
+
+/// connect to all the targets at once, true as soon as one of them
+/// accepts the connection; false once they all failed or `timeout` is
+/// elapsed
+fn any_reachable(targets: Vec<(Protocol, Target)>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = mpsc::channel();
+    let nr_targets = targets.len();
+    for (protocol, target) in targets {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let reachable = protocol::backend(protocol).connect(&target).is_ok();
+            let _ = sender.send(reachable);
+        });
+    }
+
+    for _ in 0..nr_targets {
+        let now = Instant::now();
+        if now >= deadline { return false; }
+        match receiver.recv_timeout(deadline - now) {
+            Ok(true) => return true,
+            Ok(false) => {},
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use cardano::config::ProtocolMagic;
+
+    fn target(address: String) -> (Protocol, Target) {
+        (Protocol::Native, Target {
+            blockchain: "test".to_owned(),
+            name: "stalled".to_owned(),
+            address,
+            protocol_magic: ProtocolMagic::from(764824073),
+        })
+    }
+
+    #[test]
+    fn stalled_peer_times_out() {
+        // accepts the connection but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("{}", listener.local_addr().unwrap());
+
+        let timeout = Duration::from_millis(300);
+        let started = Instant::now();
+        assert!(! any_reachable(vec![target(address)], timeout));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= timeout);
+        assert!(elapsed < timeout + Duration::from_secs(2));
+        drop(listener);
+    }
+
+    #[test]
+    fn no_peers() {
+        assert!(! any_reachable(Vec::new(), Duration::from_secs(1)));
+    }
+}
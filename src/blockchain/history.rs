@@ -0,0 +1,71 @@
+//! history of the transactions of an address
+//!
+
+use cardano::address::ExtendedAddr;
+use cardano::block::HeaderHash;
+use cardano::tx::TxId;
+use std::collections::BTreeSet;
+
+use super::{Blockchain, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// the transaction spends an output of the address
+    Input,
+    /// the transaction has an output to the address
+    Output,
+}
+
+/// a transaction referencing an address
+#[derive(Debug, Clone)]
+pub struct TxRef {
+    /// the block containing the transaction
+    pub block: HeaderHash,
+    pub txid: TxId,
+    pub direction: Direction,
+}
+
+impl Blockchain {
+    /// list the transactions referencing the given address, from the
+    /// given block (or the genesis) to the local tip.
+    ///
+    /// This is a scan of every transaction from `from`. The inputs are
+    /// recognised from the outputs seen during the scan, spending an
+    /// output created before `from` is not reported.
+    ///
+    /// A transaction both spending from and paying to the address is
+    /// listed twice, once in each direction.
+    pub fn address_history(&self, addr: &ExtendedAddr, from: Option<HeaderHash>) -> Result<Vec<TxRef>> {
+        let from = from.unwrap_or_else(|| self.config.genesis.clone());
+        let to = self.load_tip()?.0.hash;
+
+        // the outputs to the address, by transaction id and index
+        let mut outputs = BTreeSet::new();
+        let mut history = Vec::new();
+
+        for res in self.iter_transactions(from, to)? {
+            let (block, tx) = res?;
+            let txid = tx.id();
+
+            let is_input = tx.inputs.iter().fold(false, |is_input, txin| {
+                outputs.remove(&(format!("{}", txin.id), txin.index)) || is_input
+            });
+            if is_input {
+                history.push(TxRef { block: block.clone(), txid: txid.clone(), direction: Direction::Input });
+            }
+
+            let mut is_output = false;
+            for (index, txout) in tx.outputs.iter().enumerate() {
+                if &txout.address == addr {
+                    outputs.insert((format!("{}", txid), index as u32));
+                    is_output = true;
+                }
+            }
+            if is_output {
+                history.push(TxRef { block, txid, direction: Direction::Output });
+            }
+        }
+
+        Ok(history)
+    }
+}
@@ -0,0 +1,218 @@
+//! on-disk indexes of the transactions of the blockchain
+//!
+//! `Blockchain::build_tx_index` walks the blockchain up to the local tip
+//! and writes, in the `txindex` directory of the blockchain:
+//!
+//! * `transactions`: the block of every transaction, and its position
+//!   within the block;
+//! * `addresses`: the transactions paying to or spending from every
+//!   address (see `history::Direction`);
+//! * `tip`: the hash of the last block indexed.
+//!
+//! The files are text, one entry per line, sorted by transaction id and by
+//! address: a query reads them up to its key only, instead of decoding
+//! every block. The indexes are not updated by `pull`, they answer up to
+//! the indexed tip until they are built again.
+//!
+//! The `index` directory of the blockchain is the storage's (the indexes
+//! of the packed epochs), it is never written here.
+//!
+
+use std::{fs, io::{self, BufRead, BufReader, Write}, path::PathBuf, collections::BTreeMap};
+use cardano::{address::ExtendedAddr, block::HeaderHash, tx::{TxId, TxoPointer, TxOut}, util::{hex, try_from_slice::TryFromSlice}};
+use cardano_storage as storage;
+
+use super::{Blockchain, Error, Result};
+use super::history::{Direction, TxRef};
+
+/// the directory, within the blockchain directory, of the indexes
+pub const INDEX_DIRECTORY : &'static str = "txindex";
+
+const TRANSACTIONS_FILE : &'static str = "transactions";
+const ADDRESSES_FILE : &'static str = "addresses";
+const TIP_FILE : &'static str = "tip";
+
+/// where a transaction is in the blockchain
+#[derive(Debug, Clone)]
+pub struct TxLocation {
+    pub block: HeaderHash,
+    /// the date of the block
+    pub date: String,
+    /// the position of the transaction within the block
+    pub position: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexReport {
+    pub tip: HeaderHash,
+    pub transactions: usize,
+    pub addresses: usize,
+}
+
+fn invalid_data(what: &str, line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid {} index entry `{}'", what, line))
+}
+
+fn parse_hash(what: &str, line: &str, hash: &str) -> io::Result<HeaderHash> {
+    hex::decode(hash).ok()
+        .and_then(|bytes| HeaderHash::try_from_slice(&bytes).ok())
+        .ok_or_else(|| invalid_data(what, line))
+}
+
+fn direction_str(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Input  => "in",
+        Direction::Output => "out",
+    }
+}
+
+impl Blockchain {
+    fn index_dir(&self) -> PathBuf { self.dir.join(INDEX_DIRECTORY) }
+
+    /// the last block indexed, `None` if the indexes have not been built
+    pub fn indexed_tip(&self) -> Result<Option<HeaderHash>> {
+        let file = self.index_dir().join(TIP_FILE);
+        if ! file.is_file() { return Ok(None); }
+
+        let content = fs::read_to_string(file)?;
+        Ok(Some(parse_hash("tip", &content, content.trim())?))
+    }
+
+    /// walk the blockchain from the genesis to the local tip and write
+    /// the indexes again
+    ///
+    /// The inputs are recognised from the outputs seen during the walk.
+    /// The files are written in a temporary directory first, the indexes
+    /// are replaced only once complete.
+    pub fn build_tx_index(&self) -> Result<IndexReport> {
+        let tip = self.load_tip()?.0.hash;
+
+        let mut transactions = BTreeMap::new();
+        let mut addresses : BTreeMap<String, Vec<(TxId, HeaderHash, Direction)>> = BTreeMap::new();
+        // the address of the unspent outputs, by transaction id and index
+        let mut outputs = BTreeMap::new();
+
+        for res in self.iter(self.config.genesis.clone(), tip.clone())? {
+            let (_, block) = res?;
+            let header = block.get_header();
+            let hash = header.compute_hash();
+            let date = format!("{}", header.get_blockdate());
+            let txs = match block.get_transactions() {
+                None => continue,
+                Some(txs) => txs,
+            };
+
+            for (position, txaux) in txs.iter().enumerate() {
+                let tx = &txaux.tx;
+                let txid = tx.id();
+                transactions.insert(format!("{}", txid), format!("{} {} {}", hash, date, position));
+
+                for txin in tx.inputs.iter() {
+                    if let Some(address) = outputs.remove(&(format!("{}", txin.id), txin.index)) {
+                        addresses.entry(address).or_insert_with(Vec::new)
+                            .push((txid.clone(), hash.clone(), Direction::Input));
+                    }
+                }
+                for (index, txout) in tx.outputs.iter().enumerate() {
+                    let address = format!("{}", txout.address);
+                    outputs.insert((format!("{}", txid), index as u32), address.clone());
+                    addresses.entry(address).or_insert_with(Vec::new)
+                        .push((txid.clone(), hash.clone(), Direction::Output));
+                }
+            }
+        }
+
+        let tmp_dir = self.dir.join(format!("{}.tmp", INDEX_DIRECTORY));
+        if tmp_dir.is_dir() { fs::remove_dir_all(&tmp_dir)?; }
+        fs::create_dir_all(&tmp_dir)?;
+        {
+            let mut file = io::BufWriter::new(fs::File::create(tmp_dir.join(TRANSACTIONS_FILE))?);
+            for (txid, location) in transactions.iter() {
+                writeln!(file, "{} {}", txid, location)?;
+            }
+            file.flush()?;
+        }
+        {
+            let mut file = io::BufWriter::new(fs::File::create(tmp_dir.join(ADDRESSES_FILE))?);
+            for (address, refs) in addresses.iter() {
+                for (txid, block, direction) in refs.iter() {
+                    writeln!(file, "{} {} {} {}", address, txid, block, direction_str(*direction))?;
+                }
+            }
+            file.flush()?;
+        }
+        fs::write(tmp_dir.join(TIP_FILE), format!("{}\n", tip))?;
+
+        // the previous indexes are moved aside first, then removed once
+        // the new ones are in place
+        let index_dir = self.index_dir();
+        let old_dir = self.dir.join(format!("{}.old", INDEX_DIRECTORY));
+        if old_dir.is_dir() { fs::remove_dir_all(&old_dir)?; }
+        if index_dir.is_dir() { fs::rename(&index_dir, &old_dir)?; }
+        fs::rename(tmp_dir, index_dir)?;
+        if old_dir.is_dir() { fs::remove_dir_all(&old_dir)?; }
+
+        Ok(IndexReport { tip: tip, transactions: transactions.len(), addresses: addresses.len() })
+    }
+
+    /// the location of the given transaction, from the indexes
+    pub fn query_tx(&self, txid: &TxId) -> Result<Option<TxLocation>> {
+        let key = format!("{}", txid);
+        let file = BufReader::new(fs::File::open(self.index_dir().join(TRANSACTIONS_FILE))?);
+        for line in file.lines() {
+            let line = line?;
+            let mut fields = line.split(' ');
+            let entry = fields.next().unwrap_or("");
+            if entry < key.as_str() { continue; }
+            if entry > key.as_str() { break; }
+
+            let block = parse_hash("transaction", &line, fields.next().unwrap_or(""))?;
+            let date = fields.next().ok_or_else(|| invalid_data("transaction", &line))?.to_owned();
+            let position = fields.next().and_then(|position| position.parse().ok())
+                .ok_or_else(|| invalid_data("transaction", &line))?;
+            return Ok(Some(TxLocation { block, date, position }));
+        }
+        Ok(None)
+    }
+
+    /// the output the given input spends, from the indexes (see
+    /// `query_tx`), `None` if its transaction is not indexed
+    pub fn query_output(&self, input: &TxoPointer) -> Result<Option<TxOut>> {
+        let location = match self.query_tx(&input.id)? {
+            None => return Ok(None),
+            Some(location) => location,
+        };
+        let raw_block = storage::block_read(self.storage(), &location.block)
+            .ok_or_else(|| Error::BlockNotFound(location.block.clone()))?;
+        let block = raw_block.decode()?;
+        Ok(block.get_transactions()
+            .and_then(|txs| txs.iter().nth(location.position as usize).map(|txaux| txaux.tx.clone()))
+            .and_then(|tx| tx.outputs.get(input.index as usize).cloned()))
+    }
+
+    /// the transactions paying to or spending from the given address, in
+    /// the order of the blockchain, from the indexes
+    pub fn query_address(&self, address: &ExtendedAddr) -> Result<Vec<TxRef>> {
+        let key = format!("{}", address);
+        let file = BufReader::new(fs::File::open(self.index_dir().join(ADDRESSES_FILE))?);
+        let mut refs = Vec::new();
+        for line in file.lines() {
+            let line = line?;
+            let mut fields = line.split(' ');
+            let entry = fields.next().unwrap_or("");
+            if entry < key.as_str() { continue; }
+            if entry > key.as_str() { break; }
+
+            let txid = fields.next().and_then(|txid| txid.parse::<TxId>().ok())
+                .ok_or_else(|| invalid_data("address", &line))?;
+            let block = parse_hash("address", &line, fields.next().unwrap_or(""))?;
+            let direction = match fields.next() {
+                Some("in")  => Direction::Input,
+                Some("out") => Direction::Output,
+                _ => return Err(invalid_data("address", &line).into()),
+            };
+            refs.push(TxRef { block, txid, direction });
+        }
+        Ok(refs)
+    }
+}
@@ -0,0 +1,130 @@
+//! select whether the ends of a range of blocks are part of the range
+//!
+
+use cardano::block::HeaderHash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Included,
+    Excluded,
+}
+
+/// the bounds of a range of blocks, `from` and `to`.
+///
+/// The default are both ends included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeBounds {
+    pub start: Bound,
+    pub end: Bound,
+}
+impl RangeBounds {
+    pub fn new(start: Bound, end: Bound) -> Self { RangeBounds { start, end } }
+
+    /// `[from, to]`
+    pub fn inclusive() -> Self { RangeBounds::new(Bound::Included, Bound::Included) }
+
+    /// `[from, to)`, handy to chain ranges: `[from, to)` then `[to, to2)`
+    pub fn half_open() -> Self { RangeBounds::new(Bound::Included, Bound::Excluded) }
+}
+impl Default for RangeBounds {
+    fn default() -> Self { RangeBounds::inclusive() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// the block is part of the range
+    Yield,
+    /// the block is the excluded start of the range
+    Skip,
+    /// the range is over, the block is not part of it
+    Done,
+}
+
+/// apply the `RangeBounds` on the blocks of an inclusive range
+pub struct Filter {
+    start: HeaderHash,
+    end: HeaderHash,
+    bounds: RangeBounds,
+    started: bool,
+}
+impl Filter {
+    pub fn new(start: HeaderHash, end: HeaderHash, bounds: RangeBounds) -> Self {
+        Filter { start, end, bounds, started: false }
+    }
+
+    /// what to do with the next block of the inclusive range
+    pub fn step(&mut self, hash: &HeaderHash) -> Step {
+        let first = ! self.started;
+        self.started = true;
+
+        let is_end = hash == &self.end;
+        if first && hash == &self.start && self.bounds.start == Bound::Excluded {
+            return if is_end { Step::Done } else { Step::Skip };
+        }
+        if is_end && self.bounds.end == Bound::Excluded {
+            return Step::Done;
+        }
+        Step::Yield
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::Bound::*;
+
+    fn hash(i: u8) -> HeaderHash { HeaderHash::from([i; 32]) }
+
+    /// the blocks of the given inclusive range retained by the bounds
+    fn apply(blocks: &[u8], bounds: RangeBounds) -> Vec<u8> {
+        let mut filter = Filter::new(hash(blocks[0]), hash(blocks[blocks.len() - 1]), bounds);
+        let mut retained = Vec::new();
+        for i in blocks {
+            match filter.step(&hash(*i)) {
+                Step::Yield => retained.push(*i),
+                Step::Skip  => {},
+                Step::Done  => break,
+            }
+        }
+        retained
+    }
+
+    #[test]
+    fn single_block_range() {
+        assert_eq!(apply(&[1], RangeBounds::new(Included, Included)), vec![1]);
+        assert_eq!(apply(&[1], RangeBounds::new(Included, Excluded)), Vec::<u8>::new());
+        assert_eq!(apply(&[1], RangeBounds::new(Excluded, Included)), Vec::<u8>::new());
+        assert_eq!(apply(&[1], RangeBounds::new(Excluded, Excluded)), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn default_is_inclusive() {
+        assert_eq!(RangeBounds::default(), RangeBounds::inclusive());
+        assert_eq!(apply(&[1, 2, 3], RangeBounds::default()), vec![1, 2, 3]);
+    }
+
+    /// the filter with the bounds of the callers: the default bounds from
+    /// the genesis to the tip (`verify`, `index build`) and over a single
+    /// block (`export-blocks --from h --to h`, `serve` with `from=h&to=h`),
+    /// and the bounds of the wallet sync resuming after its last known block
+    #[test]
+    fn filter_with_callers_bounds() {
+        // the genesis is verified and indexed
+        assert_eq!(apply(&[0, 1, 2], RangeBounds::default()), vec![0, 1, 2]);
+        // a single block is exported
+        assert_eq!(apply(&[5], RangeBounds::default()), vec![5]);
+        // the last known block is not applied twice
+        let resume = RangeBounds::new(Excluded, Included);
+        assert_eq!(apply(&[4, 5, 6], resume), vec![5, 6]);
+        // the wallet is already at the tip: nothing to apply
+        assert_eq!(apply(&[6], resume), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn multiple_blocks_range() {
+        assert_eq!(apply(&[1, 2, 3], RangeBounds::new(Included, Included)), vec![1, 2, 3]);
+        assert_eq!(apply(&[1, 2, 3], RangeBounds::new(Included, Excluded)), vec![1, 2]);
+        assert_eq!(apply(&[1, 2, 3], RangeBounds::new(Excluded, Included)), vec![2, 3]);
+        assert_eq!(apply(&[1, 2, 3], RangeBounds::new(Excluded, Excluded)), vec![2]);
+    }
+}
@@ -1,7 +1,10 @@
 use std::{fmt, error};
+use cardano::block::HeaderHash;
 
 #[derive(Debug)]
 pub enum Error {
+    /// an end of the range is not in the storage
+    BlockNotFound(HeaderHash),
     IoError(::std::io::Error),
     CborError(::cbor_event::Error),
     StorageError(::cardano_storage::Error),
@@ -21,6 +24,7 @@ pub type Result<T> = ::std::result::Result<T, Error>;
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Error::BlockNotFound(hash) => write!(f, "Block {} not found in the local storage", hash),
             Error::IoError(_)      => write!(f, "I/O Error"),
             Error::CborError(_)    => write!(f, "Encoding error (CBOR)"),
             Error::StorageError(_) => write!(f, "Storage error"),
@@ -30,6 +34,7 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn cause(&self) -> Option<& error::Error> {
         match self {
+            Error::BlockNotFound(_) => None,
             Error::IoError(ref err) => Some(err),
             Error::CborError(ref err) => Some(err),
             Error::StorageError(ref err) => Some(err),
@@ -1,7 +1,11 @@
 mod error;
 pub mod epoch;
+pub mod bounds;
+pub mod transactions;
 
 pub use self::error::{Error, Result};
+pub use self::bounds::{Bound, RangeBounds};
+pub use self::transactions::{Transactions};
 
 use cardano::block::{Block, RawBlock, HeaderHash};
 use cardano_storage::{self as storage, Storage};
@@ -66,10 +70,18 @@ impl<'a> Iterator for IteratorType<'a> {
     }
 }
 
+/// iterate through the blocks from `from` to `to`
+///
+/// By default both `from` and `to` are part of the iteration, whether
+/// they are packed or loose blocks. See `Iter::with_bounds` to exclude
+/// either end: the callers resuming after a block they have already
+/// processed (the wallet sync) exclude `from`.
 pub struct Iter<'a> {
     storage: &'a Storage,
 
     initialised: bool,
+    finished: bool,
+    bounds: bounds::Filter,
 
     starting_from: HeaderHash,
     ending_at: HeaderHash,
@@ -80,31 +92,40 @@ pub struct Iter<'a> {
 }
 impl<'a> Iter<'a> {
     pub fn new(storage: &'a Storage, from: HeaderHash, to: HeaderHash) -> Result<Self> {
+        Self::with_bounds(storage, from, to, RangeBounds::default())
+    }
+
+    /// fails with `Error::BlockNotFound` if `from` or `to` is not stored
+    pub fn with_bounds(storage: &'a Storage, from: HeaderHash, to: HeaderHash, bounds: RangeBounds) -> Result<Self> {
+        if storage::block_location(&storage, &to).is_none() {
+            return Err(Error::BlockNotFound(to));
+        }
         let iterator = match storage::block_location(&storage, &from) {
-            None => panic!(),
+            None => return Err(Error::BlockNotFound(from)),
             Some(storage::BlockLocation::Loose) => {
-                let mut range = storage::block::Range::new(
+                let range = storage::block::Range::new(
                     storage,
                     *from.clone(),
                     *to.clone()
-                ).unwrap(); // TODO
-                range.next();
+                )?;
                 IteratorType::Loose(storage, range)
             },
             Some(location) => {
-                let block_header = storage::block_read_location(&storage, &location, &from).unwrap().decode()?.get_header();
+                let block_header = storage::block_read_location(&storage, &location, &from)
+                    .ok_or_else(|| Error::BlockNotFound(from.clone()))?
+                    .decode()?.get_header();
                 let block_date = block_header.get_blockdate();
 
                 let epochs = epoch::Epochs::new(&storage.config).from_epoch(block_date.get_epochid());
-                let mut iterator = IteratorType::Epoch(epochs, None);
-
-                iterator
+                IteratorType::Epoch(epochs, None)
             }
         };
 
         let iter = Iter {
             storage: storage,
             initialised: false,
+            finished: false,
+            bounds: bounds::Filter::new(from.clone(), to.clone(), bounds),
             starting_from: from,
             ending_at: to,
             last_known_block_hash: None,
@@ -114,10 +135,9 @@ impl<'a> Iter<'a> {
         Ok(iter)
     }
 }
-impl<'a> Iterator for Iter<'a> {
-    type Item = Result<(RawBlock, Block)>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a> Iter<'a> {
+    /// the next block of the inclusive range `[from, to]`
+    fn next_block(&mut self) -> Option<Result<(RawBlock, Block)>> {
         if let Some(ref hh) = self.last_known_block_hash {
             if hh == &self.ending_at { return None; }
         }
@@ -125,41 +145,51 @@ impl<'a> Iterator for Iter<'a> {
         if ! self.initialised {
             self.initialised = true;
 
-            let mut next = None;
-            for block in self.iterator.next() {
+            // the epoch iterator starts at the beginning of the epoch,
+            // skip the blocks preceding `from`
+            while let Some(block) = self.iterator.next() {
                 match block {
                     Err(err) => return Some(Err(err)),
                     Ok(raw_block) => {
-                        let block = raw_block.decode().unwrap();
+                        let block = match raw_block.decode() {
+                            Err(err) => return Some(Err(err.into())),
+                            Ok(block) => block,
+                        };
                         let hh = block.get_header().compute_hash();
-                        let end = &hh == &self.starting_from;
-                        next = Some(Ok((raw_block, block)));
-                        self.last_known_block_hash = Some(hh);
-                        if end { break; }
+                        if hh == self.starting_from {
+                            self.last_known_block_hash = Some(hh);
+                            return Some(Ok((raw_block, block)));
+                        }
                     }
                 }
             }
 
-            next
+            None
         } else {
             match self.iterator.next() {
                 None => {
                     if ! self.iterator.is_loose() {
-                        let mut range = storage::block::Range::new(
-                            &self.storage,
-                            *self.last_known_block_hash.clone().unwrap(),
-                            *self.ending_at.clone()
-                        ).unwrap(); // TODO
+                        let last_known_block_hash = match self.last_known_block_hash {
+                            None => return None,
+                            Some(ref hash) => hash.clone(),
+                        };
+                        let mut range = match storage::block::Range::new(&self.storage, *last_known_block_hash, *self.ending_at.clone()) {
+                            Err(err) => return Some(Err(err.into())),
+                            Ok(range) => range,
+                        };
                         range.next(); // remove the last known block hash (it was the one in the last epoch)
                         self.iterator = IteratorType::Loose(&self.storage, range);
-                        self.next()
+                        self.next_block()
                     } else {
                         None
                     }
                 },
                 Some(Err(err)) => Some(Err(err)),
                 Some(Ok(raw_block)) => {
-                    let block = raw_block.decode().unwrap();
+                    let block = match raw_block.decode() {
+                        Err(err) => return Some(Err(err.into())),
+                        Ok(block) => block,
+                    };
                     let hh = block.get_header().compute_hash();
                     self.last_known_block_hash = Some(hh);
                     Some(Ok((raw_block, block)))
@@ -168,3 +198,21 @@ impl<'a> Iterator for Iter<'a> {
         }
     }
 }
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<(RawBlock, Block)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished { return None; }
+        loop {
+            let (raw_block, block) = match self.next_block()? {
+                Err(err) => return Some(Err(err)),
+                Ok(next) => next,
+            };
+            match self.bounds.step(&block.get_header().compute_hash()) {
+                bounds::Step::Yield => return Some(Ok((raw_block, block))),
+                bounds::Step::Skip  => continue,
+                bounds::Step::Done  => { self.finished = true; return None; },
+            }
+        }
+    }
+}
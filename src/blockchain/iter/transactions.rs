@@ -0,0 +1,44 @@
+//! iterate through the transactions of a range of blocks
+//!
+
+use cardano::block::HeaderHash;
+use cardano::tx::Tx;
+use std::vec;
+
+use super::{Iter, Result};
+
+/// the transactions of the blocks of an `Iter`, each of them with the
+/// hash of its block. The blocks without transactions (the epoch
+/// boundary blocks) yield nothing.
+pub struct Transactions<'a> {
+    blocks: Iter<'a>,
+    current: Option<(HeaderHash, vec::IntoIter<Tx>)>,
+}
+impl<'a> Transactions<'a> {
+    pub fn new(blocks: Iter<'a>) -> Self {
+        Transactions { blocks, current: None }
+    }
+}
+impl<'a> Iterator for Transactions<'a> {
+    type Item = Result<(HeaderHash, Tx)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((ref hash, ref mut txs)) = self.current {
+                if let Some(tx) = txs.next() {
+                    return Some(Ok((hash.clone(), tx)));
+                }
+            }
+
+            let (_, block) = match self.blocks.next()? {
+                Err(err) => return Some(Err(err)),
+                Ok(block) => block,
+            };
+            let txs = match block.get_transactions() {
+                None => Vec::new(),
+                Some(txs) => txs.iter().map(|txaux| txaux.tx.clone()).collect(),
+            };
+            self.current = Some((block.get_header().compute_hash(), txs.into_iter()));
+        }
+    }
+}
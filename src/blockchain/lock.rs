@@ -0,0 +1,155 @@
+//! advisory lock of the blockchain directories
+//!
+//! A loaded `Blockchain` holds a lock on the `lock` file of its directory
+//! for as long as it lives: an exclusive lock to modify the blockchain
+//! (`Blockchain::load`, `Blockchain::new`), a shared lock to only read it
+//! (`Blockchain::load_read_only`). A `pull` and a `wallet sync` of the
+//! same blockchain wait for each other, two reads run side by side.
+//!
+//! A lock held by another process is waited for, up to `DEFAULT_TIMEOUT`
+//! or not at all with `--no-wait` (see `init`). The locks are released by
+//! the system when the process exits, even when it is killed.
+//!
+
+use std::{fs, thread, path::Path};
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use fs2::{self, FileExt};
+
+use super::{Error, Result};
+
+/// the lock file, within the blockchain directory
+pub const LOCK_FILE : &'static str = "lock";
+
+/// the time to wait for a lock held by another process
+pub const DEFAULT_TIMEOUT : Duration = Duration::from_secs(60);
+
+const POLL_INTERVAL : Duration = Duration::from_millis(100);
+
+static NO_WAIT : AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// to read the blockchain, along with the other readers
+    Shared,
+    /// to modify the blockchain, alone
+    Exclusive,
+}
+
+/// to be called once, with `--no-wait`, to fail at once on the locks
+/// held by another process
+pub fn init(no_wait: bool) {
+    NO_WAIT.store(no_wait, Ordering::SeqCst)
+}
+
+/// the time to wait for a lock, as set by `init`
+pub fn timeout() -> Duration {
+    if NO_WAIT.load(Ordering::SeqCst) { Duration::from_secs(0) } else { DEFAULT_TIMEOUT }
+}
+
+/// the lock of a blockchain directory, released once dropped
+#[derive(Debug)]
+pub struct Lock {
+    file: fs::File,
+    mode: Mode,
+}
+impl Lock {
+    /// lock the given blockchain directory
+    ///
+    /// fails with `Error::Locked` if the lock is still held by another
+    /// process after `timeout`.
+    pub fn acquire(dir: &Path, mode: Mode, timeout: Duration) -> Result<Self> {
+        let file = fs::OpenOptions::new().read(true).write(true).create(true).open(dir.join(LOCK_FILE))?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            let result = match mode {
+                Mode::Shared    => file.try_lock_shared(),
+                Mode::Exclusive => file.try_lock_exclusive(),
+            };
+            match result {
+                Ok(()) => return Ok(Lock { file, mode }),
+                Err(ref err) if err.kind() == fs2::lock_contended_error().kind() => {},
+                Err(err) => return Err(err.into()),
+            }
+
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                return Err(Error::Locked(dir.to_path_buf()));
+            }
+            if ! waiting {
+                warn!("{:?} is in use by another process, waiting for it", dir);
+                waiting = true;
+            }
+            thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+        }
+    }
+
+    pub fn mode(&self) -> Mode { self.mode }
+}
+impl Drop for Lock {
+    fn drop(&mut self) { let _ = self.file.unlock(); }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn lock_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("cardano-cli-lock-{}-{}", name, ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn shared_locks_side_by_side() {
+        let dir = lock_dir("shared");
+        let first = Lock::acquire(&dir, Mode::Shared, Duration::from_secs(0)).unwrap();
+        let second = Lock::acquire(&dir, Mode::Shared, Duration::from_secs(0)).unwrap();
+        assert_eq!(first.mode(), Mode::Shared);
+        assert_eq!(second.mode(), Mode::Shared);
+        drop((first, second));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shared_lock_blocks_exclusive() {
+        let dir = lock_dir("exclusive");
+        let shared = Lock::acquire(&dir, Mode::Shared, Duration::from_secs(0)).unwrap();
+
+        // waited for up to the timeout, then given up
+        let timeout = Duration::from_millis(300);
+        let started = Instant::now();
+        match Lock::acquire(&dir, Mode::Exclusive, timeout) {
+            Err(Error::Locked(locked)) => assert_eq!(locked, dir),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(started.elapsed() >= timeout);
+
+        // released once the shared lock is dropped
+        drop(shared);
+        let exclusive = Lock::acquire(&dir, Mode::Exclusive, Duration::from_secs(0)).unwrap();
+        assert_eq!(exclusive.mode(), Mode::Exclusive);
+        drop(exclusive);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn no_wait_fails_immediately() {
+        let dir = lock_dir("no-wait");
+        let exclusive = Lock::acquire(&dir, Mode::Exclusive, Duration::from_secs(0)).unwrap();
+
+        let started = Instant::now();
+        for mode in [Mode::Shared, Mode::Exclusive].iter() {
+            match Lock::acquire(&dir, *mode, Duration::from_secs(0)) {
+                Err(Error::Locked(_)) => {},
+                result => panic!("{:?}: unexpected result: {:?}", mode, result),
+            }
+        }
+        assert!(started.elapsed() < POLL_INTERVAL);
+        drop(exclusive);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+//! metrics of the synchronisation, in the Prometheus text format
+//!
+//! The counters are kept for the life of the process and updated by the
+//! syncs (`peer::ConnectedPeer::sync_with_progress`), they only move in
+//! the processes syncing the blockchains (`daemon`). The tips are read
+//! from the blockchains when the metrics are rendered:
+//!
+//! * `cardano_cli_blocks_applied_total`: the blocks downloaded and stored;
+//! * `cardano_cli_bytes_downloaded_total`: the size of these blocks;
+//! * `cardano_cli_sync_failures_total`: the syncs which failed;
+//! * `cardano_cli_tip_slot{blockchain}`: the slot of the local tip, since
+//!   the first slot of the network;
+//! * `cardano_cli_sync_lag_slots{blockchain}`: the slots between the
+//!   local tip and the highest tip of the remotes, as of their last sync.
+//!
+
+use std::{fmt::Write, sync::atomic::{AtomicUsize, Ordering}};
+
+use super::{Blockchain, Result};
+
+static BLOCKS_APPLIED : AtomicUsize = AtomicUsize::new(0);
+static BYTES_DOWNLOADED : AtomicUsize = AtomicUsize::new(0);
+static SYNC_FAILURES : AtomicUsize = AtomicUsize::new(0);
+
+/// a block of the given size has been downloaded and stored
+pub fn record_block(size: usize) {
+    BLOCKS_APPLIED.fetch_add(1, Ordering::Relaxed);
+    BYTES_DOWNLOADED.fetch_add(size, Ordering::Relaxed);
+}
+
+pub fn record_sync_failure() {
+    SYNC_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, counter: &AtomicUsize) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} counter", name).unwrap();
+    writeln!(out, "{} {}", name, counter.load(Ordering::Relaxed)).unwrap();
+}
+
+impl Blockchain {
+    /// the slot of the local tip, and the slots between it and the highest
+    /// tip of the remotes (see `peer_stats::PeerStats::tip_height`)
+    ///
+    /// both are counted from the first slot of the network (see
+    /// `Blockchain::flat_slot`), so the lag spans the epoch boundaries.
+    fn sync_lag(&self) -> Result<(u64, u64)> {
+        let tip_slot = self.flat_slot(&self.load_tip()?.0.date);
+        let remote_slot = self.peers()
+            .filter_map(|np| self.load_peer_stats(np.name()).ok().and_then(|stats| stats.tip_height))
+            .max()
+            .unwrap_or(0);
+        Ok((tip_slot, remote_slot.saturating_sub(tip_slot)))
+    }
+}
+
+/// render the counters of the process and the tips of the given
+/// blockchains, the blockchains whose tip cannot be loaded are left out
+pub fn render(blockchains: &[Blockchain]) -> String {
+    let mut out = String::new();
+    counter(&mut out, "cardano_cli_blocks_applied_total", "the number of blocks downloaded and stored", &BLOCKS_APPLIED);
+    counter(&mut out, "cardano_cli_bytes_downloaded_total", "the size of the blocks downloaded, in bytes", &BYTES_DOWNLOADED);
+    counter(&mut out, "cardano_cli_sync_failures_total", "the number of syncs with a remote which failed", &SYNC_FAILURES);
+
+    let lags : Vec<(&str, (u64, u64))> = blockchains.iter()
+        .filter_map(|blockchain| blockchain.sync_lag().ok().map(|lag| (blockchain.name.as_str(), lag)))
+        .collect();
+    writeln!(out, "# HELP cardano_cli_tip_slot the slot of the local tip").unwrap();
+    writeln!(out, "# TYPE cardano_cli_tip_slot gauge").unwrap();
+    for (name, (tip_slot, _)) in lags.iter() {
+        writeln!(out, "cardano_cli_tip_slot{{blockchain=\"{}\"}} {}", name, tip_slot).unwrap();
+    }
+    writeln!(out, "# HELP cardano_cli_sync_lag_slots the slots between the local tip and the highest remote tip").unwrap();
+    writeln!(out, "# TYPE cardano_cli_sync_lag_slots gauge").unwrap();
+    for (name, (_, lag)) in lags.iter() {
+        writeln!(out, "cardano_cli_sync_lag_slots{{blockchain=\"{}\"}} {}", name, lag).unwrap();
+    }
+    out
+}
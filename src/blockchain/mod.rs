@@ -4,48 +4,358 @@ pub mod peer;
 pub mod iter;
 pub mod parse_genesis_data;
 pub mod genesis_data;
+pub mod verify;
+pub mod store;
+pub mod bloom;
+pub mod stats;
+pub mod health;
+pub mod history;
+pub mod gaps;
+pub mod checkpoint;
+pub mod prune;
+pub mod rollback;
+pub mod archive;
+pub mod peer_stats;
+pub mod progress;
+pub mod lock;
+pub mod metrics;
+pub mod index;
+pub mod protocol;
+pub mod export_blocks;
+mod error;
 
-use std::path::PathBuf;
+pub use self::error::{Error, Result};
+pub use self::store::{BlockStore};
+
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+use std::time::{Duration, SystemTime};
+use std::cell::OnceCell;
 
 use exe_common::network::api::BlockRef;
 pub use exe_common::{config::net::{self, Config, Peer, Peers}, network};
-use cardano_storage::{self as storage, tag, Storage, config::{StorageConfig}};
+use cardano_storage::{Storage, config::{StorageConfig}};
 use cardano::block;
+use cardano::util::try_from_slice::TryFromSlice;
+
+use self::protocol::Protocol;
 
 pub const LOCAL_BLOCKCHAIN_TIP_TAG : &'static str = "tip";
 
+/// the file, within the blockchain directory, of the bloom filter of
+/// the stored block hashes (see `Blockchain::rebuild_index`)
+pub const BLOOM_FILTER_FILE : &'static str = "bloom";
+
+/// the file, within the blockchain directory, of the version of its
+/// layout. The blockchains created before it was introduced have none,
+/// they are of the first version.
+pub const STORAGE_VERSION_FILE : &'static str = "version";
+
+/// the version of the layout of the blockchains created, and supported
+pub const STORAGE_VERSION : u32 = 1;
+
+/// the genesis of a blockchain (see `Blockchain::genesis`)
+#[derive(Debug, Clone)]
+pub struct GenesisInfo {
+    /// the hash of the first block of the blockchain
+    pub hash: block::HeaderHash,
+    /// the hash of the genesis data (the parent of the first block)
+    pub prev: block::HeaderHash,
+    /// the epoch of the first block
+    pub epoch_start: block::EpochId,
+}
+impl GenesisInfo {
+    /// the genesis block as a `BlockRef`
+    pub fn block_ref(&self) -> BlockRef {
+        BlockRef {
+            hash: self.hash.clone(),
+            parent: self.prev.clone(),
+            date: block::BlockDate::Genesis(self.epoch_start)
+        }
+    }
+}
+
+/// how many times, and how often, to try fetching a remote's tip from
+/// the network before reporting the error (see
+/// `peer::ConnectedPeer::probe_tip_with`)
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// the total number of attempts (including the first)
+    pub attempts: usize,
+    /// the delay between two attempts
+    pub delay: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 2,
+            delay: Duration::from_millis(100),
+        }
+    }
+}
+
 /// handy structure to use to manage and orginise a blockchain
 ///
-pub struct Blockchain {
+/// The blocks and the tags are stored in a `BlockStore`, by default
+/// the file storage (`cardano_storage::Storage`). The store is only
+/// opened on the first access to the blocks or the tags (see `storage`
+/// and `open_storage`), operations on the config alone don't need it.
+///
+pub struct Blockchain<S = Storage> {
     pub name: String,
     pub dir: PathBuf,
     pub storage_config: StorageConfig,
-    pub storage: Storage,
     pub config: Config,
+
+    /// the protocol declared for each remote, by alias (see `protocol`)
+    pub protocols: BTreeMap<String, Protocol>,
+
+    /// the checkpoint the blockchain has been started from, `None` if it
+    /// starts from the genesis of the network (see `is_from_checkpoint`)
+    pub checkpoint: Option<checkpoint::Checkpoint>,
+
+    storage: OnceCell<S>,
+
+    bloom: bloom::LazyBloomFilter,
+
+    /// held as long as the blockchain is loaded (see `lock`)
+    lock: Option<lock::Lock>,
 }
 impl Blockchain {
     /// create the new blockhain with the given setting
-    pub fn new(root_dir: PathBuf, name: String, config: Config) -> Self {
+    ///
+    /// fails with `Error::DuplicatePeer` if the same peer alias is
+    /// present more than once in the given config. Nothing is created
+    /// on the filesystem in that case.
+    pub fn new(root_dir: PathBuf, name: String, config: Config) -> Result<Self> {
+        let dir = config::directory(root_dir, &name);
+        Self::new_in(dir, name, config)
+    }
+
+    /// same as `new` but the blockchain is stored in the given directory
+    /// instead of the one derived from the name.
+    ///
+    /// the directory is locked (exclusively) before anything is written
+    /// in it but the storage directories.
+    pub fn new_in(dir: PathBuf, name: String, config: Config) -> Result<Self> {
+        Self::create(dir, name, config, None)
+    }
+
+    /// create the blockchain in `dir`, started from the given checkpoint
+    /// if any (see `new_from_checkpoint`)
+    fn create(dir: PathBuf, name: String, config: Config, checkpoint: Option<checkpoint::Checkpoint>) -> Result<Self> {
+        check_peers_uniqueness(&config)?;
+
+        let storage_config = StorageConfig::new(&dir);
+
+        // the lock is taken before the storage is created, another process
+        // creating the same blockchain does not see it half initialised
+        ::std::fs::create_dir_all(&dir)?;
+        let lock = lock::Lock::acquire(&dir, lock::Mode::Exclusive, lock::timeout())?;
+        let storage = Storage::init(&storage_config)?;
+        ::std::fs::write(dir.join(STORAGE_VERSION_FILE), format!("{}\n", STORAGE_VERSION))?;
+        write_config(&config, &BTreeMap::new(), checkpoint.as_ref(), &storage_config.get_config_file())?;
+
+        let mut blockchain = Blockchain::init(name, dir, storage_config, storage, config);
+        blockchain.checkpoint = checkpoint;
+        blockchain.lock = Some(lock);
+        Ok(blockchain)
+    }
+
+    /// the lock held on the blockchain directory, `None` if it has been
+    /// loaded without (see `load_from`)
+    pub fn lock_mode(&self) -> Option<lock::Mode> {
+        self.lock.as_ref().map(|lock| lock.mode())
+    }
+
+    pub unsafe fn destroy(self) -> ::std::io::Result<()> {
+        ::std::fs::remove_dir_all(self.dir)
+    }
+
+    /// load the blockchain, to modify it
+    ///
+    /// only the configuration is read, the storage is opened on first
+    /// use. Call `open_storage` right after to fail early on a broken
+    /// storage directory.
+    ///
+    /// the blockchain directory is locked exclusively as long as the
+    /// blockchain is loaded (see `lock`), use `load_read_only` for the
+    /// commands which do not modify it.
+    ///
+    /// fails with `Error::ConfigNotFound` if there is no such blockchain,
+    /// `Error::InvalidConfig` if its configuration cannot be read and
+    /// `Error::IncompatibleStorageVersion` if it has been created by a
+    /// later version.
+    pub fn load(root_dir: PathBuf, name: String) -> Result<Self> {
+        Self::load_with(root_dir, name, lock::Mode::Exclusive, lock::timeout())
+    }
+
+    /// same as `load` but the lock is shared with the other readers
+    pub fn load_read_only(root_dir: PathBuf, name: String) -> Result<Self> {
+        Self::load_with(root_dir, name, lock::Mode::Shared, lock::timeout())
+    }
+
+    /// load the blockchain with the given lock, waiting at most `timeout`
+    /// for the other processes to release it
+    pub fn load_with(root_dir: PathBuf, name: String, mode: lock::Mode, timeout: Duration) -> Result<Self> {
         let dir = config::directory(root_dir, &name);
+        let config_file = StorageConfig::new(&dir).get_config_file();
+        if ! config_file.is_file() {
+            return Err(Error::ConfigNotFound(config_file));
+        }
+
+        let lock = lock::Lock::acquire(&dir, mode, timeout)?;
+        let mut blockchain = Self::load_from(dir, name)?;
+        blockchain.lock = Some(lock);
+        Ok(blockchain)
+    }
+
+    /// same as `load` but the blockchain is read from the given directory
+    /// instead of the one derived from the name.
+    ///
+    /// no lock is taken: for the directories private to the process (see
+    /// `archive`), or to only read the tags, which are written atomically
+    /// (see `metrics`).
+    pub fn load_from(dir: PathBuf, name: String) -> Result<Self> {
         let storage_config = StorageConfig::new(&dir);
 
-        let storage = Storage::init(&storage_config).unwrap();
-        let file = storage_config.get_config_file();
-        config.to_file(file);
+        check_storage_version(&dir)?;
+        let ConfigFile { config, protocols, checkpoint } = read_config(&storage_config.get_config_file())?;
 
+        let bloom = bloom::LazyBloomFilter::new(dir.join(BLOOM_FILTER_FILE));
+        Ok(Blockchain {
+            name,
+            dir,
+            storage_config,
+            config,
+            protocols,
+            checkpoint,
+            storage: OnceCell::new(),
+            bloom,
+            lock: None,
+        })
+    }
+
+    /// iterate through the blocks from `from` to `to`, both included
+    pub fn iter<'a>(&'a self, from: block::HeaderHash, to: block::HeaderHash) -> iter::Result<iter::Iter<'a>> {
+        self.iter_between(from, to, iter::RangeBounds::inclusive())
+    }
+
+    /// iterate through the blocks from `from` to `to`, the `bounds`
+    /// select whether `from` and `to` are part of the iteration.
+    pub fn iter_between<'a>(&'a self, from: block::HeaderHash, to: block::HeaderHash, bounds: iter::RangeBounds)
+        -> iter::Result<iter::Iter<'a>>
+    {
+        iter::Iter::with_bounds(self.storage(), from, to, bounds)
+    }
+
+    /// iterate through the transactions of the blocks from `from` to `to`
+    /// (both included), each with the hash of the block containing it.
+    pub fn iter_transactions<'a>(&'a self, from: block::HeaderHash, to: block::HeaderHash)
+        -> iter::Result<iter::Transactions<'a>>
+    {
+        Ok(iter::Transactions::new(self.iter(from, to)?))
+    }
+
+    /// iterate through the blocks from `from` to the local tip, both
+    /// included
+    pub fn iter_to_tip<'a>(&'a self, from: block::HeaderHash) -> Result<iter::Iter<'a>> {
+        let to   = self.load_tip()?.0.hash;
+
+        Ok(self.iter(from, to)?)
+    }
+
+    /// walk the blockchain from the given block up to the local tip (both
+    /// included), calling `f` on every block.
+    ///
+    /// The walk stops as soon as `f` returns `ControlFlow::Break`.
+    /// Any error while reading or decoding the blocks is returned.
+    pub fn walk<F>(&self, from: block::HeaderHash, mut f: F) -> Result<()>
+        where F: FnMut(&block::Block) -> ControlFlow<()>
+    {
+        for res in self.iter_to_tip(from)? {
+            let (_raw_block, block) = res?;
+            if let ControlFlow::Break(()) = f(&block) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// rebuild the bloom filter of the stored block hashes
+    ///
+    /// every packed epoch and every loose block is read, this may take
+    /// some time on a large blockchain.
+    pub fn rebuild_index(&self) -> Result<()> {
+        let mut hashes = Vec::new();
+        for epoch in iter::epoch::Epochs::new(&self.storage_config).from_epoch(self.config.epoch_start) {
+            for raw_block in epoch? {
+                hashes.push(raw_block?.decode()?.get_header().compute_hash());
+            }
+        }
+
+        // the loose blocks are stored in files named after their hash
+        let blob_dir = self.dir.join("blob");
+        if blob_dir.is_dir() {
+            for entry in ::std::fs::read_dir(blob_dir)? {
+                let entry = entry?;
+                let hash = entry.file_name().to_str()
+                    .and_then(|name| ::cardano::util::hex::decode(name).ok())
+                    .and_then(|bytes| block::HeaderHash::try_from_slice(&bytes).ok());
+                match hash {
+                    None => warn!("unexpected file in the blobs directory: {:?}", entry.path()),
+                    Some(hash) => hashes.push(hash),
+                }
+            }
+        }
+
+        let mut filter = bloom::BloomFilter::new(hashes.len());
+        for hash in hashes.iter() { filter.insert(hash); }
+        self.bloom.resume(filter)?;
+        Ok(())
+    }
+
+    /// take the bloom filter out while new blocks are written to the
+    /// storage, give it back with `resume_index`.
+    pub fn suspend_index(&self) -> Result<Option<bloom::BloomFilter>> {
+        Ok(self.bloom.suspend()?)
+    }
+
+    pub fn resume_index(&self, filter: bloom::BloomFilter) -> Result<()> {
+        Ok(self.bloom.resume(filter)?)
+    }
+}
+impl<S: BlockStore> Blockchain<S> {
+    /// create a new blockchain on top of the given `BlockStore`
+    ///
+    /// the configuration is still saved in the given directory (see `save`).
+    pub fn with_store(dir: PathBuf, name: String, config: Config, store: S) -> Result<Self> {
+        check_peers_uniqueness(&config)?;
+
+        let storage_config = StorageConfig::new(&dir);
+        Ok(Blockchain::init(name, dir, storage_config, store, config))
+    }
+
+    fn init(name: String, dir: PathBuf, storage_config: StorageConfig, storage: S, config: Config) -> Self {
         // by default, the config file comes with pre-set remote peers,
         // check that, for every peer, we add them to the fold
         for peer in config.peers.iter() {
             let tag = format!("remote/{}", peer.name());
-            tag::write_hash(&storage, &tag, &config.genesis)
+            storage.write_tag(&tag, &config.genesis)
         }
 
+        let bloom = bloom::LazyBloomFilter::new(dir.join(BLOOM_FILTER_FILE));
         let blockchain = Blockchain {
             name,
             dir,
             storage_config,
-            storage,
             config,
+            protocols: BTreeMap::new(),
+            checkpoint: None,
+            storage: OnceCell::from(storage),
+            bloom,
+            lock: None,
         };
 
         blockchain.save_tip(&blockchain.config.genesis);
@@ -53,59 +363,97 @@ impl Blockchain {
         blockchain
     }
 
-    pub unsafe fn destroy(self) -> ::std::io::Result<()> {
-        ::std::fs::remove_dir_all(self.dir)
+    /// open the store of the blockchain, if not already opened
+    pub fn open_storage(&self) -> Result<&S> {
+        if let Some(storage) = self.storage.get() {
+            return Ok(storage);
+        }
+        let storage = S::open(&self.storage_config)?;
+        Ok(self.storage.get_or_init(|| storage))
     }
 
-    /// load the blockchain
-    pub fn load(root_dir: PathBuf, name: String) -> Self {
-        let dir = config::directory(root_dir, &name);
-        let storage_config = StorageConfig::new(&dir);
-        let storage = Storage::init(&storage_config).unwrap();
+    /// the store of the blockchain, opened on first use
+    ///
+    /// panics if the store cannot be opened, see `open_storage` to
+    /// handle the error. The blockchains loaded by the commands (see
+    /// `commands::load`) have their store opened already.
+    pub fn storage(&self) -> &S {
+        match self.open_storage() {
+            Ok(storage) => storage,
+            Err(err) => panic!("cannot open the storage of blockchain `{}': {}", self.name, err),
+        }
+    }
+
+    /// save the blockchain settings (see `write_config`)
+    pub fn save(&self) -> Result<()> {
+        write_config(&self.config, &self.protocols, self.checkpoint.as_ref(), &self.storage_config.get_config_file())
+    }
 
-        let file = storage_config.get_config_file();
-        let config = Config::from_file(file).unwrap();
+    /// check the block of the given hash is in the local storage
+    ///
+    /// the bloom filter (if any, see `Blockchain::rebuild_index`) is
+    /// queried first so the storage is only looked up for possible matches.
+    pub fn block_exists(&self, hash: &block::HeaderHash) -> bool {
+        self.bloom.might_contain(hash) && self.storage().block_exists(hash)
+    }
 
-        Blockchain {
-            name,
-            dir,
-            storage_config,
-            storage,
-            config
-        }
+    /// read the block of the given hash from the local storage
+    pub fn get_block(&self, hash: &block::HeaderHash) -> Option<block::RawBlock> {
+        if ! self.bloom.might_contain(hash) { return None; }
+        self.storage().read_block(hash)
     }
 
-    /// save the blockchain settings
-    pub fn save(&self) {
-        self.config.to_file(self.storage_config.get_config_file());
+    /// reload the blockchain settings from the configuration file
+    ///
+    /// The configuration on disk wins over the one in memory: the peers
+    /// that are no longer in the file have their remote tag removed and
+    /// the new peers are added with a remote tag pointing to the genesis.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let ConfigFile { config, protocols, checkpoint } = read_config(&self.storage_config.get_config_file())?;
+        check_peers_uniqueness(&config)?;
+
+        for np in self.config.peers.iter() {
+            if ! config.peers.iter().any(|new_np| new_np.name() == np.name()) {
+                self.storage().remove_tag(&self.mk_remote_tag(np.name()));
+            }
+        }
+        for np in config.peers.iter() {
+            let tag = self.mk_remote_tag(np.name());
+            if self.storage().read_tag(&tag).is_none() {
+                self.storage().write_tag(&tag, &config.genesis);
+            }
+        }
+
+        self.config = config;
+        self.protocols = protocols;
+        self.checkpoint = checkpoint;
+        Ok(())
     }
 
-    /// add a peer to the blockchain
-    pub fn add_peer(&mut self, remote_alias: String, remote_endpoint: String) {
+    /// add a peer of the given protocol to the blockchain
+    pub fn add_peer(&mut self, remote_alias: String, remote_endpoint: String, protocol: Protocol) {
         let tag = self.mk_remote_tag(&remote_alias);
 
         let peer = Peer::new(remote_endpoint);
-        self.config.peers.push(remote_alias, peer);
+        self.config.peers.push(remote_alias.clone(), peer);
+        self.protocols.insert(remote_alias, protocol);
 
-        tag::write_hash(&self.storage, &tag, &self.config.genesis)
+        self.storage().write_tag(&tag, &self.config.genesis)
     }
 
     pub fn mk_remote_tag(&self, remote: &str) -> String {
         format!("remote/{}", remote)
     }
 
-    pub fn load_remote_tips(&self) -> Vec<(BlockRef, bool)> {
-        self.peers().map(|np| {
-            let peer = peer::Peer::prepare(self, np.name().to_owned());
-            peer.load_local_tip()
-        }).collect()
-    }
-
     /// remove a peer from the blockchain
     pub fn remove_peer(&mut self, remote_alias: String) {
         self.config.peers = self.config.peers.iter().filter(|np| np.name() != remote_alias).cloned().collect();
+        self.protocols.remove(&remote_alias);
         let tag = self.mk_remote_tag(&remote_alias);
-        tag::remove_tag(&self.storage, &tag);
+        self.storage().remove_tag(&tag);
+        if let Err(err) = self.remove_peer_stats(&remote_alias) {
+            warn!("cannot remove the metrics of {}: {}", remote_alias, err);
+        }
     }
 
     pub fn peers<'a>(&'a self)
@@ -114,38 +462,370 @@ impl Blockchain {
         self.config.peers.iter()
     }
 
-    pub fn load_tip(&self) -> (BlockRef, bool) {
-        let genesis_ref = (BlockRef {
+    /// the genesis of the blockchain, as set in the configuration
+    pub fn genesis(&self) -> GenesisInfo {
+        GenesisInfo {
             hash: self.config.genesis.clone(),
-            parent: self.config.genesis_prev.clone(),
-            date: block::BlockDate::Genesis(self.config.epoch_start)
-        }, true);
-        match self.storage.get_block_from_tag(LOCAL_BLOCKCHAIN_TIP_TAG) {
-            Err(storage::Error::NoSuchTag) => genesis_ref,
-            Err(err) => panic!(err),
-            Ok(block) => {
+            prev: self.config.genesis_prev.clone(),
+            epoch_start: self.config.epoch_start,
+        }
+    }
+
+    /// the genesis block, in the same form as the tips (see `load_tip`)
+    pub fn genesis_ref(&self) -> (BlockRef, bool) {
+        (self.genesis().block_ref(), true)
+    }
+
+    /// the time parameters of the network, from its genesis data
+    ///
+    /// fails with `Error::GenesisDataNotFound` if the genesis data of
+    /// the network is not known (custom networks).
+    pub fn time_parameters(&self) -> Result<parse_genesis_data::TimeParameters> {
+        let genesis_prev = &self.config.genesis_prev;
+        match genesis_data::get_genesis_data(genesis_prev) {
+            None => Err(Error::GenesisDataNotFound(genesis_prev.clone())),
+            Some(json) => parse_genesis_data::parse_time_parameters(json).map_err(Error::InvalidGenesisData),
+        }
+    }
+
+    /// the duration of a slot
+    pub fn slot_duration(&self) -> Result<Duration> {
+        Ok(self.time_parameters()?.slot_duration)
+    }
+
+    /// the number of slots in an epoch (ten times the stability depth `k`)
+    pub fn slots_per_epoch(&self) -> u64 {
+        self.config.epoch_stability_depth as u64 * 10
+    }
+
+    /// the start of the first slot of the network
+    pub fn system_start(&self) -> Result<SystemTime> {
+        Ok(self.time_parameters()?.system_start)
+    }
+
+    /// the slot of the given date counted from the first slot of the
+    /// network, rather than from the start of its epoch (as the `slotid`
+    /// of the date). The epoch boundary blocks are at the first slot of
+    /// their epoch.
+    pub fn flat_slot(&self, date: &block::BlockDate) -> u64 {
+        match date {
+            block::BlockDate::Genesis(epoch) => epoch * self.slots_per_epoch(),
+            block::BlockDate::Normal(d) => d.epoch * self.slots_per_epoch() + d.slotid as u64,
+        }
+    }
+
+    /// the time the slot of the given date started
+    pub fn slot_start(&self, date: &block::BlockDate) -> Result<SystemTime> {
+        let slots = self.flat_slot(date);
+        Ok(self.system_start()? + self.slot_duration()? * slots as u32)
+    }
+
+    /// load the block the given tag points to (or the genesis if the
+    /// tag does not exist). The bool is to note if the block is the genesis.
+    ///
+    /// fails with `Error::CorruptTag` if the block of the tag is missing
+    /// or cannot be decoded (see `BlockStore::get_block_from_tag`).
+    pub fn load_tip_from_tag(&self, tag: &str) -> Result<(BlockRef, bool)> {
+        let genesis_ref = self.genesis_ref();
+        let block = self.open_storage()?.get_block_from_tag(tag)?;
+        match block {
+            None => Ok(genesis_ref),
+            Some(block) => {
                 let header = block.get_header();
                 let hash = header.compute_hash();
                 let is_genesis = hash == genesis_ref.0.hash;
-                (BlockRef {
+                Ok((BlockRef {
                     hash: hash,
                     parent: header.get_previous_header(),
                     date: header.get_blockdate()
-                }, is_genesis)
+                }, is_genesis))
+            }
+        }
+    }
+
+    /// load the tips of every remote, as known locally
+    ///
+    /// This only reads the remotes' tags (`remote/<alias>`) from the local
+    /// storage, no network connection is made. The tags are updated when
+    /// fetching blocks from the remotes (see `peer::ConnectedPeer::sync`).
+    ///
+    /// Every remote is returned along with its alias, a remote whose
+    /// tip cannot be loaded comes with the error instead: a corrupt tag,
+    /// reading it again would not help.
+    pub fn load_remote_tips(&self) -> Vec<(String, Result<(BlockRef, bool)>)> {
+        self.peers().map(|np| {
+            let tag = self.mk_remote_tag(np.name());
+            (np.name().to_owned(), self.load_tip_from_tag(&tag))
+        }).collect()
+    }
+
+    /// the latest of the local tip and of the remotes' tips, along with
+    /// the remotes whose tip cannot be loaded (see `load_remote_tips`)
+    pub fn best_tip(&self) -> Result<(BlockRef, Vec<(String, Error)>)> {
+        let mut best = self.load_tip()?.0;
+        let mut errors = Vec::new();
+        for (name, tip) in self.load_remote_tips() {
+            match tip {
+                Ok((tip, _is_genesis)) => if tip.date > best.date { best = tip; },
+                Err(err) => errors.push((name, err)),
             }
         }
+        Ok((best, errors))
+    }
+
+    /// load the local tip, fails with `Error::CorruptTag` if the tag
+    /// does not point to a block of the storage
+    pub fn load_tip(&self) -> Result<(BlockRef, bool)> {
+        self.load_tip_from_tag(LOCAL_BLOCKCHAIN_TIP_TAG)
     }
     pub fn save_tip(&self, hh: &block::HeaderHash) {
-        tag::write_hash(&self.storage, &LOCAL_BLOCKCHAIN_TIP_TAG, hh);
+        self.storage().write_tag(LOCAL_BLOCKCHAIN_TIP_TAG, hh);
     }
+}
 
-    pub fn iter<'a>(&'a self, from: block::HeaderHash, to: block::HeaderHash) -> iter::Result<iter::Iter<'a>> {
-        iter::Iter::new(&self.storage, from, to)
+/// the content of the configuration file: the configuration of
+/// `exe_common` and, next to its fields, the protocol of the remotes
+#[derive(Serialize, Deserialize)]
+struct ConfigFile {
+    #[serde(flatten)]
+    config: Config,
+
+    /// absent from the configurations written before the protocols were
+    /// declared, see `Blockchain::protocol`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    protocols: BTreeMap<String, Protocol>,
+
+    /// only for the blockchains created with `--checkpoint`, see
+    /// `Blockchain::new_from_checkpoint`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checkpoint: Option<checkpoint::Checkpoint>,
+}
+
+/// write the configuration in the given file, see `write_atomically`
+fn write_config( config: &Config
+               , protocols: &BTreeMap<String, Protocol>
+               , checkpoint: Option<&checkpoint::Checkpoint>
+               , file: &Path
+               ) -> Result<()>
+{
+    let content = ConfigFile { config: config.clone(), protocols: protocols.clone(), checkpoint: checkpoint.cloned() };
+    write_atomically(file, |writer| {
+        ::serde_yaml::to_writer(writer, &content)
+            .map_err(|err| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, err))
+    })
+}
+
+/// write the given file with `write`
+///
+/// the content is written in `<file>.tmp` first and synced to the disk,
+/// then renamed into place and the directory is synced: a crash (or a
+/// failure of `write`) leaves the previous file untouched, and a
+/// configuration replaced is not lost on a power failure.
+fn write_atomically<F>(file: &Path, write: F) -> Result<()>
+    where F: FnOnce(&mut ::std::fs::File) -> ::std::io::Result<()>
+{
+    let mut tmp = file.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let mut writer = ::std::fs::File::create(&tmp)?;
+    write(&mut writer)?;
+    writer.sync_all()?;
+    ::std::fs::rename(&tmp, file)?;
+    sync_parent_dir(file)?;
+    Ok(())
+}
+
+/// make the entries of the file's directory (a rename) durable
+#[cfg(unix)]
+fn sync_parent_dir(file: &Path) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        ::std::fs::File::open(dir)?.sync_all()?;
     }
+    Ok(())
+}
+/// the directories cannot be opened, nor synced, on the other systems
+#[cfg(not(unix))]
+fn sync_parent_dir(_file: &Path) -> Result<()> { Ok(()) }
+
+/// read the configuration from the given file
+fn read_config(file: &Path) -> Result<ConfigFile> {
+    if ! file.is_file() {
+        return Err(Error::ConfigNotFound(file.to_path_buf()));
+    }
+    let reader = ::std::fs::File::open(file)?;
+    ::serde_yaml::from_reader(reader).map_err(|err| Error::InvalidConfig(file.to_path_buf(), err))
+}
+
+/// check the blockchain directory is of a supported layout (see
+/// `STORAGE_VERSION`)
+fn check_storage_version(dir: &Path) -> Result<()> {
+    let file = dir.join(STORAGE_VERSION_FILE);
+    if ! file.is_file() { return Ok(()); }
 
-    pub fn iter_to_tip<'a>(&'a self, from: block::HeaderHash) -> iter::Result<iter::Iter<'a>> {
-        let to   = self.load_tip().0.hash;
+    let version = ::std::fs::read_to_string(file)?;
+    match version.trim().parse::<u32>() {
+        Ok(version) if version <= STORAGE_VERSION => Ok(()),
+        _ => Err(Error::IncompatibleStorageVersion(version.trim().to_owned())),
+    }
+}
+
+/// check that every peer alias of the configuration is unique
+///
+/// the alias is used to name the remote's tag (see `Blockchain::mk_remote_tag`)
+fn check_peers_uniqueness(config: &Config) -> Result<()> {
+    let mut aliases = ::std::collections::BTreeSet::new();
+    for np in config.peers.iter() {
+        if ! aliases.insert(np.name()) {
+            return Err(Error::DuplicatePeer(np.name().to_owned()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn duplicate_peer_alias() {
+        let mut config = Config::mainnet();
+        config.peers.push("relay".to_owned(), Peer::new("relay1.cardano.local:3000".to_owned()));
+        config.peers.push("relay".to_owned(), Peer::new("relay2.cardano.local:3000".to_owned()));
+
+        match check_peers_uniqueness(&config) {
+            Err(Error::DuplicatePeer(alias)) => assert_eq!(alias, "relay"),
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(()) => panic!("duplicate peer alias not detected"),
+        }
+    }
+
+    #[test]
+    fn remote_tips_errors_are_per_peer() {
+        let mut config = Config::mainnet();
+        config.peers.push("reachable".to_owned(), Peer::new("relay1.cardano.local:3000".to_owned()));
+        config.peers.push("dead".to_owned(), Peer::new("relay2.cardano.local:3000".to_owned()));
+        let blockchain = Blockchain::with_store(PathBuf::from("memory"), "test".to_owned(), config, store::MemoryStore::new()).unwrap();
+
+        // the dead peer points to a block that is not in the store
+        let unknown = block::HeaderHash::try_from_slice(&[0u8;32]).unwrap();
+        blockchain.storage().write_tag(&blockchain.mk_remote_tag("dead"), &unknown);
+        // the reachable peer has no tag, its tip is the genesis
+        blockchain.storage().remove_tag(&blockchain.mk_remote_tag("reachable"));
+
+        let tips = blockchain.load_remote_tips();
+        assert_eq!(tips.len(), blockchain.peers().count());
+        for (name, tip) in tips.into_iter().filter(|(name, _)| name == "reachable" || name == "dead") {
+            match (name.as_str(), tip) {
+                ("reachable", Ok((tip, is_genesis))) => {
+                    assert!(is_genesis);
+                    assert_eq!(tip.hash, blockchain.config.genesis);
+                },
+                ("dead", Err(Error::CorruptTag(tag, cause))) => {
+                    assert_eq!(tag, blockchain.mk_remote_tag("dead"));
+                    match *cause {
+                        Error::BlockNotFound(hash) => assert_eq!(hash, unknown),
+                        cause => panic!("unexpected cause: {:?}", cause),
+                    }
+                },
+                (name, tip) => panic!("unexpected tip for {}: {:?}", name, tip.map(|t| t.0.hash)),
+            }
+        }
+    }
+
+    #[test]
+    fn interrupted_config_write() {
+        let dir = ::std::env::temp_dir().join(format!("cardano-cli-config-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.yml");
+
+        let config = Config::mainnet();
+        write_config(&config, &BTreeMap::new(), None, &file).unwrap();
+
+        // the next write is interrupted half way: a truncated temporary
+        // file is left behind, the config itself is still intact
+        let interrupted = write_atomically(&file, |writer| {
+            writer.write_all(b"genesis: ")?;
+            Err(::std::io::Error::new(::std::io::ErrorKind::Interrupted, "crash"))
+        });
+        assert!(interrupted.is_err());
+        assert!(dir.join("config.yml.tmp").exists());
+        let loaded = read_config(&file).unwrap();
+        assert_eq!(loaded.config.genesis, config.genesis);
+
+        // and the next write goes through
+        write_config(&config, &BTreeMap::new(), None, &file).unwrap();
+        assert!(! dir.join("config.yml.tmp").exists());
+
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_config() {
+        let dir = ::std::env::temp_dir().join(format!("cardano-cli-truncated-config-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.yml");
+
+        ::std::fs::write(&file, b"genesis: ").unwrap();
+        match read_config(&file) {
+            Err(Error::InvalidConfig(path, _)) => assert_eq!(path, file),
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(_) => panic!("truncated config not detected"),
+        }
+
+        ::std::fs::write(dir.join(STORAGE_VERSION_FILE), format!("{}\n", STORAGE_VERSION + 1)).unwrap();
+        match check_storage_version(&dir) {
+            Err(Error::IncompatibleStorageVersion(version)) => assert_eq!(version, format!("{}", STORAGE_VERSION + 1)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mainnet_time_parameters() {
+        let blockchain = Blockchain::with_store(PathBuf::from("memory"), "test".to_owned(), Config::mainnet(), store::MemoryStore::new()).unwrap();
+
+        assert_eq!(blockchain.slot_duration().unwrap(), Duration::from_secs(20));
+        assert_eq!(blockchain.slots_per_epoch(), 21600);
+        assert_eq!(blockchain.system_start().unwrap(), ::std::time::UNIX_EPOCH + Duration::from_secs(1506203091));
+    }
+
+    /// `pull` then forwards the local tip through the blockchain it has
+    /// loaded: loading it again would wait for its own lock
+    #[test]
+    fn pull_then_forward_in_one_process() {
+        let root_dir = ::std::env::temp_dir().join(format!("cardano-cli-forward-{}", ::std::process::id()));
+        let name = "forward".to_owned();
+        let blockchain = Blockchain::new(root_dir.clone(), name.clone(), Config::mainnet()).unwrap();
+
+        // the tips are at the genesis, which is not stored
+        blockchain.storage().remove_tag(LOCAL_BLOCKCHAIN_TIP_TAG);
+        for np in blockchain.peers() {
+            blockchain.storage().remove_tag(&blockchain.mk_remote_tag(np.name()));
+        }
+
+        match Blockchain::load_with(root_dir.clone(), name.clone(), lock::Mode::Exclusive, Duration::from_secs(0)) {
+            Err(Error::Locked(_)) => {},
+            Err(err) => panic!("unexpected error: {:?}", err),
+            Ok(_) => panic!("the blockchain is loaded twice"),
+        }
+
+        let (tip, errors) = blockchain.best_tip().unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(tip.hash, blockchain.config.genesis);
+        blockchain.save_tip(&tip.hash);
+
+        drop(blockchain);
+        ::std::fs::remove_dir_all(&root_dir).unwrap();
+    }
 
-        self.iter(from, to)
+    #[test]
+    fn preset_peers_are_unique() {
+        assert!(check_peers_uniqueness(&Config::mainnet()).is_ok());
+        assert!(check_peers_uniqueness(&Config::testnet()).is_ok());
+        assert!(check_peers_uniqueness(&Config::staging()).is_ok());
     }
 }
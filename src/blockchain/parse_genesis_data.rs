@@ -1,5 +1,7 @@
 use std::collections::{HashMap, BTreeMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde_json;
+use serde::de::Error as DeError;
 use cardano::{config, fee, block, coin, redeem};
 use base64;
 
@@ -31,6 +33,41 @@ struct TxFeePolicy {
     multiplier: String,
 }
 
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+struct RawTimeParameters {
+    startTime: u64,
+    blockVersionData: RawSlotDuration,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+struct RawSlotDuration {
+    slotDuration: String,
+}
+
+/// the time parameters of the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeParameters {
+    /// the start of the first slot of the network
+    pub system_start: SystemTime,
+    /// the duration of a slot (the genesis data has it in milliseconds)
+    pub slot_duration: Duration,
+}
+
+pub fn parse_time_parameters(json: &str) -> serde_json::Result<TimeParameters> {
+    let data: RawTimeParameters = serde_json::from_str(&json)?;
+
+    let slot_duration = data.blockVersionData.slotDuration.parse::<u64>().map_err(|err| {
+        serde_json::Error::custom(format!("invalid slotDuration: {}", err))
+    })?;
+
+    Ok(TimeParameters {
+        system_start: UNIX_EPOCH + Duration::from_secs(data.startTime),
+        slot_duration: Duration::from_millis(slot_duration),
+    })
+}
+
 pub fn parse_genesis_data(json: &str) -> config::GenesisData { // FIXME: use Result
 
     let data: RawGenesisData = serde_json::from_str(&json).unwrap();
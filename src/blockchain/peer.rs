@@ -1,17 +1,238 @@
 
 use exe_common;
-use exe_common::network::{api::Api, api::BlockRef};
-use cardano::{block::{BlockDate, EpochId, HeaderHash}, tx::{TxAux}};
-use utils::term::Term;
+use exe_common::network::api::BlockRef;
+use cardano::{block::{BlockDate, EpochId, HeaderHash, RawBlock}, tx::{TxAux}};
+use utils::{term::Term, cancel::CancelToken, logging};
+use log::Level;
 use cardano_storage::{self as storage, tag};
 use storage_units::packfile;
 use std::ops::Deref;
-use std::time::SystemTime;
-use std::mem;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use std::{mem, thread, sync::mpsc};
+
+use super::progress::{PartialEpoch, SyncEvent, SyncObserver, SyncProgress, TermProgress};
+use super::protocol::{self, Backend, Connection, Protocol};
+use super::RetryPolicy;
+
+/// limits of a synchronisation, `None` for no limit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncBudget {
+    /// the maximum number of blocks to download
+    pub max_blocks: Option<u64>,
+    /// the maximum duration of the download
+    pub max_duration: Option<Duration>,
+}
+impl SyncBudget {
+    fn is_exhausted(&self, nr_blocks: u64, started: &Instant) -> bool {
+        self.max_blocks.map(|max| nr_blocks >= max).unwrap_or(false)
+            || self.max_duration.map(|max| started.elapsed() >= max).unwrap_or(false)
+    }
+}
+
+/// the outcome of a synchronisation
+#[derive(Debug, Clone)]
+pub struct SyncReport {
+    /// the number of blocks downloaded
+    pub nr_blocks: u64,
+    /// the tip of the peer, as stored locally, at the end of the sync
+    pub tip: HeaderHash,
+    /// the budget was exhausted before reaching the remote tip. Syncing
+    /// again continues from `tip`.
+    pub incomplete: bool,
+}
+
+/// settings of the synchronisation with a remote peer
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    /// the number of downloaded blocks that may be waiting to be written
+    /// to the storage. The download carries on while the blocks are
+    /// written, up to this many blocks ahead.
+    pub window: usize,
+
+    /// the number of loose blocks (the blocks of the unstable epochs, and
+    /// the copies of the blocks of the epoch being packed) to write before
+    /// moving the tip of the peer. After a crash, the blocks after the tip
+    /// are downloaded again.
+    pub batch_size: usize,
+
+    /// stop the synchronisation once cancelled, the blocks written so
+    /// far are kept and the tip is set to the last consistent block.
+    pub cancel: CancelToken,
+
+    /// stop the synchronisation once the budget is exhausted, the tip is
+    /// set to the last consistent block (see `SyncReport::incomplete`).
+    pub budget: SyncBudget,
+}
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            window: 2048,
+            batch_size: 256,
+            cancel: CancelToken::new(),
+            budget: SyncBudget::default(),
+        }
+    }
+}
+
+/// the time given to a peer to accept the connection and to send its
+/// tip (see `probe_peers`)
+pub const DEFAULT_TIP_PROBE_TIMEOUT : Duration = Duration::from_secs(30);
+
+/// query the tips of the given peers (see `ConnectedPeer::query_tip`), in
+/// the order of `names`
+///
+/// the peers are queried all at once, each from its own thread. The tip
+/// of a peer which has not answered once `timeout` is elapsed is an
+/// `Error::Timeout` (its thread is left to finish on its own).
+pub fn query_remote_tips(blockchain: &super::Blockchain, names: &[String], timeout: Duration) -> Vec<(String, super::Result<BlockRef>)> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = mpsc::channel();
+    let mut tips : Vec<(String, Option<super::Result<BlockRef>>)> = Vec::new();
+    for (index, name) in names.iter().enumerate() {
+        match blockchain.peers().find(|np| np.name() == name) {
+            None => tips.push((name.clone(), Some(Err(super::Error::UnknownPeer(name.clone()))))),
+            Some(np) => {
+                tips.push((name.clone(), None));
+                let protocol = blockchain.protocol(name);
+                let target = protocol::Target::new(blockchain, name, np.peer());
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let tip = protocol::backend(protocol).connect(&target)
+                        .and_then(|mut connection| connection.get_tip())
+                        .map(|tip_header| BlockRef {
+                            hash: tip_header.compute_hash(),
+                            parent: tip_header.get_previous_header(),
+                            date: tip_header.get_blockdate()
+                        });
+                    let _ = sender.send((index, tip));
+                });
+            },
+        }
+    }
+
+    let mut nr_pending = tips.iter().filter(|(_, tip)| tip.is_none()).count();
+    while nr_pending > 0 {
+        let now = Instant::now();
+        if now >= deadline { break; }
+        match receiver.recv_timeout(deadline - now) {
+            Ok((index, tip)) => {
+                tips[index].1 = Some(tip.map_err(super::Error::from));
+                nr_pending -= 1;
+            },
+            Err(_) => break,
+        }
+    }
+
+    tips.into_iter().map(|(name, tip)| {
+        (name, tip.unwrap_or_else(|| Err(super::Error::Timeout(timeout))))
+    }).collect()
+}
+
+/// the tip of a reachable peer, as probed by `probe_peers`
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub name: String,
+    pub protocol: Protocol,
+    pub tip: BlockRef,
+    /// the time taken to connect and to probe the tip
+    pub latency: Duration,
+}
+
+/// connect to the given peers and probe their tips (see
+/// `ConnectedPeer::probe_tip`), the connections are closed afterward.
+///
+/// the peers are probed all at once, each from its own thread. The peers
+/// which have not sent their tip once `timeout` is elapsed are skipped
+/// (their thread is left to finish on its own).
+///
+/// the reachable peers are returned, the best first: the native peers
+/// before the HTTP bridges (see `protocol::Protocol`), then the highest
+/// tip, then the lowest latency.
+pub fn probe_peers(blockchain: &super::Blockchain, term: &mut Term, names: &[String], timeout: Duration) -> Vec<Probe> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = mpsc::channel();
+    let mut pending = Vec::new();
+    for name in names {
+        let config = match blockchain.peers().find(|np| np.name() == name) {
+            None => {
+                term.warn(&format!("skipping peer {}: {}\n", name, super::Error::UnknownPeer(name.clone()))).unwrap();
+                continue;
+            },
+            Some(np) => np.peer().clone(),
+        };
+        let protocol = blockchain.protocol(name);
+        let target = protocol::Target::new(blockchain, name, &config);
+        let sender = sender.clone();
+        let name = name.clone();
+        pending.push(name.clone());
+        thread::spawn(move || {
+            let started = Instant::now();
+            let tip = protocol::backend(protocol).connect(&target)
+                .map_err(super::Error::from)
+                .and_then(|mut connection| fetch_tip(&mut *connection))
+                .map_err(|err| format!("{}", err));
+            let _ = sender.send((name, protocol, tip, started.elapsed()));
+        });
+    }
+
+    let mut probes = Vec::new();
+    while ! pending.is_empty() {
+        let now = Instant::now();
+        if now >= deadline { break; }
+        let (name, protocol, tip, latency) = match receiver.recv_timeout(deadline - now) {
+            Ok(probe) => probe,
+            Err(_) => break,
+        };
+        pending.retain(|pending| pending != &name);
+        match tip {
+            Err(err) => {
+                term.warn(&format!("skipping peer {}, its tip cannot be fetched: {}\n", name, err)).unwrap();
+                logging::event(Level::Warn, "peer.failure", &[("blockchain", &blockchain.name), ("peer", &name), ("error", &err)]);
+                blockchain.update_peer_stats(&name, |stats| stats.record_failure());
+            },
+            Ok(tip) => {
+                blockchain.update_peer_stats(&name, |stats| stats.latency = Some(latency));
+                probes.push(Probe { name, protocol, tip, latency });
+            },
+        }
+    }
+    for name in pending {
+        term.warn(&format!("skipping peer {}, its tip has not been received within {} seconds\n", name, timeout.as_secs())).unwrap();
+        blockchain.update_peer_stats(&name, |stats| stats.record_failure());
+    }
+
+    probes.sort_by(|a, b| {
+        a.protocol.cmp(&b.protocol)
+            .then(b.tip.date.partial_cmp(&a.tip.date).unwrap_or(::std::cmp::Ordering::Equal))
+            .then(a.latency.cmp(&b.latency))
+    });
+    probes
+}
+
+/// query the tip of the connection and check the remote can actually
+/// serve it: the hash of the tip block it sends needs to be the hash of
+/// the advertised tip header
+fn fetch_tip(connection: &mut Connection) -> super::Result<BlockRef> {
+    let tip_header = connection.get_tip()?;
+    let hash = tip_header.compute_hash();
+
+    let block = connection.get_block(&hash)?.decode()?;
+    let found = block.get_header().compute_hash();
+    if found != hash {
+        return Err(super::Error::InvalidRemoteTip { advertised: hash, found });
+    }
+
+    Ok(BlockRef {
+        hash,
+        parent: tip_header.get_previous_header(),
+        date: tip_header.get_blockdate()
+    })
+}
 
 pub struct ConnectedPeer<'a> {
     peer: Peer<'a>,
-    connection: exe_common::network::Peer
+    connection: Box<Connection>
 }
 impl<'a> Deref for ConnectedPeer<'a> {
     type Target = Peer<'a>;
@@ -19,13 +240,117 @@ impl<'a> Deref for ConnectedPeer<'a> {
 }
 impl<'a> ConnectedPeer<'a> {
     /// get the remote tip
-    pub fn query_tip(&mut self) -> BlockRef {
-        let tip_header = self.connection.get_tip().unwrap();
-        BlockRef {
+    pub fn query_tip(&mut self) -> super::Result<BlockRef> {
+        let tip_header = self.connection.get_tip()?;
+        Ok(BlockRef {
             hash: tip_header.compute_hash(),
             parent: tip_header.get_previous_header(),
             date: tip_header.get_blockdate()
+        })
+    }
+
+    /// query the remote tip and check the peer can actually serve it
+    ///
+    /// the tip block is downloaded and decoded, its hash needs to be the
+    /// hash of the advertised tip header.
+    ///
+    /// the time taken to query the tip is recorded as the peer's latency,
+    /// a failure is recorded in the peer's metrics (see `peer_stats`).
+    /// The network failures are retried following the default
+    /// `RetryPolicy`.
+    pub fn probe_tip(&mut self) -> super::Result<BlockRef> {
+        self.probe_tip_with(RetryPolicy::default())
+    }
+
+    /// same as `probe_tip` with the given `RetryPolicy`
+    ///
+    /// only the network failures are retried, a momentary failure is not
+    /// recorded: a peer delivering an invalid tip fails at once.
+    pub fn probe_tip_with(&mut self, retry: RetryPolicy) -> super::Result<BlockRef> {
+        let mut attempt = 1;
+        let mut result = self.probe_tip_unrecorded();
+        while attempt < retry.attempts {
+            match result {
+                Err(super::Error::NetworkError(ref err)) => {
+                    debug!("cannot fetch the tip of remote {} (attempt {}): {}", self.name, attempt, err);
+                },
+                _ => break,
+            }
+            thread::sleep(retry.delay);
+            attempt += 1;
+            result = self.probe_tip_unrecorded();
         }
+        if let Err(ref err) = result {
+            logging::event(Level::Warn, "peer.failure", &[("blockchain", &self.blockchain.name), ("peer", &self.name), ("error", err)]);
+            self.blockchain.update_peer_stats(&self.name, |stats| stats.record_failure());
+        }
+        result
+    }
+
+    fn probe_tip_unrecorded(&mut self) -> super::Result<BlockRef> {
+        let started = Instant::now();
+        let tip = fetch_tip(&mut *self.connection)?;
+        let latency = started.elapsed();
+        self.blockchain.update_peer_stats(&self.name, |stats| stats.latency = Some(latency));
+        Ok(tip)
+    }
+
+    /// look for a fork between the remote chain and the local chain
+    ///
+    /// the remote chain is walked back from its tip, up to the stability
+    /// depth, to the first block of the local chain. This block is
+    /// returned if the remote chain forked from the local chain after it
+    /// (see `Blockchain::rollback_to`). `None` if the remote tip is on
+    /// the local chain, if the remote chain extends the local chain, or
+    /// if no common block is found within the stability depth.
+    pub fn find_fork(&mut self) -> super::Result<Option<BlockRef>> {
+        let blockchain = self.peer.blockchain;
+        let (local_tip, _is_genesis) = blockchain.load_tip()?;
+
+        let mut header = self.connection.get_tip()?;
+        for depth in 0..=blockchain.config.epoch_stability_depth {
+            let hash = header.compute_hash();
+            if blockchain.is_ancestor(&hash)? {
+                if depth == 0 || hash == local_tip.hash { return Ok(None); }
+                return Ok(Some(BlockRef {
+                    hash,
+                    parent: header.get_previous_header(),
+                    date: header.get_blockdate()
+                }));
+            }
+            let parent = header.get_previous_header();
+            header = self.connection.get_block(&parent)?.decode()?.get_header();
+        }
+        Ok(None)
+    }
+
+    /// download the blocks from `from` to `to` and write them as loose
+    /// blocks, the local tips are not modified.
+    ///
+    /// `inclusive` is whether `from` is downloaded too. The number of
+    /// blocks written is returned. The blocks written are added to the
+    /// bloom filter (if any), like in `sync_with`.
+    pub fn fetch_range(&mut self, from: &BlockRef, inclusive: bool, to: &BlockRef) -> super::Result<usize> {
+        let blockchain = self.peer.blockchain;
+        let storage = blockchain.open_storage()?;
+        let mut index = blockchain.suspend_index()?;
+        let mut nr_blocks = 0;
+        let mut result = Ok(());
+        let fetched = self.connection.get_blocks(from, inclusive, to, &mut |block_hash, _block, block_raw| {
+            if result.is_err() { return; }
+            let hash = storage::types::header_to_blockhash(&block_hash);
+            result = storage::blob::write(storage, &hash, block_raw.as_ref());
+            if result.is_ok() {
+                if let Some(ref mut index) = index { index.insert(&block_hash); }
+                nr_blocks += 1;
+            }
+        });
+        if let Some(index) = index {
+            blockchain.resume_index(index)?;
+        }
+        fetched?;
+        result?;
+        Ok(nr_blocks)
     }
 
     pub fn send_txaux(mut self, txaux: TxAux) -> bool {
@@ -38,15 +363,57 @@ impl<'a> ConnectedPeer<'a> {
         }
     }
 
-    pub fn sync(mut self, term: &mut Term) -> Peer<'a> {
+    pub fn sync(self, term: &mut Term) -> super::Result<Peer<'a>> {
+        self.sync_with(term, &SyncConfig::default()).map(|(peer, _)| peer)
+    }
+
+    /// fetch the blocks from the remote tip
+    ///
+    /// fails with `Error::NetworkError` if the peer fails during the
+    /// download, the local tip of the peer is then set to the last
+    /// consistent block. Fails with `Error::Cancelled` if the `cancel` token of the config
+    /// is cancelled. The download from the peer is then aborted (see
+    /// `Connection::get_blocks_while`), the same goes once the `budget` is
+    /// exhausted.
+    ///
+    /// The blocks of a stable epoch are packed once the whole epoch is
+    /// downloaded, they are also written as loose blocks by batches of
+    /// `batch_size` so the next sync resumes from the last batch (see
+    /// `progress::PartialEpoch`).
+    ///
+    /// The progress is rendered as a progress bar, see
+    /// `sync_with_progress` to follow it otherwise.
+    pub fn sync_with(self, term: &mut Term, sync_config: &SyncConfig) -> super::Result<(Peer<'a>, SyncReport)> {
+        self.sync_with_progress(term, sync_config, &mut TermProgress::new())
+    }
+
+    /// fetch the blocks from the remote tip (see `sync_with`), the
+    /// `observer` is notified of the progress of the download
+    pub fn sync_with_progress( mut self
+                             , term: &mut Term
+                             , sync_config: &SyncConfig
+                             , observer: &mut SyncObserver
+                             ) -> super::Result<(Peer<'a>, SyncReport)>
+    {
         // recover and print the TIP of the network
-        let tip = self.query_tip();
+        let tip = self.query_tip()?;
 
         // Start fetching at the current HEAD tag, or the genesis block if
-        // it doesn't exist.
-        let our_tip = self.load_local_tip();
+        // it doesn't exist (or cannot be read).
+        let our_tip = self.load_local_tip().unwrap_or_else(|err| {
+            term.warn(&format!("cannot load the local tip of {}, restarting from the genesis ({})\n", self.peer.name, err)).unwrap();
+            self.peer.blockchain.genesis_ref()
+        });
 
-        let mut best_tip = self.peer.blockchain.load_remote_tips().into_iter().fold(our_tip.clone(), |best_tip, current_tip| {
+        let mut best_tip = self.peer.blockchain.load_remote_tips().into_iter().filter_map(|(name, tip)| {
+            match tip {
+                Ok(tip) => Some(tip),
+                Err(err) => {
+                    warn!("ignoring local tip of remote {}: {}", name, err);
+                    None
+                }
+            }
+        }).fold(our_tip.clone(), |best_tip, current_tip| {
             if best_tip.0.date < current_tip.0.date {
                 current_tip
             } else {
@@ -54,13 +421,13 @@ impl<'a> ConnectedPeer<'a> {
             }
         });
 
-        let mut connection = self.connection;
+        let connection = self.connection;
         let peer = self.peer;
 
         if best_tip.0.date < tip.date {
             // do nothing, best_tip is behind the remote tip.
         } else if best_tip.0.date > tip.date {
-            match storage::block_read(&peer.blockchain.storage, &tip.hash) {
+            match storage::block_read(peer.blockchain.storage(), &tip.hash) {
                 None => {
                     // we don't have the block locally... might be a fork, we need to download the
                     // blockchain anyway
@@ -70,14 +437,16 @@ impl<'a> ConnectedPeer<'a> {
                 Some(_) => {
                     term.info("remote already as further as it takes").unwrap();
                     peer.save_peer_local_tip(&tip.hash);
-                    return peer;
+                    peer.record_sync(&tip);
+                    return Ok((peer, SyncReport { nr_blocks: 0, tip: tip.hash, incomplete: false }));
                 }
             }
         } else { // best_tip.0.date == tip.date
             if best_tip.0.hash == tip.hash {
                 // this is the same block hash. save the local tip
                 peer.save_peer_local_tip(&tip.hash);
-                return peer;
+                peer.record_sync(&tip);
+                return Ok((peer, SyncReport { nr_blocks: 0, tip: tip.hash, incomplete: false }));
             } else {
                 // it seems the best_tip is for the same date, but has a different hash
                 // it could be there is a fork between the remotes.
@@ -96,6 +465,17 @@ impl<'a> ConnectedPeer<'a> {
 
         info!("Fetching from        : {} ({})", best_tip.0.hash, best_tip.0.date);
 
+        // the epoch left partially downloaded by the previous sync, if it
+        // stopped where this one starts
+        let partial_file = peer.blockchain.partial_epoch_file(&peer.name);
+        let resumed = match PartialEpoch::load(&partial_file) {
+            Err(err) => {
+                warn!("ignoring the partial epoch of remote {}: {}", peer.name, err);
+                None
+            },
+            Ok(partial) => partial.filter(|partial| partial.last_block == format!("{}", best_tip.0.hash)),
+        };
+
         // Determine whether the previous epoch is stable yet. Note: This
         // assumes that k is smaller than the number of blocks in an
         // epoch.
@@ -108,6 +488,7 @@ impl<'a> ConnectedPeer<'a> {
         info!("First unstable epoch : {}", first_unstable_epoch);
 
         let mut cur_epoch_state : Option<(EpochId, packfile::Writer, SystemTime)> = None;
+        let mut epoch_blocks = 0;
 
         let mut last_block : Option<HeaderHash> = None;
 
@@ -115,22 +496,24 @@ impl<'a> ConnectedPeer<'a> {
         // to pack it. So read the previously fetched blocks in this epoch
         // and prepend them to the incoming blocks.
         if best_tip.0.date.get_epochid() < first_unstable_epoch && (! best_tip.1) // the second item mark if the tip is genesis
-            && !internal::epoch_exists(&peer.blockchain.storage, best_tip.0.date.get_epochid())
+            && !internal::epoch_exists(peer.blockchain.storage(), best_tip.0.date.get_epochid())
         {
             let epoch_id = best_tip.0.date.get_epochid();
-            let mut writer = storage::pack::packwriter_init(&peer.blockchain.storage.config).unwrap();
+            let mut writer = storage::pack::packwriter_init(&peer.blockchain.storage().config).unwrap();
             let epoch_time_start = SystemTime::now();
 
             let prev_block = internal::append_blocks_to_epoch_reverse(
-                &peer.blockchain.storage, epoch_id, &mut writer, &best_tip.0.hash);
+                peer.blockchain.storage(), epoch_id, &mut writer, &best_tip.0.hash);
 
             cur_epoch_state = Some((epoch_id, writer, epoch_time_start));
             last_block = Some(best_tip.0.hash.clone());
+            epoch_blocks = resumed.as_ref().filter(|partial| partial.epoch == epoch_id)
+                .map(|partial| partial.blocks).unwrap_or(0);
 
             // If tip.slotid < w, the previous epoch won't have been
             // created yet either, so do that now.
             if epoch_id > peer.blockchain.config.epoch_start {
-                internal::maybe_create_epoch(&peer.blockchain.storage, epoch_id - 1, &prev_block);
+                internal::maybe_create_epoch(peer.blockchain.storage(), epoch_id - 1, &prev_block);
             }
         }
 
@@ -138,75 +521,274 @@ impl<'a> ConnectedPeer<'a> {
         // pack it.
         else if best_tip.0.date.get_epochid() == first_unstable_epoch
             && first_unstable_epoch > peer.blockchain.config.epoch_start
-            && !internal::epoch_exists(&peer.blockchain.storage, first_unstable_epoch - 1)
+            && !internal::epoch_exists(peer.blockchain.storage(), first_unstable_epoch - 1)
         {
             // Iterate to the last block in the previous epoch.
             let mut cur_hash = best_tip.0.hash.clone();
             loop {
-                let block_raw = storage::block_read(&peer.blockchain.storage, &cur_hash).unwrap();
+                let block_raw = storage::block_read(peer.blockchain.storage(), &cur_hash).unwrap();
                 let block = block_raw.decode().unwrap();
                 let hdr = block.get_header();
                 assert!(hdr.get_blockdate().get_epochid() == first_unstable_epoch);
                 cur_hash = hdr.get_previous_header();
                 if hdr.get_blockdate().is_genesis() { break }
             }
-            internal::maybe_create_epoch(&peer.blockchain.storage, first_unstable_epoch - 1, &cur_hash);
+            internal::maybe_create_epoch(peer.blockchain.storage(), first_unstable_epoch - 1, &cur_hash);
         }
 
 
-        // initialisation of the progress bar:
-        let count = tip.date - best_tip.0.date;
-        let pbr = term.progress_bar(count as u64);
-        connection.get_blocks(&best_tip.0, best_tip.1, &tip, &mut |block_hash, block, block_raw| {
+        // the blocks are written by a dedicated thread (with its own
+        // handle on the storage) so the download is not held back by
+        // the disk.
+        let mut writer = BlockWriter {
+            storage: storage::Storage::init(&peer.blockchain.storage_config)?,
+            tag: peer.tag.clone(),
+            first_unstable_epoch,
+            cur_epoch_state,
+            last_block,
+            committed: None,
+            batch_size: sync_config.batch_size,
+            pending: Vec::new(),
+            epoch_pending: Vec::new(),
+            epoch_blocks,
+            partial_file,
+        };
+
+        // the bloom filter of the stored blocks (if any) is taken out while
+        // writing the blocks and saved back with the new blocks afterward.
+        let mut index = peer.blockchain.suspend_index()?;
+
+        let cancel = sync_config.cancel.clone();
+        let (sender, receiver) = mpsc::sync_channel(sync_config.window);
+        let writer_thread = thread::spawn(move || {
+            for (block_hash, date, block_raw) in receiver {
+                if cancel.is_cancelled() { break; }
+                writer.write(block_hash, date, block_raw);
+            }
+            writer.finish();
+            (writer.last_block, writer.committed)
+        });
+
+        logging::event(Level::Info, "sync.started", &[
+            ("blockchain", &peer.blockchain.name), ("peer", &peer.name),
+            ("from", &best_tip.0.date), ("to", &tip.date),
+        ]);
+        let mut progress = SyncProgress::new(best_tip.0.date.clone(), tip.date.clone());
+        observer.notify(term, &SyncEvent::Started { resumed }, &progress);
+        let started = Instant::now();
+        let mut nr_blocks = 0;
+        let mut exhausted = false;
+        let fetched = connection.get_blocks_while(&best_tip.0, best_tip.1, &tip, &mut |block_hash, block, block_raw| {
+            if sync_config.cancel.is_cancelled() { return false; }
+            if sync_config.budget.is_exhausted(nr_blocks, &started) {
+                exhausted = true;
+                return false;
+            }
+            nr_blocks += 1;
             let date = block.get_header().get_blockdate();
-            pbr.inc(1);
-            pbr.set_message(&format!("downloading epoch {} -> ", date.get_epochid()));
+            if let Some(ref mut index) = index { index.insert(&block_hash); }
+            progress.current = date.clone();
+            progress.nr_blocks = nr_blocks;
+            super::metrics::record_block(block_raw.as_ref().len());
+            observer.notify(term, &SyncEvent::Block, &progress);
+
+            // the writer thread is gone if the sync has been cancelled
+            let _ = sender.send((block_hash.clone(), date, RawBlock(block_raw.as_ref().to_vec())));
+            true
+        });
+        mem::drop(sender);
+        let written = writer_thread.join();
+        observer.notify(term, &SyncEvent::Finished, &progress);
+
+        // the index may now have blocks which have not been written, it
+        // is only a hint of the blocks in the storage (see
+        // `Blockchain::block_exists`)
+        if let Some(index) = index {
+            peer.blockchain.resume_index(index)?;
+        }
 
-            // Flush the previous epoch (if any).
-            if date.is_genesis() {
-                let mut writer_state = None;
-                mem::swap(&mut writer_state, &mut cur_epoch_state);
-                if let Some((epoch_id, writer, epoch_time_start)) = writer_state {
-                    internal::finish_epoch(&peer.blockchain.storage, epoch_id, writer, &epoch_time_start);
-
-                    // Checkpoint the tip so we don't have to refetch
-                    // everything if we get interrupted.
-                    peer.save_peer_local_tip(last_block.as_ref().unwrap());
-                }
+        let (last_block, committed) = match written {
+            Ok(written) => written,
+            Err(_) => {
+                super::metrics::record_sync_failure();
+                return Err(::std::io::Error::new(::std::io::ErrorKind::Other, "the blocks could not be written to the storage").into());
+            },
+        };
+
+        if let Err(err) = fetched {
+            // the peer failed in the middle of the download, keep what
+            // has been stored so another peer can carry on from there.
+            if let Some(block_hash) = committed {
+                peer.save_peer_local_tip(&block_hash);
+            }
+            logging::event(Level::Warn, "sync.failed", &[
+                ("blockchain", &peer.blockchain.name), ("peer", &peer.name),
+                ("blocks", &nr_blocks), ("error", &err),
+            ]);
+            super::metrics::record_sync_failure();
+            peer.blockchain.update_peer_stats(&peer.name, |stats| stats.record_failure());
+            return Err(err.into());
+        }
+
+        if sync_config.cancel.is_cancelled() {
+            // the epoch being packed (if any) is not complete, the tip can
+            // only be moved to the last block known to be stored (the last
+            // batch of the epoch written as loose blocks).
+            if let Some(block_hash) = committed {
+                peer.save_peer_local_tip(&block_hash);
             }
+            logging::event(Level::Info, "sync.cancelled", &[
+                ("blockchain", &peer.blockchain.name), ("peer", &peer.name), ("blocks", &nr_blocks),
+            ]);
+            return Err(super::Error::Cancelled);
+        }
 
-            if date.get_epochid() >= first_unstable_epoch {
-                // This block is not part of a stable epoch yet and could
-                // be rolled back. Therefore we can't pack this epoch
-                // yet. Instead we write this block to disk separately.
-                let block_hash = storage::types::header_to_blockhash(&block_hash);
-                storage::blob::write(&peer.blockchain.storage, &block_hash, block_raw.as_ref()).unwrap();
-            } else {
+        if exhausted {
+            if let Some(block_hash) = committed {
+                peer.save_peer_local_tip(&block_hash);
+            }
+            let tip = peer.load_peer_local_tip().unwrap_or_else(|| best_tip.0.hash.clone());
+            logging::event(Level::Info, "sync.finished", &[
+                ("blockchain", &peer.blockchain.name), ("peer", &peer.name),
+                ("blocks", &nr_blocks), ("tip", &tip), ("incomplete", &true),
+            ]);
+            return Ok((peer, SyncReport { nr_blocks, tip, incomplete: true }));
+        }
+
+        // Update the tip tag to point to the most recent block.
+        if let Some(ref block_hash) = last_block {
+            peer.save_peer_local_tip(block_hash);
+        }
+        peer.record_sync(&tip);
+
+        let tip = last_block.unwrap_or(best_tip.0.hash);
+        logging::event(Level::Info, "sync.finished", &[
+            ("blockchain", &peer.blockchain.name), ("peer", &peer.name),
+            ("blocks", &nr_blocks), ("tip", &tip), ("incomplete", &false),
+        ]);
+        Ok((peer, SyncReport { nr_blocks, tip, incomplete: false }))
+    }
+}
 
-                // If this is the epoch genesis block, start writing a new epoch pack.
-                if date.is_genesis() {
-                    cur_epoch_state = Some((
-                        date.get_epochid(),
-                        storage::pack::packwriter_init(&peer.blockchain.storage.config).unwrap(),
-                        SystemTime::now()
-                    ));
+/// write the downloaded blocks in the storage
+///
+/// the blocks of the stable epochs are packed, the others are written
+/// as loose blocks (they could be rolled back).
+struct BlockWriter {
+    storage: storage::Storage,
+    tag: String,
+    first_unstable_epoch: EpochId,
+    cur_epoch_state: Option<(EpochId, packfile::Writer, SystemTime)>,
+    last_block: Option<HeaderHash>,
+    /// the last block written at a consistent point: the end of a
+    /// packed epoch or of a batch of loose blocks
+    committed: Option<HeaderHash>,
+    batch_size: usize,
+    /// the loose blocks waiting to be written
+    pending: Vec<(HeaderHash, RawBlock)>,
+    /// the blocks of the epoch being packed not written as loose blocks
+    /// yet (see `checkpoint_epoch`)
+    epoch_pending: Vec<(HeaderHash, RawBlock)>,
+    /// the number of blocks of the epoch being packed
+    epoch_blocks: u64,
+    /// where the epoch being packed is recorded, see `progress::PartialEpoch`
+    partial_file: PathBuf,
+}
+impl BlockWriter {
+    fn write(&mut self, block_hash: HeaderHash, date: BlockDate, block_raw: RawBlock) {
+        // Flush the previous epoch (if any).
+        if date.is_genesis() {
+            let mut writer_state = None;
+            mem::swap(&mut writer_state, &mut self.cur_epoch_state);
+            if let Some((epoch_id, writer, epoch_time_start)) = writer_state {
+                internal::finish_epoch(&self.storage, epoch_id, writer, &epoch_time_start);
+
+                // Checkpoint the tip so we don't have to refetch
+                // everything if we get interrupted.
+                tag::write_hash(&self.storage, &self.tag, self.last_block.as_ref().unwrap());
+                self.committed = self.last_block.clone();
+
+                // the loose copies of the epoch are left for `prune`
+                self.epoch_pending.clear();
+                if let Err(err) = PartialEpoch::delete(&self.partial_file) {
+                    warn!("cannot remove the record of the partial epoch {}: {}", epoch_id, err);
                 }
+            }
+        }
+
+        if date.get_epochid() >= self.first_unstable_epoch {
+            // This block is not part of a stable epoch yet and could
+            // be rolled back. Therefore we can't pack this epoch
+            // yet. Instead we write this block to disk separately.
+            self.pending.push((block_hash.clone(), block_raw));
+            if self.pending.len() >= self.batch_size { self.flush(); }
+        } else {
 
-                // And append the block to the epoch pack.
-                let (_, writer, _) = &mut cur_epoch_state.as_mut().unwrap();
-                writer.append(&storage::types::header_to_blockhash(&block_hash), block_raw.as_ref()).unwrap();
+            // If this is the epoch genesis block, start writing a new epoch pack.
+            if date.is_genesis() {
+                self.cur_epoch_state = Some((
+                    date.get_epochid(),
+                    storage::pack::packwriter_init(&self.storage.config).unwrap(),
+                    SystemTime::now()
+                ));
+                self.epoch_blocks = 0;
             }
 
-            last_block = Some(block_hash.clone());
-        }).unwrap();
-        pbr.finish();
+            // And append the block to the epoch pack.
+            let (_, writer, _) = &mut self.cur_epoch_state.as_mut().unwrap();
+            writer.append(&storage::types::header_to_blockhash(&block_hash), block_raw.as_ref()).unwrap();
+            self.epoch_blocks += 1;
+            self.epoch_pending.push((block_hash.clone(), block_raw));
+            if self.epoch_pending.len() >= self.batch_size { self.checkpoint_epoch(); }
+        }
 
-        // Update the tip tag to point to the most recent block.
-        if let Some(block_hash) = last_block {
-            peer.save_peer_local_tip(&block_hash);
+        self.last_block = Some(block_hash);
+    }
+
+    /// write the pending loose blocks, then move the tip of the peer to
+    /// the last one of them.
+    fn flush(&mut self) {
+        let last = match self.pending.last() {
+            None => return,
+            Some((block_hash, _)) => block_hash.clone(),
+        };
+        for (block_hash, block_raw) in self.pending.drain(..) {
+            let block_hash = storage::types::header_to_blockhash(&block_hash);
+            storage::blob::write(&self.storage, &block_hash, block_raw.as_ref()).unwrap();
         }
+        tag::write_hash(&self.storage, &self.tag, &last);
+        self.committed = Some(last);
+    }
 
-        peer
+    /// write the pending blocks of the epoch being packed as loose
+    /// blocks, then move the tip of the peer to the last one of them and
+    /// record the partial epoch: an interrupted sync resumes from there.
+    fn checkpoint_epoch(&mut self) {
+        let epoch_id = match &self.cur_epoch_state {
+            None => return,
+            Some((epoch_id, _, _)) => *epoch_id,
+        };
+        let last = match self.epoch_pending.last() {
+            None => return,
+            Some((block_hash, _)) => block_hash.clone(),
+        };
+        for (block_hash, block_raw) in self.epoch_pending.drain(..) {
+            let block_hash = storage::types::header_to_blockhash(&block_hash);
+            storage::blob::write(&self.storage, &block_hash, block_raw.as_ref()).unwrap();
+        }
+        tag::write_hash(&self.storage, &self.tag, &last);
+
+        let partial = PartialEpoch { epoch: epoch_id, blocks: self.epoch_blocks, last_block: format!("{}", last) };
+        if let Err(err) = partial.save(&self.partial_file) {
+            warn!("cannot record the partial epoch {}: {}", epoch_id, err);
+        }
+        self.committed = Some(last);
+    }
+
+    /// write everything pending, at the end of the download
+    fn finish(&mut self) {
+        self.checkpoint_epoch();
+        self.flush();
     }
 }
 
@@ -223,35 +805,45 @@ pub struct Peer<'a> {
     pub name: String,
 
     pub tag: String,
+
+    /// the backend of the protocol of the peer (see `protocol`)
+    pub backend: Box<Backend>,
 }
 impl<'a> Peer<'a> {
-    pub fn prepare(blockchain: &'a super::Blockchain, name: String) -> Self {
+    /// prepare the peer of the given alias, fails with
+    /// `Error::UnknownPeer` if the blockchain has no such remote
+    pub fn prepare(blockchain: &'a super::Blockchain, name: String) -> super::Result<Self> {
         let config = match blockchain.peers().find(|np| np.name() == &name) {
-            None => panic!(""),
+            None => return Err(super::Error::UnknownPeer(name)),
             Some(np) => np.peer().clone(),
         };
         let tag = blockchain.mk_remote_tag(&name);
+        let backend = protocol::backend(blockchain.protocol(&name));
 
-        Peer {
+        Ok(Peer {
             blockchain,
             name,
             config: config,
-            tag
-        }
+            tag,
+            backend
+        })
     }
 
+    /// the protocol spoken with the peer
+    pub fn protocol(&self) -> Protocol { self.backend.protocol() }
+
     /// initialise the connection by performing initial handshake (if necessary).
     pub fn connect(self, term: &mut Term) -> Result<ConnectedPeer<'a>, ()> {
-        let peer_handshake = exe_common::network::Peer::new(
-            self.blockchain.name.clone(),
-            self.name.to_owned(),
-            self.config.clone(),
-            self.blockchain.config.protocol_magic
-        );
+        let target = protocol::Target::new(self.blockchain, &self.name, &self.config);
+        let peer_handshake = self.backend.connect(&target);
 
         let connection = match peer_handshake {
             Err(err) => {
-                term.warn(&format!("Unable to initiate handshake with peer {} ({})\n\t{:?}\n", self.name, self.config, err)).unwrap();
+                term.warn(&format!("Unable to initiate handshake with peer {} ({}, {})\n\t{:?}\n", self.name, self.config, self.protocol(), err)).unwrap();
+                logging::event(Level::Warn, "peer.unreachable", &[
+                    ("blockchain", &self.blockchain.name), ("peer", &self.name), ("error", &format!("{:?}", err)),
+                ]);
+                self.blockchain.update_peer_stats(&self.name, |stats| stats.record_failure());
                 return Err(());
             },
             Ok(peer) => peer
@@ -263,45 +855,37 @@ impl<'a> Peer<'a> {
         })
     }
 
-    /// load the peer current block
-    pub fn load_peer_local_tip(&self) -> HeaderHash {
-        match tag::read_hash(&self.blockchain.storage, &self.tag) {
-            None => panic!("expecting any peer to have a tag"),
-            Some(hh) => hh
-        }
+    /// load the peer current block, `None` if the peer has no tag yet
+    pub fn load_peer_local_tip(&self) -> Option<HeaderHash> {
+        tag::read_hash(self.blockchain.storage(), &self.tag)
     }
 
     /// save the given peer header hash
     fn save_peer_local_tip(&self, tip: &HeaderHash) {
         tag::write_hash(
-            &self.blockchain.storage,
+            self.blockchain.storage(),
             &self.tag,
             tip
         )
     }
 
+    /// record a successful sync up to the given remote tip in the
+    /// peer's metrics
+    fn record_sync(&self, tip: &BlockRef) {
+        self.blockchain.update_peer_stats(&self.name, |stats| {
+            stats.last_sync = Some(SystemTime::now());
+            stats.tip_date = Some(format!("{}", tip.date));
+            stats.tip_height = Some(self.blockchain.flat_slot(&tip.date));
+            stats.failures = 0;
+        });
+    }
+
     /// get the remote local tip. the bool is to note if the tip is the same as genesis
-    pub fn load_local_tip(&self) -> (BlockRef, bool) {
-        let genesis_ref = (BlockRef {
-            hash: self.blockchain.config.genesis.clone(),
-            parent: self.blockchain.config.genesis_prev.clone(),
-            date: BlockDate::Genesis(self.blockchain.config.epoch_start)
-        }, true);
-        let our_tip = match self.blockchain.storage.get_block_from_tag(&self.tag) {
-            Err(storage::Error::NoSuchTag) => genesis_ref,
-            Err(err) => panic!(err),
-            Ok(block) => {
-                let header = block.get_header();
-                let hash = header.compute_hash();
-                let is_genesis = hash == genesis_ref.0.hash;
-                (BlockRef {
-                    hash: hash,
-                    parent: header.get_previous_header(),
-                    date: header.get_blockdate()
-                }, is_genesis)
-            }
-        };
-        our_tip
+    ///
+    /// this is the tip of the remote as of the last fetch, read from the
+    /// local storage. Use `ConnectedPeer::query_tip` to query the remote.
+    pub fn load_local_tip(&self) -> super::Result<(BlockRef, bool)> {
+        self.blockchain.load_tip_from_tag(&self.tag)
     }
 }
 
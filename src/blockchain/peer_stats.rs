@@ -0,0 +1,95 @@
+//! health metrics of the remotes, as measured when connecting to them
+//!
+//! The metrics of each remote are kept in `remote/<alias>.yml` in the
+//! blockchain directory, next to the remote's tag. They are updated by
+//! `peer::Peer::connect`, `peer::ConnectedPeer::probe_tip` and
+//! `peer::ConnectedPeer::sync_with`.
+//!
+
+use std::{fs, path::PathBuf, time::{Duration, SystemTime}};
+use serde_yaml;
+
+use super::{Blockchain, Result};
+
+/// the directory, within the blockchain directory, of the remotes' metrics
+pub const PEER_STATS_DIRECTORY : &'static str = "remote";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerStats {
+    /// the end of the last successful sync
+    pub last_sync: Option<SystemTime>,
+    /// the time taken by the last query of the remote's tip
+    pub latency: Option<Duration>,
+    /// the date of the remote's tip, as of the last sync
+    pub tip_date: Option<String>,
+    /// the slot of the remote's tip since the first slot of the network
+    /// (see `Blockchain::flat_slot`), as of the last sync
+    pub tip_height: Option<u64>,
+    /// the number of failures since the last successful sync
+    pub failures: u64,
+    /// the number of failures ever recorded
+    pub total_failures: u64,
+}
+impl PeerStats {
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+        self.total_failures += 1;
+    }
+}
+
+impl Blockchain {
+    fn peer_stats_file(&self, alias: &str) -> PathBuf {
+        self.dir.join(PEER_STATS_DIRECTORY).join(format!("{}.yml", alias))
+    }
+
+    /// the metrics of the given remote, empty if none has been recorded
+    pub fn load_peer_stats(&self, alias: &str) -> Result<PeerStats> {
+        let file = self.peer_stats_file(alias);
+        if ! file.is_file() { return Ok(PeerStats::default()); }
+
+        let content = fs::read(file)?;
+        serde_yaml::from_slice(&content).map_err(|err| {
+            ::std::io::Error::new(::std::io::ErrorKind::InvalidData, err).into()
+        })
+    }
+
+    /// save the metrics of the given remote
+    ///
+    /// written in `<file>.tmp` first then renamed, as the configuration.
+    pub fn save_peer_stats(&self, alias: &str, stats: &PeerStats) -> Result<()> {
+        let file = self.peer_stats_file(alias);
+        let tmp = file.with_extension("yml.tmp");
+        fs::create_dir_all(self.dir.join(PEER_STATS_DIRECTORY))?;
+
+        let content = serde_yaml::to_vec(stats).map_err(|err| {
+            ::std::io::Error::new(::std::io::ErrorKind::InvalidData, err)
+        })?;
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, file)?;
+        Ok(())
+    }
+
+    /// remove the metrics of the given remote (see `remove_peer`)
+    pub fn remove_peer_stats(&self, alias: &str) -> Result<()> {
+        let file = self.peer_stats_file(alias);
+        if file.is_file() { fs::remove_file(file)?; }
+        Ok(())
+    }
+
+    /// load, update and save the metrics of the given remote
+    ///
+    /// the metrics are only informative, failing to read or write them
+    /// is logged and ignored.
+    pub fn update_peer_stats<F>(&self, alias: &str, f: F)
+        where F: FnOnce(&mut PeerStats)
+    {
+        let mut stats = self.load_peer_stats(alias).unwrap_or_else(|err| {
+            warn!("ignoring the invalid metrics of {}: {}", alias, err);
+            PeerStats::default()
+        });
+        f(&mut stats);
+        if let Err(err) = self.save_peer_stats(alias, &stats) {
+            warn!("cannot save the metrics of {}: {}", alias, err);
+        }
+    }
+}
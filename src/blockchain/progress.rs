@@ -0,0 +1,179 @@
+//! progress of the synchronisation with a remote
+//!
+//! `peer::ConnectedPeer::sync_with_progress` notifies a `SyncObserver` of
+//! every block downloaded, with the rate of the download and an estimate
+//! of the remaining time (`SyncProgress`). `TermProgress` renders it as a
+//! progress bar.
+//!
+//! The blocks of a stable epoch are packed once the whole epoch has been
+//! downloaded. So an interrupted sync does not download them again, they
+//! are also written as loose blocks by batches while the epoch is being
+//! downloaded, and the epoch partially downloaded is recorded in
+//! `remote/<alias>.partial.yml`: the next sync resumes from the last
+//! batch written. Once the epoch is packed, the loose copies are left for
+//! `Blockchain::prune`.
+//!
+
+use std::{fs, io, path::{Path, PathBuf}, time::{Duration, Instant}};
+use cardano::block::{BlockDate, EpochId};
+use indicatif::ProgressBar;
+use humantime;
+use serde_yaml;
+
+use utils::term::Term;
+
+use super::{Blockchain, Result, peer_stats::PEER_STATS_DIRECTORY};
+
+/// an epoch partially downloaded by an interrupted sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialEpoch {
+    pub epoch: EpochId,
+    /// the number of blocks of the epoch already written
+    pub blocks: u64,
+    /// the hash of the last block written, the tip of the remote
+    pub last_block: String,
+}
+impl PartialEpoch {
+    pub fn load(file: &Path) -> io::Result<Option<Self>> {
+        if ! file.is_file() { return Ok(None); }
+
+        let content = fs::read(file)?;
+        serde_yaml::from_slice(&content).map(Some).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })
+    }
+
+    /// written in `<file>.tmp` first then renamed, as the remotes' metrics
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        let tmp = file.with_extension("yml.tmp");
+        if let Some(dir) = file.parent() { fs::create_dir_all(dir)?; }
+
+        let content = serde_yaml::to_vec(self).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })?;
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, file)
+    }
+
+    pub fn delete(file: &Path) -> io::Result<()> {
+        if file.is_file() { fs::remove_file(file)?; }
+        Ok(())
+    }
+}
+
+impl Blockchain {
+    pub fn partial_epoch_file(&self, alias: &str) -> PathBuf {
+        self.dir.join(PEER_STATS_DIRECTORY).join(format!("{}.partial.yml", alias))
+    }
+
+    /// the epoch left partially downloaded by the last sync with the given
+    /// remote, if it was interrupted
+    pub fn load_partial_epoch(&self, alias: &str) -> Result<Option<PartialEpoch>> {
+        Ok(PartialEpoch::load(&self.partial_epoch_file(alias))?)
+    }
+}
+
+/// the progress of a download, from the date of the local tip of the
+/// remote to the date of the remote tip
+#[derive(Debug, Clone)]
+pub struct SyncProgress {
+    pub from: BlockDate,
+    pub to: BlockDate,
+    /// the date of the last block downloaded
+    pub current: BlockDate,
+    /// the number of blocks downloaded
+    pub nr_blocks: u64,
+    pub started: Instant,
+}
+impl SyncProgress {
+    pub fn new(from: BlockDate, to: BlockDate) -> Self {
+        SyncProgress { from: from.clone(), to, current: from, nr_blocks: 0, started: Instant::now() }
+    }
+
+    /// the number of slots between the local tip and the remote tip
+    pub fn total_slots(&self) -> u64 {
+        if self.to > self.from { (self.to.clone() - self.from.clone()) as u64 } else { 0 }
+    }
+
+    /// the number of slots downloaded
+    pub fn done_slots(&self) -> u64 {
+        if self.current > self.from { (self.current.clone() - self.from.clone()) as u64 } else { 0 }
+    }
+
+    pub fn remaining_epochs(&self) -> u64 {
+        self.to.get_epochid().saturating_sub(self.current.get_epochid())
+    }
+
+    pub fn blocks_per_sec(&self) -> f64 {
+        let elapsed = self.started.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_millis() as f64 / 1000.0;
+        if secs > 0.0 { self.nr_blocks as f64 / secs } else { 0.0 }
+    }
+
+    /// the time left at the current rate, `None` until the first slots
+    /// have been downloaded
+    pub fn eta(&self) -> Option<Duration> {
+        let done = self.done_slots();
+        if done == 0 { return None; }
+        let remaining = self.total_slots().saturating_sub(done);
+        let elapsed = self.started.elapsed().as_secs();
+        Some(Duration::from_secs(elapsed * remaining / done))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    /// the download starts, `resumed` is the epoch partially downloaded by
+    /// the previous sync, if it was interrupted
+    Started { resumed: Option<PartialEpoch> },
+    /// a block has been downloaded
+    Block,
+    /// the download is over (completed or interrupted)
+    Finished,
+}
+
+pub trait SyncObserver {
+    fn notify(&mut self, term: &mut Term, event: &SyncEvent, progress: &SyncProgress);
+}
+
+/// ignore the progress of the sync
+pub struct NoProgress;
+impl SyncObserver for NoProgress {
+    fn notify(&mut self, _term: &mut Term, _event: &SyncEvent, _progress: &SyncProgress) {}
+}
+
+/// render the progress of the sync as a progress bar, over the slots
+/// between the local tip and the remote tip
+#[derive(Default)]
+pub struct TermProgress {
+    bar: Option<ProgressBar>,
+}
+impl TermProgress {
+    pub fn new() -> Self { TermProgress::default() }
+}
+impl SyncObserver for TermProgress {
+    fn notify(&mut self, term: &mut Term, event: &SyncEvent, progress: &SyncProgress) {
+        match event {
+            SyncEvent::Started { resumed } => {
+                if let Some(partial) = resumed {
+                    term.info(&format!("resuming the download of epoch {} ({} blocks already downloaded)\n",
+                        partial.epoch, partial.blocks)).unwrap();
+                }
+                self.bar = Some(term.progress_bar_with_message(progress.total_slots()));
+            },
+            SyncEvent::Block => {
+                if let Some(bar) = &self.bar {
+                    bar.set_position(progress.done_slots());
+                    let eta = progress.eta()
+                        .map(|eta| format!("{}", humantime::format_duration(eta)))
+                        .unwrap_or_else(|| "unknown".to_owned());
+                    bar.set_message(&format!("epoch {} ({} to go), {:.0} blocks/s, ETA {}",
+                        progress.current.get_epochid(), progress.remaining_epochs(), progress.blocks_per_sec(), eta));
+                }
+            },
+            SyncEvent::Finished => {
+                if let Some(bar) = self.bar.take() { bar.finish(); }
+            },
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! the protocols spoken with the remotes
+//!
+//! Each remote of the blockchain configuration declares its protocol
+//! (see `Protocol`): the native protocol of the cardano nodes, or the
+//! HTTP bridge, an HTTP(S) front of a node serving the blocks (useful
+//! when the native port is blocked). `peer::Peer::prepare` picks the
+//! `Backend` of the remote's protocol, which opens its own transport; the
+//! connections are then used through the `Connection` trait whatever the
+//! protocol.
+//!
+//! The protocol is recorded with the remote in the blockchain
+//! configuration (see `Blockchain::protocol`). The configurations written
+//! before have none, the protocol is then guessed from the remote's
+//! address: the HTTP bridge remotes are the `http://` and `https://`
+//! URLs, the native remotes are `host:port` addresses (see
+//! `Protocol::guess`).
+//!
+
+use std::{fmt, panic, str::FromStr};
+use exe_common::{config::net, network::{self, api::{Api, BlockRef}, hermes::HermesEndPoint, native::OpenPeer}};
+use cardano::block::{Block, BlockHeader, HeaderHash, RawBlock};
+use cardano::{config::ProtocolMagic, tx::TxAux};
+
+use super::Blockchain;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    /// the native protocol of the cardano nodes
+    Native,
+    /// the HTTP bridge, the remotes are reached over HTTP(S)
+    HttpBridge,
+}
+impl Protocol {
+    /// the names accepted by `from_str`
+    pub const NAMES : &'static [&'static str] = &["native", "http-bridge"];
+
+    /// the remote's address for the given endpoint, as recorded in the
+    /// blockchain configuration
+    ///
+    /// the HTTP bridge endpoints default to `https://` when given without
+    /// their scheme. Fails if the endpoint is not an endpoint of this
+    /// protocol (an URL for a native remote).
+    pub fn endpoint(self, endpoint: &str) -> Result<String, String> {
+        let is_url = endpoint.starts_with("http://") || endpoint.starts_with("https://");
+        match self {
+            Protocol::Native if is_url => Err(format!("`{}' is an HTTP endpoint, not a native one (see `--protocol')", endpoint)),
+            Protocol::Native => Ok(endpoint.to_owned()),
+            Protocol::HttpBridge if is_url => Ok(endpoint.to_owned()),
+            Protocol::HttpBridge => Ok(format!("https://{}", endpoint)),
+        }
+    }
+
+    /// the protocol of the given endpoint, if none is declared
+    pub fn guess(endpoint: &str) -> Self {
+        if net::Peer::new(endpoint.to_owned()).is_native() { Protocol::Native } else { Protocol::HttpBridge }
+    }
+}
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protocol::Native     => write!(f, "native"),
+            Protocol::HttpBridge => write!(f, "http-bridge"),
+        }
+    }
+}
+impl FromStr for Protocol {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native"      => Ok(Protocol::Native),
+            "http-bridge" => Ok(Protocol::HttpBridge),
+            _ => Err(format!("unknown protocol `{}', expected one of: {}", s, Protocol::NAMES.join(", "))),
+        }
+    }
+}
+
+/// a connection to a remote, whatever its protocol
+///
+/// the operations of `exe_common::network::api::Api`, in a form usable
+/// through a trait object.
+pub trait Connection {
+    fn get_tip(&mut self) -> Result<BlockHeader, network::Error>;
+
+    fn get_block(&mut self, hash: &HeaderHash) -> Result<RawBlock, network::Error>;
+
+    fn get_blocks( &mut self
+                 , from: &BlockRef
+                 , inclusive: bool
+                 , to: &BlockRef
+                 , got_block: &mut FnMut(&HeaderHash, &Block, &RawBlock)
+                 ) -> Result<(), network::Error>;
+
+    fn send_transaction(&mut self, txaux: TxAux) -> Result<bool, network::Error>;
+
+    /// same as `get_blocks`, but the download is aborted as soon as
+    /// `got_block` returns `false`: the remaining blocks are not
+    /// downloaded.
+    ///
+    /// The transports offer no way to interrupt a stream, the download is
+    /// unwound out of the transport (see `Stopped`) and the connection is
+    /// closed: it is consumed.
+    fn get_blocks_while( self: Box<Self>
+                       , from: &BlockRef
+                       , inclusive: bool
+                       , to: &BlockRef
+                       , got_block: &mut FnMut(&HeaderHash, &Block, &RawBlock) -> bool
+                       ) -> Result<(), network::Error>
+    {
+        let mut connection = self;
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            connection.get_blocks(from, inclusive, to, &mut |hash, block, raw| {
+                if ! got_block(hash, block, raw) { panic::resume_unwind(Box::new(Stopped)); }
+            })
+        }));
+        match result {
+            Ok(result) => result,
+            Err(ref payload) if payload.is::<Stopped>() => Ok(()),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// the payload of the unwinding aborting a download, see
+/// `Connection::get_blocks_while`
+///
+/// `resume_unwind` does not call the panic hook: nothing is reported.
+struct Stopped;
+impl<A: Api> Connection for A {
+    fn get_tip(&mut self) -> Result<BlockHeader, network::Error> { Api::get_tip(self) }
+
+    fn get_block(&mut self, hash: &HeaderHash) -> Result<RawBlock, network::Error> { Api::get_block(self, hash) }
+
+    fn get_blocks( &mut self
+                 , from: &BlockRef
+                 , inclusive: bool
+                 , to: &BlockRef
+                 , got_block: &mut FnMut(&HeaderHash, &Block, &RawBlock)
+                 ) -> Result<(), network::Error>
+    {
+        Api::get_blocks(self, from, inclusive, to, &mut |hash, block, raw| got_block(hash, block, raw))
+    }
+
+    fn send_transaction(&mut self, txaux: TxAux) -> Result<bool, network::Error> { Api::send_transaction(self, txaux) }
+}
+
+/// what is needed to reach a remote, whatever its protocol
+///
+/// only made of owned values: the connection can be opened from another
+/// thread (see `health`).
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// the name of the blockchain
+    pub blockchain: String,
+    /// the alias of the remote
+    pub name: String,
+    /// the remote's address, as recorded in the configuration
+    pub address: String,
+    pub protocol_magic: ProtocolMagic,
+}
+impl Target {
+    pub fn new(blockchain: &Blockchain, name: &str, peer: &net::Peer) -> Self {
+        let address = match peer {
+            net::Peer::Native(address) => address.clone(),
+            net::Peer::Http(address) => address.clone(),
+        };
+        Target {
+            blockchain: blockchain.name.clone(),
+            name: name.to_owned(),
+            address,
+            protocol_magic: blockchain.config.protocol_magic,
+        }
+    }
+}
+
+/// the way to connect to the remotes of a protocol
+pub trait Backend {
+    fn protocol(&self) -> Protocol;
+
+    /// the remotes of this protocol accept the transactions (see
+    /// `peer::ConnectedPeer::send_txaux`)
+    fn sends_transactions(&self) -> bool { true }
+
+    /// connect to the remote, performing the initial handshake if the
+    /// protocol has one
+    fn connect(&self, target: &Target) -> Result<Box<Connection>, network::Error>;
+}
+
+/// the native protocol: a TCP connection to the node, opened with the
+/// handshake of the protocol (see `exe_common::network::native`)
+pub struct NativeBackend;
+impl Backend for NativeBackend {
+    fn protocol(&self) -> Protocol { Protocol::Native }
+
+    fn connect(&self, target: &Target) -> Result<Box<Connection>, network::Error> {
+        let connection = OpenPeer::new(target.name.clone(), target.address.clone(), target.protocol_magic)?;
+        Ok(Box::new(connection))
+    }
+}
+
+/// the HTTP bridge: HTTP(S) requests to the bridge's endpoint for the
+/// blockchain (see `exe_common::network::hermes`), there is no handshake
+///
+/// the bridge only serves the blocks, the transactions are sent to the
+/// native remotes.
+pub struct HttpBridgeBackend;
+impl Backend for HttpBridgeBackend {
+    fn protocol(&self) -> Protocol { Protocol::HttpBridge }
+
+    fn sends_transactions(&self) -> bool { false }
+
+    fn connect(&self, target: &Target) -> Result<Box<Connection>, network::Error> {
+        let connection = HermesEndPoint::new(target.address.clone(), target.blockchain.clone());
+        Ok(Box::new(connection))
+    }
+}
+
+/// the backend of the given protocol
+pub fn backend(protocol: Protocol) -> Box<Backend> {
+    match protocol {
+        Protocol::Native     => Box::new(NativeBackend),
+        Protocol::HttpBridge => Box::new(HttpBridgeBackend),
+    }
+}
+
+impl Blockchain {
+    /// the protocol of the given remote: the one declared in the
+    /// configuration (see `remote-add --protocol`), guessed from the
+    /// remote's address for the remotes added before the protocols were
+    /// declared
+    pub fn protocol(&self, alias: &str) -> Protocol {
+        if let Some(protocol) = self.protocols.get(alias) { return *protocol; }
+        match self.peers().find(|np| np.name() == alias) {
+            Some(np) if ! np.peer().is_native() => Protocol::HttpBridge,
+            _ => Protocol::Native,
+        }
+    }
+
+    /// the remotes to sync from, in order of preference: the native
+    /// remotes, then the HTTP bridges
+    pub fn sync_peers(&self) -> Vec<String> {
+        let mut peers : Vec<&net::NamedPeer> = self.peers().collect();
+        peers.sort_by_key(|np| self.protocol(np.name()));
+        peers.into_iter().map(|np| np.name().to_owned()).collect()
+    }
+
+    /// the remotes accepting the transactions
+    pub fn transaction_peers(&self) -> Vec<String> {
+        self.peers()
+            .filter(|np| backend(self.protocol(np.name())).sends_transactions())
+            .map(|np| np.name().to_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn endpoint() {
+        assert_eq!(Protocol::HttpBridge.endpoint("hermes.iohk.io"), Ok("https://hermes.iohk.io".to_owned()));
+        assert_eq!(Protocol::HttpBridge.endpoint("http://localhost:8080"), Ok("http://localhost:8080".to_owned()));
+        assert_eq!(Protocol::Native.endpoint("relays.cardano-mainnet.iohk.io:3000"), Ok("relays.cardano-mainnet.iohk.io:3000".to_owned()));
+        assert!(Protocol::Native.endpoint("https://hermes.iohk.io").is_err());
+    }
+
+    #[test]
+    fn guess() {
+        assert_eq!(Protocol::guess("https://hermes.iohk.io"), Protocol::HttpBridge);
+        assert_eq!(Protocol::guess("relays.cardano-mainnet.iohk.io:3000"), Protocol::Native);
+    }
+
+    #[test]
+    fn names() {
+        for name in Protocol::NAMES {
+            assert_eq!(&format!("{}", name.parse::<Protocol>().unwrap()), name);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! remove the loose blocks that are already packed
+//!
+//! The blocks of the unstable epochs are written as loose blocks, they
+//! are packed once their epoch becomes stable but the loose copies are
+//! left in the storage. The packs are written once and are not
+//! fragmented, there is nothing to compact in them.
+//!
+
+use std::fs;
+use cardano::block::EpochId;
+
+use super::{Blockchain, Result, iter};
+
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    /// the packed epochs whose loose blocks have been removed
+    pub epochs: u64,
+    /// the number of loose blocks removed
+    pub blocks: u64,
+    /// the disk space reclaimed, in bytes
+    pub reclaimed: u64,
+}
+
+impl Blockchain {
+    /// remove the loose copies of the packed blocks, the blocks of the
+    /// last `keep_epochs` packed epochs are kept.
+    ///
+    /// The blocks remain readable from their pack, the tags are left
+    /// untouched.
+    pub fn prune(&self, keep_epochs: u64) -> Result<PruneReport> {
+        let packed = self.packed_epochs();
+        let end = if packed.end - packed.start > keep_epochs { packed.end - keep_epochs } else { packed.start };
+
+        let mut report = PruneReport::default();
+        let blob_dir = self.dir.join("blob");
+        if ! blob_dir.is_dir() { return Ok(report); }
+
+        for epoch_id in packed.start..end {
+            report.epochs += 1;
+            self.prune_epoch(epoch_id, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    fn prune_epoch(&self, epoch_id: EpochId, report: &mut PruneReport) -> Result<()> {
+        for raw_block in iter::epoch::Iter::new(&self.storage_config, epoch_id)? {
+            let hash = raw_block?.decode()?.get_header().compute_hash();
+            let blob = self.dir.join("blob").join(format!("{}", hash));
+            let metadata = match fs::metadata(&blob) {
+                Err(_) => continue,
+                Ok(metadata) => metadata,
+            };
+
+            fs::remove_file(&blob)?;
+            report.blocks += 1;
+            report.reclaimed += metadata.len();
+        }
+        Ok(())
+    }
+}
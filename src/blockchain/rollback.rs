@@ -0,0 +1,100 @@
+//! rewind the local tip to one of its ancestors
+//!
+//! When a remote rolls back (or follows another branch), its chain is no
+//! longer an extension of the local chain: the local tip needs to be
+//! moved back to the last common block before syncing the new branch.
+//! The blocks of the abandoned branch are left in the storage.
+//!
+
+use cardano::block::{Block, HeaderHash};
+use cardano_storage::{self as storage, tag, block::iter::ReverseIter};
+use exe_common::network::api::BlockRef;
+
+use super::{Blockchain, Error, Result};
+
+fn block_ref(block: &Block) -> BlockRef {
+    let header = block.get_header();
+    BlockRef {
+        hash: header.compute_hash(),
+        parent: header.get_previous_header(),
+        date: header.get_blockdate(),
+    }
+}
+
+impl Blockchain {
+    /// the given block is stored and is the local tip or one of its
+    /// ancestors
+    pub fn is_ancestor(&self, hash: &HeaderHash) -> Result<bool> {
+        let (tip, _is_genesis) = self.load_tip()?;
+        self.is_ancestor_of(hash, &tip.hash)
+    }
+
+    /// the given block is stored and is `descendant` or one of its
+    /// ancestors
+    pub fn is_ancestor_of(&self, hash: &HeaderHash, descendant: &HeaderHash) -> Result<bool> {
+        let block = match storage::block_read(self.storage(), hash) {
+            None => return Ok(false),
+            Some(raw_block) => raw_block.decode()?,
+        };
+        let date = block.get_header().get_blockdate();
+
+        let descendant_date = match storage::block_read(self.storage(), descendant) {
+            None => return Ok(false),
+            Some(raw_block) => raw_block.decode()?.get_header().get_blockdate(),
+        };
+        if descendant_date < date { return Ok(false); }
+
+        // the dates of the blocks of a chain are all different, the
+        // first block not later than `date` is the only candidate
+        for block in ReverseIter::from(self.storage(), descendant.clone())? {
+            let header = block.get_header();
+            if header.get_blockdate() <= date {
+                return Ok(&header.compute_hash() == hash);
+            }
+        }
+        Ok(false)
+    }
+
+    /// move the local tip back to the given block
+    ///
+    /// The tags of the remotes beyond the block are moved back too,
+    /// otherwise the next sync would start from them again. Fails with
+    /// `Error::NotAnAncestor` if the block is not on the local chain,
+    /// and with `Error::StableBlock` if its epoch is already packed.
+    pub fn rollback_to(&self, hash: &HeaderHash) -> Result<BlockRef> {
+        let block = match storage::block_read(self.storage(), hash) {
+            None => return Err(Error::BlockNotFound(hash.clone())),
+            Some(raw_block) => raw_block.decode()?,
+        };
+        let target = block_ref(&block);
+
+        if storage::epoch::epoch_read_pack(&self.storage_config, target.date.get_epochid()).is_ok() {
+            return Err(Error::StableBlock(hash.clone()));
+        }
+        if ! self.is_ancestor(hash)? {
+            return Err(Error::NotAnAncestor(hash.clone()));
+        }
+
+        self.rewind_to(&target);
+        Ok(target)
+    }
+
+    /// move the local tip, and the tags of the remotes beyond it, back to
+    /// the given block
+    ///
+    /// unlike `rollback_to`, nothing is checked: the block has to be an
+    /// ancestor of the local tip.
+    pub fn rewind_to(&self, target: &BlockRef) {
+        for np in self.peers() {
+            let remote_tag = self.mk_remote_tag(np.name());
+            match self.load_tip_from_tag(&remote_tag) {
+                Ok((remote_tip, _)) if remote_tip.date > target.date => {
+                    tag::write_hash(self.storage(), &remote_tag, &target.hash);
+                },
+                _ => {},
+            }
+        }
+
+        self.save_tip(&target.hash);
+    }
+}
@@ -0,0 +1,148 @@
+//! statistics about the content of the local storage
+//!
+//! The packed blocks are read as raw bytes, they are never decoded. The
+//! loose blocks and the tags are only looked up on the filesystem, only
+//! the headers of the loose blocks are decoded to count them by epoch.
+//!
+
+use std::{fs, path::Path, io::Cursor};
+use cardano::block::{BlockHeader, EpochId, RawBlock};
+use cardano_storage as storage;
+use cbor_event::{self, de::Deserializer};
+
+use super::{Blockchain, Result, iter};
+
+/// decode the header of the given block, its body is not read: the CBOR
+/// of a block is `[ type, [ header, body, extra ] ]`
+fn decode_header(raw_block: &RawBlock) -> cbor_event::Result<BlockHeader> {
+    let mut raw = Deserializer::from(Cursor::new(raw_block.as_ref()));
+    raw.array()?;
+    let block_type = raw.unsigned_integer()?;
+    raw.array()?;
+    match block_type {
+        0 => Ok(BlockHeader::GenesisBlockHeader(raw.deserialize()?)),
+        1 => Ok(BlockHeader::MainBlockHeader(raw.deserialize()?)),
+        _ => Err(cbor_event::Error::CustomError(format!("unknown block type {}", block_type))),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageReport {
+    /// number of blocks in the packed epochs
+    pub packed_blocks: u64,
+    /// number of blocks stored on their own (the unstable epochs)
+    pub loose_blocks: u64,
+    /// number of packed epochs
+    pub packs: u64,
+    /// total size of the blocks, in bytes
+    pub total_size: u64,
+    /// the size of the largest block, in bytes
+    pub largest_block: u64,
+    /// number of tags (the local tip and the remotes' tips included)
+    pub tags: u64,
+}
+impl StorageReport {
+    pub fn blocks(&self) -> u64 { self.packed_blocks + self.loose_blocks }
+
+    /// the average size of a block, in bytes (0 if there are no blocks)
+    pub fn average_block_size(&self) -> u64 {
+        match self.blocks() {
+            0 => 0,
+            n => self.total_size / n,
+        }
+    }
+
+    fn add_block(&mut self, size: u64) {
+        self.total_size += size;
+        if size > self.largest_block { self.largest_block = size; }
+    }
+}
+
+/// count the files in the given directory and its sub-directories
+fn count_files(dir: &Path) -> Result<u64> {
+    if ! dir.is_dir() { return Ok(0); }
+
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            count += count_files(&path)?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+impl Blockchain {
+    /// walk through the storage and report on its composition
+    pub fn storage_report(&self) -> Result<StorageReport> {
+        let mut report = StorageReport::default();
+
+        for epoch in iter::epoch::Epochs::new(&self.storage_config).from_epoch(self.config.epoch_start) {
+            report.packs += 1;
+            for raw_block in epoch? {
+                report.packed_blocks += 1;
+                report.add_block(raw_block?.as_ref().len() as u64);
+            }
+        }
+
+        // the loose blocks are stored in files named after their hash
+        let blob_dir = self.dir.join("blob");
+        if blob_dir.is_dir() {
+            for entry in fs::read_dir(blob_dir)? {
+                let metadata = entry?.metadata()?;
+                if ! metadata.is_file() { continue; }
+                report.loose_blocks += 1;
+                report.add_block(metadata.len());
+            }
+        }
+
+        report.tags = count_files(&self.dir.join("tag"))?;
+
+        Ok(report)
+    }
+
+    /// the number of stored blocks of each epoch from `from` to `to`
+    /// (both included), in the order of the epochs.
+    ///
+    /// The blocks of the packed epochs are counted without being decoded,
+    /// the loose blocks are counted walking back from the local tip. The
+    /// epoch of the local tip reports the blocks stored so far, the epochs
+    /// after it are not reported.
+    pub fn blocks_per_epoch(&self, from: EpochId, to: EpochId) -> Result<Vec<(EpochId, u64)>> {
+        let mut counts = Vec::new();
+
+        let mut epoch_id = from;
+        while epoch_id <= to && storage::epoch::epoch_read_pack(&self.storage_config, epoch_id).is_ok() {
+            let mut count = 0;
+            for raw_block in iter::epoch::Iter::new(&self.storage_config, epoch_id)? {
+                raw_block?;
+                count += 1;
+            }
+            counts.push((epoch_id, count));
+            epoch_id += 1;
+        }
+        let first_loose_epoch = epoch_id;
+
+        let (tip, is_genesis) = self.load_tip()?;
+        if is_genesis || first_loose_epoch > to || tip.date.get_epochid() < first_loose_epoch {
+            return Ok(counts);
+        }
+
+        let mut loose_counts = vec![0; (tip.date.get_epochid() - first_loose_epoch + 1) as usize];
+        let mut cur_hash = tip.hash;
+        // the parent of the first block of the chain is not stored
+        while let Some(raw_block) = storage::block_read(self.storage(), &cur_hash) {
+            let header = decode_header(&raw_block)?;
+            let epoch_id = header.get_blockdate().get_epochid();
+            if epoch_id < first_loose_epoch { break; }
+            loose_counts[(epoch_id - first_loose_epoch) as usize] += 1;
+            cur_hash = header.get_previous_header();
+        }
+
+        let loose_epochs = first_loose_epoch..;
+        counts.extend(loose_epochs.zip(loose_counts).take_while(|(epoch_id, _)| *epoch_id <= to));
+        Ok(counts)
+    }
+}
@@ -0,0 +1,124 @@
+//! abstraction over the storage of the blocks and tags
+//!
+//! The tags (the local tip and the remotes' tips) and the lookup of the
+//! blocks they point to only need a handful of operations from the
+//! storage, they are captured by the `BlockStore` trait. The default
+//! implementation is the file storage of `cardano_storage`. A
+//! `MemoryStore` is also provided, handy for testing.
+//!
+//! The synchronisation, the iterators, the verification and the
+//! statistics work on the packed epochs and the loose blocks of the file
+//! storage directly, they are only available for `Blockchain<Storage>`.
+//!
+
+use cardano::block::{Block, RawBlock, HeaderHash};
+use cardano_storage::{self as storage, tag, Storage, config::StorageConfig};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::{Error, Result};
+
+pub trait BlockStore {
+    /// open the store described by the given storage config
+    fn open(config: &StorageConfig) -> Result<Self> where Self: Sized;
+
+    /// read the block of the given hash, `None` if the block is not stored
+    fn read_block(&self, hash: &HeaderHash) -> Option<RawBlock>;
+
+    /// check the block of the given hash is stored
+    fn block_exists(&self, hash: &HeaderHash) -> bool {
+        self.read_block(hash).is_some()
+    }
+
+    /// read the hash the given tag points to, `None` if there is no such tag
+    fn read_tag(&self, tag: &str) -> Option<HeaderHash>;
+
+    /// set the given tag to point to the given hash
+    fn write_tag(&self, tag: &str, hash: &HeaderHash);
+
+    /// remove the given tag
+    fn remove_tag(&self, tag: &str);
+
+    /// read the block the given tag points to, `None` if there is no such tag
+    ///
+    /// fails with `Error::CorruptTag` if the block of the tag is not
+    /// stored or cannot be decoded.
+    fn get_block_from_tag(&self, tag: &str) -> Result<Option<Block>> {
+        let hash = match self.read_tag(tag) {
+            None => return Ok(None),
+            Some(hash) => hash,
+        };
+        let corrupt = |err: Error| Error::CorruptTag(tag.to_owned(), Box::new(err));
+        match self.read_block(&hash) {
+            None => Err(corrupt(Error::BlockNotFound(hash))),
+            Some(raw_block) => raw_block.decode().map(Some).map_err(|err| corrupt(err.into())),
+        }
+    }
+}
+
+impl BlockStore for Storage {
+    fn open(config: &StorageConfig) -> Result<Self> {
+        Ok(Storage::init(config)?)
+    }
+
+    fn read_block(&self, hash: &HeaderHash) -> Option<RawBlock> {
+        storage::block_read(self, hash)
+    }
+
+    fn block_exists(&self, hash: &HeaderHash) -> bool {
+        storage::block_location(self, hash).is_some()
+    }
+
+    fn read_tag(&self, name: &str) -> Option<HeaderHash> {
+        tag::read_hash(self, &name)
+    }
+
+    fn write_tag(&self, name: &str, hash: &HeaderHash) {
+        tag::write_hash(self, &name, hash)
+    }
+
+    fn remove_tag(&self, name: &str) {
+        tag::remove_tag(self, &name)
+    }
+}
+
+/// a store keeping everything in memory, nothing is persisted
+pub struct MemoryStore {
+    blocks: RefCell<BTreeMap<String, Vec<u8>>>,
+    tags: RefCell<BTreeMap<String, HeaderHash>>,
+}
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            blocks: RefCell::new(BTreeMap::new()),
+            tags: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// store the given block
+    pub fn write_block(&self, hash: &HeaderHash, block: &RawBlock) {
+        self.blocks.borrow_mut().insert(format!("{}", hash), block.as_ref().to_vec());
+    }
+}
+impl BlockStore for MemoryStore {
+    /// the memory store has nothing to open, a new empty store is returned
+    fn open(_: &StorageConfig) -> Result<Self> {
+        Ok(MemoryStore::new())
+    }
+
+    fn read_block(&self, hash: &HeaderHash) -> Option<RawBlock> {
+        self.blocks.borrow().get(&format!("{}", hash)).map(|bytes| RawBlock(bytes.clone()))
+    }
+
+    fn read_tag(&self, name: &str) -> Option<HeaderHash> {
+        self.tags.borrow().get(name).cloned()
+    }
+
+    fn write_tag(&self, name: &str, hash: &HeaderHash) {
+        self.tags.borrow_mut().insert(name.to_owned(), hash.clone());
+    }
+
+    fn remove_tag(&self, name: &str) {
+        self.tags.borrow_mut().remove(name);
+    }
+}
@@ -0,0 +1,471 @@
+//! verify the hash links of the local blockchain
+//!
+//! Every packed epoch is verified on its own: each block needs to be
+//! valid (see `cardano::block::verify_block`), to point to the block
+//! preceding it in the pack and to be dated after it. A pack starts with
+//! the epoch boundary block of its epoch. Then the boundaries of the epochs are
+//! stitched together and the loose blocks (the unstable epochs) are
+//! verified after the last packed epoch, each of them needs to be stored
+//! under its own hash.
+//!
+//! The corrupt loose blocks can then be moved aside (see `quarantine`)
+//! so they can be downloaded again: the local tip is moved back before
+//! them first.
+//!
+//! With the `parallel` feature the packed epochs can be verified
+//! concurrently (see `verify_links_parallel`). The report is the same
+//! as the one of the sequential verifier.
+//!
+
+use cardano::block::{self, BlockDate, EpochId, HeaderHash, verify::Error as BlockError};
+use cardano_storage::{self as storage, config::StorageConfig};
+use cardano::config::ProtocolMagic;
+use exe_common::network::api::BlockRef;
+use std::fs;
+use utils::cancel::CancelToken;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::{Blockchain, Error, Result, iter};
+
+/// the directory, within the blockchain directory, where the corrupt
+/// blocks are moved (see `Blockchain::quarantine`)
+pub const QUARANTINE_DIRECTORY : &'static str = "quarantine";
+
+/// the reason a block has been reported as invalid
+#[derive(Debug)]
+pub enum Invalid {
+    /// the block does not pass the verification of its own content
+    Block(BlockError),
+    /// the block's previous hash does not match the hash of the block
+    /// preceding it in the local blockchain
+    BrokenLink { expected: HeaderHash, found: HeaderHash },
+    /// the block is referenced (as a parent) but is not present in the storage
+    Missing,
+    /// the block is stored but cannot be decoded
+    Decode(::cbor_event::Error),
+    /// the block is stored under another hash than its own, `computed`
+    /// is the hash of its header
+    HashMismatch { computed: HeaderHash },
+    /// the date of the block does not follow the date of the block
+    /// preceding it: it is not later, or it skips the boundary block of
+    /// its epoch. `None` for the first block of a pack which is not the
+    /// boundary block of the pack's epoch.
+    Date { previous: Option<BlockDate> },
+}
+impl Invalid {
+    /// the block stored locally is corrupt (it needs to be downloaded again)
+    pub fn is_corrupt(&self) -> bool {
+        match self {
+            Invalid::Block(_)  => true,
+            Invalid::Decode(_) => true,
+            Invalid::HashMismatch { .. } => true,
+            _                  => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidBlock {
+    pub hash: HeaderHash,
+    /// the date of the block, `None` if the block is missing
+    pub date: Option<BlockDate>,
+    pub reason: Invalid,
+}
+
+/// the result of the verification of the local blockchain
+///
+/// the invalid blocks are listed in the order of the chain
+#[derive(Debug)]
+pub struct Report {
+    pub nr_blocks: usize,
+    pub invalid_blocks: Vec<InvalidBlock>,
+    /// the last block of the packed epochs, the `genesis_prev` of the
+    /// configuration if none is packed
+    pub last_packed: HeaderHash,
+}
+impl Report {
+    pub fn is_valid(&self) -> bool { self.invalid_blocks.is_empty() }
+
+    /// the first invalid block, in the order of the chain
+    pub fn first_invalid(&self) -> Option<&InvalidBlock> { self.invalid_blocks.first() }
+}
+
+/// the block dated `date` can follow the block dated `previous`: within
+/// the same epoch, or as the epoch boundary block of the next epoch
+pub fn date_follows(previous: &BlockDate, date: &BlockDate) -> bool {
+    match date {
+        BlockDate::Genesis(epoch) => previous.get_epochid() + 1 == *epoch,
+        BlockDate::Normal(d) => previous.get_epochid() == d.epoch && previous < date,
+    }
+}
+
+/// the verification of a single packed epoch
+struct EpochReport {
+    nr_blocks: usize,
+    /// the hash, date and parent's hash of the first block of the epoch
+    first: Option<(HeaderHash, BlockDate, HeaderHash)>,
+    /// the hash of the last block of the epoch
+    last: Option<HeaderHash>,
+    invalid_blocks: Vec<InvalidBlock>,
+}
+
+struct LinkVerifier {
+    protocol_magic: ProtocolMagic,
+    previous: Option<HeaderHash>,
+    previous_date: Option<BlockDate>,
+    nr_blocks: usize,
+    invalid_blocks: Vec<InvalidBlock>,
+}
+impl LinkVerifier {
+    fn new(protocol_magic: ProtocolMagic, previous: Option<HeaderHash>) -> Self {
+        LinkVerifier { protocol_magic, previous, previous_date: None, nr_blocks: 0, invalid_blocks: Vec::new() }
+    }
+
+    /// verify the block stored under the given hash, the blocks are
+    /// reported invalid under the hash they are stored with
+    fn verify(&mut self, hash: HeaderHash, blk: &block::Block) {
+        let header = blk.get_header();
+        let computed = header.compute_hash();
+        let date = header.get_blockdate();
+
+        if computed != hash {
+            self.invalid(hash.clone(), Some(date.clone()), Invalid::HashMismatch { computed: computed.clone() });
+        }
+        if let Err(err) = block::verify_block(self.protocol_magic, &computed, blk) {
+            self.invalid(hash.clone(), Some(date.clone()), Invalid::Block(err));
+        }
+        if let Some(expected) = self.previous.take() {
+            let found = header.get_previous_header();
+            if found != expected {
+                self.invalid(hash.clone(), Some(date.clone()), Invalid::BrokenLink { expected, found });
+            }
+        }
+        if let Some(previous) = self.previous_date.take() {
+            if ! date_follows(&previous, &date) {
+                self.invalid(hash.clone(), Some(date.clone()), Invalid::Date { previous: Some(previous) });
+            }
+        }
+
+        self.nr_blocks += 1;
+        self.previous = Some(hash);
+        self.previous_date = Some(date);
+    }
+
+    fn invalid(&mut self, hash: HeaderHash, date: Option<BlockDate>, reason: Invalid) {
+        self.invalid_blocks.push(InvalidBlock { hash, date, reason })
+    }
+}
+
+/// verify the internal links of the given packed epoch
+fn verify_epoch(storage_config: &StorageConfig, protocol_magic: ProtocolMagic, epoch_id: EpochId, cancel: &CancelToken)
+    -> Result<EpochReport>
+{
+    let mut verifier = LinkVerifier::new(protocol_magic, None);
+    let mut first = None;
+
+    for raw_block in iter::epoch::Iter::new(storage_config, epoch_id)? {
+        if cancel.is_cancelled() { return Err(Error::Cancelled); }
+        let blk = raw_block?.decode()?;
+        // the packs do not keep the hashes of their blocks
+        let hash = blk.get_header().compute_hash();
+        verifier.verify(hash.clone(), &blk);
+        if first.is_none() {
+            let header = blk.get_header();
+            let date = header.get_blockdate();
+            if date != BlockDate::Genesis(epoch_id) {
+                verifier.invalid(hash.clone(), Some(date.clone()), Invalid::Date { previous: None });
+            }
+            first = Some((hash, date, header.get_previous_header()));
+        }
+    }
+
+    Ok(EpochReport {
+        nr_blocks: verifier.nr_blocks,
+        first: first,
+        last: verifier.previous,
+        invalid_blocks: verifier.invalid_blocks,
+    })
+}
+
+impl Blockchain {
+    /// list the epochs (starting from the `epoch_start` of the config)
+    /// that have already been packed.
+    pub fn packed_epochs(&self) -> ::std::ops::Range<EpochId> {
+        let start = self.config.epoch_start;
+        let mut end = start;
+        while storage::epoch::epoch_read_pack(&self.storage_config, end).is_ok() {
+            end += 1;
+        }
+        start..end
+    }
+
+    /// verify the hash links of the whole local blockchain, from the
+    /// genesis to the local tip.
+    ///
+    /// fails with `Error::Cancelled` as soon as `cancel` is cancelled.
+    pub fn verify_links(&self, cancel: &CancelToken) -> Result<Report> {
+        let epochs = self.packed_epochs().map(|epoch_id| {
+            verify_epoch(&self.storage_config, self.config.protocol_magic, epoch_id, cancel)
+        }).collect::<Result<Vec<_>>>()?;
+
+        self.stitch(epochs, cancel)
+    }
+
+    /// same as `verify_links` but the packed epochs are verified concurrently
+    #[cfg(feature = "parallel")]
+    pub fn verify_links_parallel(&self, cancel: &CancelToken) -> Result<Report> {
+        let storage_config = &self.storage_config;
+        let protocol_magic = self.config.protocol_magic;
+        let epochs = self.packed_epochs().into_par_iter().map(|epoch_id| {
+            verify_epoch(storage_config, protocol_magic, epoch_id, cancel)
+        }).collect::<Result<Vec<_>>>()?;
+
+        self.stitch(epochs, cancel)
+    }
+
+    /// link the packed epochs together and verify the loose blocks
+    /// from the last packed epoch up to the local tip.
+    fn stitch(&self, epochs: Vec<EpochReport>, cancel: &CancelToken) -> Result<Report> {
+        let mut previous = self.config.genesis_prev.clone();
+        // the parent of the first block of a checkpoint is not stored
+        let mut check_link = ! self.is_from_checkpoint();
+        let mut nr_blocks = 0;
+        let mut invalid_blocks = Vec::new();
+
+        let first_loose_epoch = self.config.epoch_start + epochs.len() as EpochId;
+        for epoch in epochs {
+            if let Some((hash, date, parent)) = epoch.first {
+                if check_link && parent != previous {
+                    invalid_blocks.push(InvalidBlock {
+                        hash,
+                        date: Some(date),
+                        reason: Invalid::BrokenLink { expected: previous.clone(), found: parent }
+                    });
+                }
+            }
+            if let Some(last) = epoch.last { previous = last; }
+            check_link = true;
+            nr_blocks += epoch.nr_blocks;
+            invalid_blocks.extend(epoch.invalid_blocks);
+        }
+
+        // collect the loose blocks, walking back from the tip to the
+        // last block of the packed epochs
+        let mut loose_blocks = Vec::new();
+        let mut link = if check_link { Some(previous.clone()) } else { None };
+        let (tip, is_genesis) = self.load_tip()?;
+        let mut cur_hash = tip.hash;
+        while ! is_genesis && cur_hash != previous {
+            if cancel.is_cancelled() { return Err(Error::Cancelled); }
+            let blk = match storage::block_read(self.storage(), &cur_hash).map(|raw| raw.decode()) {
+                Some(Ok(blk)) => blk,
+                res => {
+                    // the blocks before this one can't be reached, start
+                    // verifying the links from the next block
+                    let reason = match res {
+                        Some(Err(err)) => Invalid::Decode(err),
+                        _              => Invalid::Missing,
+                    };
+                    invalid_blocks.push(InvalidBlock { hash: cur_hash, date: None, reason });
+                    link = None;
+                    break;
+                }
+            };
+            let date = blk.get_header().get_blockdate();
+            if date.get_epochid() < first_loose_epoch { break; }
+            let parent = blk.get_header().get_previous_header();
+            loose_blocks.push((cur_hash, blk));
+            cur_hash = parent;
+            if date.is_genesis() && date.get_epochid() == self.config.epoch_start { break; }
+        }
+
+        let mut verifier = LinkVerifier::new(self.config.protocol_magic, link);
+        while let Some((hash, blk)) = loose_blocks.pop() {
+            verifier.verify(hash, &blk);
+        }
+        nr_blocks += verifier.nr_blocks;
+        invalid_blocks.extend(verifier.invalid_blocks);
+
+        Ok(Report { nr_blocks, invalid_blocks, last_packed: previous })
+    }
+
+    /// move the corrupt loose blocks of the report to the quarantine
+    /// directory, the quarantined blocks are returned.
+    ///
+    /// The file of each quarantined block is named after its hash. The
+    /// blocks of the packed epochs cannot be taken out of their pack,
+    /// they are left in place.
+    ///
+    /// The local tip (and the remotes' tags beyond it) is moved back
+    /// before the first quarantined block first (see `rewind_to`): to its
+    /// parent, or to the last packed block if the block cannot be decoded
+    /// or is stored under another hash. The next sync downloads the blocks
+    /// again.
+    pub fn quarantine(&self, report: &Report) -> Result<Vec<HeaderHash>> {
+        let quarantine_dir = self.dir.join(QUARANTINE_DIRECTORY);
+        let blob_dir = self.dir.join("blob");
+
+        let corrupt : Vec<&InvalidBlock> = report.invalid_blocks.iter()
+            .filter(|invalid| invalid.reason.is_corrupt())
+            .filter(|invalid| {
+                let is_loose = blob_dir.join(format!("{}", invalid.hash)).is_file();
+                if ! is_loose { warn!("block {} is packed, it cannot be quarantined", invalid.hash); }
+                is_loose
+            })
+            .collect();
+
+        // the invalid blocks are in the order of the chain
+        if let Some(first) = corrupt.first() {
+            let before = match first.reason {
+                Invalid::Block(_) => {
+                    let raw_block = storage::block_read(self.storage(), &first.hash)
+                        .ok_or_else(|| Error::BlockNotFound(first.hash.clone()))?;
+                    raw_block.decode()?.get_header().get_previous_header()
+                },
+                _ => report.last_packed.clone(),
+            };
+            let target = self.block_ref_of(&before)?;
+            self.rewind_to(&target);
+        }
+
+        let mut quarantined = Vec::new();
+        for invalid in corrupt {
+            // a block may be corrupt for more than one reason
+            if quarantined.contains(&invalid.hash) { continue; }
+            let name = format!("{}", invalid.hash);
+            fs::create_dir_all(&quarantine_dir)?;
+            fs::rename(blob_dir.join(&name), quarantine_dir.join(&name))?;
+            quarantined.push(invalid.hash.clone());
+        }
+
+        Ok(quarantined)
+    }
+
+    /// the given stored block in the form of the tips, the genesis if it
+    /// is the `genesis_prev` of the configuration
+    fn block_ref_of(&self, hash: &HeaderHash) -> Result<BlockRef> {
+        if hash == &self.config.genesis_prev {
+            return Ok(self.genesis_ref().0);
+        }
+        let block = storage::block_read(self.storage(), hash)
+            .ok_or_else(|| Error::BlockNotFound(hash.clone()))?
+            .decode()?;
+        let header = block.get_header();
+        Ok(BlockRef {
+            hash: hash.clone(),
+            parent: header.get_previous_header(),
+            date: header.get_blockdate(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano_storage::{tag, types::header_to_blockhash};
+    use cbor_event::{se::Serializer, Len};
+    use exe_common::config::net::Config;
+
+    /// the CBOR of an epoch boundary block of the given epoch and parent
+    fn boundary_block(protocol_magic: u32, epoch: u64, parent: &HeaderHash) -> block::RawBlock {
+        let mut se = Serializer::new_vec();
+        se.write_array(Len::Len(2)).unwrap()
+          .write_unsigned_integer(0).unwrap()
+          .write_array(Len::Len(3)).unwrap();
+        // the header: protocol magic, parent, body proof, consensus, extra data
+        se.write_array(Len::Len(5)).unwrap()
+          .write_unsigned_integer(protocol_magic as u64).unwrap()
+          .write_bytes(parent.as_ref()).unwrap()
+          .write_bytes(&[0u8; 32]).unwrap()
+          .write_array(Len::Len(2)).unwrap()
+          .write_unsigned_integer(epoch).unwrap()
+          .write_array(Len::Len(1)).unwrap()
+          .write_unsigned_integer(epoch).unwrap()
+          .write_array(Len::Len(1)).unwrap()
+          .write_map(Len::Len(0)).unwrap();
+        // the body (no slot leaders) and the extra data
+        se.write_array(Len::Len(0)).unwrap()
+          .write_array(Len::Len(1)).unwrap()
+          .write_map(Len::Len(0)).unwrap();
+        block::RawBlock(se.finalize())
+    }
+
+    fn write_blob(blockchain: &Blockchain, hash: &HeaderHash, raw_block: &block::RawBlock) {
+        storage::blob::write(blockchain.storage(), &header_to_blockhash(hash), raw_block.as_ref()).unwrap();
+    }
+
+    #[test]
+    fn block_stored_under_another_hash() {
+        let root_dir = ::std::env::temp_dir().join(format!("cardano-cli-verify-{}", ::std::process::id()));
+        let blockchain = Blockchain::new(root_dir.clone(), "verify".to_owned(), Config::mainnet()).unwrap();
+        // the protocol magic of the mainnet
+        let protocol_magic = 764824073;
+
+        // the first boundary block is stored under a wrong hash, the
+        // next one points to it by that hash
+        let wrong = HeaderHash::try_from_slice(&[1u8; 32]).unwrap();
+        let first = boundary_block(protocol_magic, 0, &blockchain.config.genesis_prev);
+        write_blob(&blockchain, &wrong, &first);
+        let second = boundary_block(protocol_magic, 1, &wrong);
+        let tip = second.decode().unwrap().get_header().compute_hash();
+        write_blob(&blockchain, &tip, &second);
+        blockchain.save_tip(&tip);
+
+        let report = blockchain.verify_links(&CancelToken::new()).unwrap();
+        assert_eq!(report.nr_blocks, 2);
+        let mismatch = report.invalid_blocks.iter()
+            .find(|invalid| match invalid.reason { Invalid::HashMismatch { .. } => true, _ => false })
+            .expect("the hash mismatch is not reported");
+        assert_eq!(mismatch.hash, wrong);
+        assert!(mismatch.reason.is_corrupt());
+        // the link of the next block is the hash it is stored under
+        assert!(report.invalid_blocks.iter().all(|invalid| invalid.hash == wrong || invalid.hash == tip));
+        assert!(report.invalid_blocks.iter().all(|invalid| match invalid.reason { Invalid::BrokenLink { .. } => false, _ => true }));
+
+        let quarantined = blockchain.quarantine(&report).unwrap();
+        assert!(quarantined.contains(&wrong));
+        assert!(root_dir.join(QUARANTINE_DIRECTORY).join(format!("{}", wrong)).is_file());
+        // the local tip is moved back to the genesis
+        let tip = tag::read_hash(blockchain.storage(), super::super::LOCAL_BLOCKCHAIN_TIP_TAG);
+        assert_eq!(tip, Some(blockchain.config.genesis.clone()));
+
+        drop(blockchain);
+        ::std::fs::remove_dir_all(&root_dir).unwrap();
+    }
+
+    /// compare the durations of the sequential and parallel verifications
+    /// of an existing local blockchain, its root directory and name are
+    /// given by `CARDANO_CLI_BENCH_ROOT` and `CARDANO_CLI_BENCH_BLOCKCHAIN`:
+    ///
+    /// ```text
+    /// cargo test --release --features parallel -- --ignored --nocapture verify_links_parallel_speedup
+    /// ```
+    #[cfg(feature = "parallel")]
+    #[test]
+    #[ignore]
+    fn verify_links_parallel_speedup() {
+        use std::{env, path::PathBuf, time::Instant};
+
+        let root_dir = PathBuf::from(env::var("CARDANO_CLI_BENCH_ROOT").expect("CARDANO_CLI_BENCH_ROOT is not set"));
+        let name = env::var("CARDANO_CLI_BENCH_BLOCKCHAIN").expect("CARDANO_CLI_BENCH_BLOCKCHAIN is not set");
+        let blockchain = Blockchain::load_read_only(root_dir, name).unwrap();
+        let cancel = CancelToken::new();
+
+        let started = Instant::now();
+        let sequential = blockchain.verify_links(&cancel).unwrap();
+        let sequential_time = started.elapsed();
+
+        let started = Instant::now();
+        let parallel = blockchain.verify_links_parallel(&cancel).unwrap();
+        let parallel_time = started.elapsed();
+
+        println!("{} blocks in {} packed epochs: sequential {:?}, parallel {:?} ({} threads)",
+            sequential.nr_blocks, blockchain.packed_epochs().count(),
+            sequential_time, parallel_time, ::rayon::current_num_threads());
+        assert_eq!(parallel.nr_blocks, sequential.nr_blocks);
+        assert_eq!(parallel.invalid_blocks.len(), sequential.invalid_blocks.len());
+        assert_eq!(parallel.last_packed, sequential.last_packed);
+    }
+}
@@ -0,0 +1,341 @@
+//! keep local blockchains synchronised in the background
+//!
+//! The daemon pulls the given blockchains (see `blockchain pull`) every
+//! `interval`. A peer that fails is not tried again before an exponential
+//! backoff (capped to `max_backoff`), the other peers are still pulled.
+//! The blockchains are loaded again on every round, the remotes added or
+//! removed meanwhile are taken into account.
+//!
+//! A pidfile, locked for as long as the daemon runs, prevents two daemons
+//! from syncing the same root directory. A blockchain locked by another
+//! process (see `blockchain::lock`) is skipped until the next round.
+//!
+//! The daemon stops once interrupted (`SIGINT`, `SIGTERM`, see
+//! `utils::cancel::interrupted`): the sync in progress stops at the last
+//! consistent block and the pidfile is removed.
+//!
+//! With `metrics`, the metrics of the syncs and of the blockchains are
+//! served on `GET /metrics` (see `blockchain::metrics`).
+//!
+
+use std::{fmt, fs, io, process, thread};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use fs2::{self, FileExt};
+use humantime::format_duration;
+
+use utils::{term::Term, cancel::CancelToken};
+use blockchain::{self, Blockchain, lock, peer, protocol::Protocol};
+
+/// the name of the pidfile, in the root directory, if none is given
+pub const DEFAULT_PIDFILE : &'static str = "daemon.pid";
+
+/// the time between two checks of the interruption while waiting
+const POLL_INTERVAL : Duration = Duration::from_millis(200);
+
+/// the time given to a client of the metrics to send its request
+const METRICS_READ_TIMEOUT : Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// the time between two pulls of the blockchains
+    pub interval: Duration,
+    /// the maximum time before retrying a failing peer
+    pub max_backoff: Duration,
+    pub pidfile: PathBuf,
+    /// the address (`host:port`) to serve the metrics on, if any
+    pub metrics: Option<String>,
+}
+
+/// the failures of a peer, to delay the next attempt
+#[derive(Debug, Clone, Default)]
+struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+impl Backoff {
+    /// `interval` doubled for every failure after the first one
+    fn delay(&self, interval: Duration, max: Duration) -> Duration {
+        let factor = 1u32.checked_shl(self.failures.saturating_sub(1)).unwrap_or(u32::max_value());
+        match interval.checked_mul(factor) {
+            Some(delay) if delay < max => delay,
+            _ => max,
+        }
+    }
+
+    fn is_ready(&self, now: Instant) -> bool {
+        self.retry_at.map(|retry_at| now >= retry_at).unwrap_or(true)
+    }
+
+    fn failed(&mut self, now: Instant, config: &Config) -> Duration {
+        self.failures += 1;
+        let delay = self.delay(config.interval, config.max_backoff);
+        self.retry_at = Some(now + delay);
+        delay
+    }
+}
+
+/// the pidfile of the running daemon, exclusively locked until dropped,
+/// then removed
+pub struct PidFile {
+    path: PathBuf,
+    file: fs::File,
+}
+impl PidFile {
+    /// lock the given file and write the pid of the current process in it
+    ///
+    /// fails if the file is locked by another daemon. The lock is released
+    /// by the system when the daemon exits, even if it is killed: a stale
+    /// pidfile does not prevent the next daemon from starting.
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+        loop {
+            let mut file = fs::OpenOptions::new().read(true).write(true).create(true).open(&path)?;
+            match file.try_lock_exclusive() {
+                Ok(()) => {},
+                Err(ref err) if err.kind() == fs2::lock_contended_error().kind() => {
+                    let mut pid = String::new();
+                    file.read_to_string(&mut pid)?;
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("a daemon is already running (pid {}, see {:?})", pid.trim(), path)
+                    ));
+                },
+                Err(err) => return Err(err),
+            }
+            // the daemon holding the lock removed the file before releasing it
+            if ! path.exists() { continue; }
+
+            file.set_len(0)?;
+            write!(file, "{}\n", process::id())?;
+            file.sync_all()?;
+            return Ok(PidFile { path, file });
+        }
+    }
+}
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // removed while still locked, see `create`
+        let _ = fs::remove_file(&self.path);
+        let _ = self.file.unlock();
+    }
+}
+
+#[derive(Debug)]
+enum PeerError {
+    /// the connection failed (the details are printed by `Peer::connect`)
+    Unreachable,
+    Sync(blockchain::Error),
+}
+impl From<blockchain::Error> for PeerError {
+    fn from(e: blockchain::Error) -> Self { PeerError::Sync(e) }
+}
+impl fmt::Display for PeerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerError::Unreachable => write!(f, "peer unreachable"),
+            PeerError::Sync(err)   => write!(f, "{}", err),
+        }
+    }
+}
+
+fn sync_peer(term: &mut Term, blockchain: &Blockchain, name: &str, cancel: &CancelToken) -> Result<(), PeerError> {
+    let peer = peer::Peer::prepare(blockchain, name.to_owned())?;
+    let connected_peer = peer.connect(term).map_err(|()| PeerError::Unreachable)?;
+    let sync_config = peer::SyncConfig { cancel: cancel.clone(), ..peer::SyncConfig::default() };
+    connected_peer.sync_with(term, &sync_config)?;
+    Ok(())
+}
+
+/// pull the peers of the blockchain which are not backing off, then
+/// forward the local tip to the best of the remotes' tips
+///
+/// the peers are probed first, all at once (see `peer::probe_peers`):
+/// the peers not sending their tip in time are backed off. The HTTP
+/// bridges are only pulled if no native peer could be synced with (see
+/// `blockchain::protocol`). Nothing more is done once `cancel` is
+/// cancelled.
+fn pull(term: &mut Term, blockchain: &Blockchain, backoffs: &mut BTreeMap<(String, String), Backoff>, config: &Config, cancel: &CancelToken) {
+    let now = Instant::now();
+    let ready : Vec<String> = blockchain.sync_peers().into_iter().filter(|alias| {
+        let ready = backoffs.get(&(blockchain.name.clone(), alias.clone())).map(|backoff| backoff.is_ready(now)).unwrap_or(true);
+        if ! ready { debug!("{}: skipping {}, backing off", blockchain.name, alias); }
+        ready
+    }).collect();
+
+    let probes = peer::probe_peers(blockchain, term, &ready, peer::DEFAULT_TIP_PROBE_TIMEOUT);
+    for alias in ready.iter().filter(|alias| ! probes.iter().any(|probe| &probe.name == *alias)) {
+        let backoff = backoffs.entry((blockchain.name.clone(), alias.clone())).or_insert_with(Backoff::default);
+        let delay = backoff.failed(now, config);
+        term.warn(&format!("{}: cannot probe {}, retrying in {}\n", blockchain.name, alias, format_duration(delay))).unwrap();
+    }
+
+    let mut synced = false;
+    for probe in probes {
+        if cancel.is_cancelled() { return; }
+        if synced && probe.protocol != Protocol::Native { break; }
+
+        let backoff = backoffs.entry((blockchain.name.clone(), probe.name.clone())).or_insert_with(Backoff::default);
+        match sync_peer(term, blockchain, &probe.name, cancel) {
+            Ok(()) => {
+                *backoff = Backoff::default();
+                synced = true;
+            },
+            Err(PeerError::Sync(blockchain::Error::Cancelled)) => return,
+            Err(err) => {
+                let delay = backoff.failed(now, config);
+                term.warn(&format!("{}: cannot sync with {} ({}), retrying in {}\n",
+                    blockchain.name, probe.name, err, format_duration(delay))).unwrap();
+            }
+        }
+    }
+
+    let initial_tip = match blockchain.load_tip() {
+        Ok((tip, _is_genesis)) => tip,
+        Err(err) => {
+            term.warn(&format!("{}: cannot load the local tip ({})\n", blockchain.name, err)).unwrap();
+            return;
+        },
+    };
+    let tip = match blockchain.best_tip() {
+        Ok((tip, errors)) => {
+            for (alias, err) in errors {
+                term.warn(&format!("{}: ignoring the tip of {} ({})\n", blockchain.name, alias, err)).unwrap();
+            }
+            tip
+        },
+        Err(err) => {
+            term.warn(&format!("{}: cannot load the local tip ({})\n", blockchain.name, err)).unwrap();
+            return;
+        },
+    };
+    if tip.hash != initial_tip.hash {
+        blockchain.save_tip(&tip.hash);
+        term.success(&format!("{}: local tip forwarded to {} ({})\n", blockchain.name, tip.hash, tip.date)).unwrap();
+    }
+}
+
+/// serve `GET /metrics` on the given address, from a dedicated thread
+///
+/// the metrics are a plain text reply, a minimal HTTP reply is enough.
+fn serve_metrics(listen: &str, root_dir: PathBuf, names: Vec<String>) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Err(err) => {
+                    warn!("cannot accept the metrics request: {}", err);
+                    continue;
+                },
+                Ok(stream) => stream,
+            };
+
+            // a client not sending its request would hold up the others
+            if let Err(err) = stream.set_read_timeout(Some(METRICS_READ_TIMEOUT)) {
+                warn!("cannot set the timeout of the metrics request: {}", err);
+                continue;
+            }
+            let mut request_line = String::new();
+            if let Err(err) = BufReader::new(&stream).read_line(&mut request_line) {
+                warn!("cannot read the metrics request: {}", err);
+                continue;
+            }
+            let response = if request_line.starts_with("GET /metrics ") {
+                // not locked, the syncs of the daemon are not waited for
+                let blockchains : Vec<Blockchain> = names.iter()
+                    .filter_map(|name| Blockchain::load_from(blockchain::config::directory(root_dir.clone(), name), name.clone()).ok())
+                    .collect();
+                let body = blockchain::metrics::render(&blockchains);
+                format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+            };
+            if let Err(err) = stream.write_all(response.as_bytes()) {
+                warn!("cannot send the metrics: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// wait for `duration`, returns `false` if `cancel` is cancelled meanwhile
+fn wait(duration: Duration, cancel: &CancelToken) -> bool {
+    let started = Instant::now();
+    while ! cancel.is_cancelled() {
+        let elapsed = started.elapsed();
+        if elapsed >= duration { return true; }
+        thread::sleep(POLL_INTERVAL.min(duration - elapsed));
+    }
+    false
+}
+
+/// pull the given blockchains every `config.interval`, until `cancel` is
+/// cancelled
+pub fn run( mut term: Term
+          , root_dir: PathBuf
+          , names: Vec<String>
+          , config: Config
+          , cancel: CancelToken
+          )
+{
+    let pidfile = PidFile::create(config.pidfile.clone()).unwrap_or_else(|e| term.fail_with(e));
+
+    term.info(&format!("pulling {} every {}\n", names.join(", "), format_duration(config.interval))).unwrap();
+    if let Some(ref listen) = config.metrics {
+        serve_metrics(listen, root_dir.clone(), names.clone()).unwrap_or_else(|e| term.fail_with(e));
+        term.info(&format!("serving the metrics on http://{}/metrics\n", listen)).unwrap();
+    }
+
+    let mut backoffs = BTreeMap::new();
+    loop {
+        for name in names.iter() {
+            if cancel.is_cancelled() { break; }
+            match Blockchain::load_with(root_dir.clone(), name.clone(), lock::Mode::Exclusive, lock::timeout()) {
+                Ok(blockchain) => match blockchain.open_storage() {
+                    Ok(_) => pull(&mut term, &blockchain, &mut backoffs, &config, &cancel),
+                    Err(err) => term.warn(&format!("{}: skipped ({})\n", name, err)).unwrap(),
+                },
+                Err(err) => term.warn(&format!("{}: skipped ({})\n", name, err)).unwrap(),
+            }
+        }
+        if ! wait(config.interval, &cancel) { break; }
+    }
+
+    term.info("interrupted, stopping the daemon\n").unwrap();
+    drop(pidfile);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_delay() {
+        let interval = Duration::from_secs(60);
+        let max = Duration::from_secs(3600);
+        let delay = |failures| Backoff { failures, retry_at: None }.delay(interval, max);
+
+        assert_eq!(delay(1), Duration::from_secs(60));
+        assert_eq!(delay(2), Duration::from_secs(120));
+        assert_eq!(delay(3), Duration::from_secs(240));
+        assert_eq!(delay(7), max);
+        assert_eq!(delay(100), max);
+    }
+
+    #[test]
+    fn pidfile_locked() {
+        let path = ::std::env::temp_dir().join(format!("cardano-cli-daemon-{}.pid", process::id()));
+        let pidfile = PidFile::create(path.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", process::id()));
+
+        let err = PidFile::create(path.clone()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        drop(pidfile);
+        assert!(! path.exists());
+        drop(PidFile::create(path.clone()).unwrap());
+    }
+}
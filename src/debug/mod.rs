@@ -1,7 +1,9 @@
 use cardano::{address::{ExtendedAddr, StakeDistribution}, util::{base58, hex, try_from_slice::{TryFromSlice}}, hash};
+use cardano::{tx::{self, Tx, TxAux, TxInWitness}, fee::{LinearFee, FeeAlgorithm}};
+use cbor_event::{self, de::Deserializer, Len};
 use utils::term::Term;
-use std::io::{self, Read};
-use super::blockchain::parse_genesis_data;
+use std::{fs, io::{self, Cursor, Read}, path::{Path, PathBuf}};
+use super::blockchain::{self, parse_genesis_data};
 
 pub fn command_address( mut term: Term
                       , address: String
@@ -39,6 +41,269 @@ pub fn command_address( mut term: Term
     }
 }
 
+/// the key of the network magic in the attributes of the addresses
+const NETWORK_MAGIC_ATTRIBUTE : u64 = 2;
+
+#[derive(Serialize)]
+struct AddressReport {
+    address: String,
+    address_type: String,
+    root: String,
+    /// the encrypted derivation path (the random index wallets)
+    derivation_payload: Option<String>,
+    stake_distribution: String,
+    /// `None` for the addresses of the mainnet
+    network_magic: Option<u64>,
+}
+
+/// the network magic of the address, read from the raw attributes: the
+/// CBOR of an address is `[ #24(bytes [ root, { attributes }, type ]), crc ]`
+fn address_network_magic(bytes: &[u8]) -> cbor_event::Result<Option<u64>> {
+    let mut raw = Deserializer::from(Cursor::new(bytes));
+    raw.array()?;
+    raw.tag()?;
+    let payload = raw.bytes()?;
+
+    let mut raw = Deserializer::from(Cursor::new(payload));
+    raw.array()?;
+    raw.bytes()?;
+    let len = match raw.map()? {
+        Len::Len(len) => len,
+        Len::Indefinite => return Ok(None),
+    };
+    for _ in 0..len {
+        let key = raw.unsigned_integer()?;
+        let value = raw.bytes()?;
+        if key == NETWORK_MAGIC_ATTRIBUTE {
+            return Ok(Some(Deserializer::from(Cursor::new(value)).unsigned_integer()?));
+        }
+    }
+    Ok(None)
+}
+
+/// decode the address and all its attributes
+pub fn inspect_address( mut term: Term
+                      , address: String
+                      )
+{
+    let bytes = base58::decode(&address).unwrap_or_else(|err| {
+        term.error(&format!("Invalid Address, should be encoded in base58: {}\n", err)).unwrap();
+        ::std::process::exit(1)
+    });
+    let extended = ExtendedAddr::try_from_slice(&bytes).unwrap_or_else(|err| {
+        term.error(&format!("Invalid Address: {:?}\n", err)).unwrap();
+        ::std::process::exit(2)
+    });
+    let network_magic = address_network_magic(&bytes).unwrap_or_else(|e| term.fail_with(e));
+
+    let report = AddressReport {
+        address: address,
+        address_type: format!("{}", extended.addr_type),
+        root: format!("{}", extended.addr),
+        derivation_payload: extended.attributes.derivation_path.as_ref().map(|payload| hex::encode(payload.as_ref())),
+        stake_distribution: match extended.attributes.stake_distribution {
+            StakeDistribution::BootstrapEraDistr => "bootstrap era".to_owned(),
+            StakeDistribution::SingleKeyDistr(ref id) => format!("single key {}", id),
+        },
+        network_magic: network_magic,
+    };
+    if term.is_json() {
+        term.json(&report).unwrap();
+        return;
+    }
+
+    term.success("Cardano Extended Address\n").unwrap();
+    term.info(&format!("  - address type:       {}\n", report.address_type)).unwrap();
+    term.info(&format!("  - root (hash):        {}\n", report.root)).unwrap();
+    match report.derivation_payload {
+        None => term.info("  - derivation payload: none (sequential wallet, or redeem address)\n").unwrap(),
+        Some(ref payload) => term.info(&format!("  - derivation payload: {} (encrypted with the wallet's root key)\n", payload)).unwrap(),
+    }
+    term.info(&format!("  - stake distribution: {}\n", report.stake_distribution)).unwrap();
+    match report.network_magic {
+        None => term.info("  - network magic:      none (mainnet)\n").unwrap(),
+        Some(magic) => term.info(&format!("  - network magic:      {}\n", magic)).unwrap(),
+    }
+}
+
+#[derive(Serialize)]
+struct DecodedInput {
+    txid: String,
+    index: u32,
+    /// the spent output, `None` if it has not been resolved (see
+    /// `decode_tx`)
+    address: Option<String>,
+    value: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct DecodedOutput {
+    address: String,
+    value: u64,
+}
+
+#[derive(Serialize)]
+struct DecodedWitness {
+    /// `public key`, `script` or `redeem`
+    kind: &'static str,
+    public_key: Option<String>,
+    signature: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DecodedTransaction {
+    txid: String,
+    size: usize,
+    inputs: Vec<DecodedInput>,
+    outputs: Vec<DecodedOutput>,
+    witnesses: Vec<DecodedWitness>,
+    /// the fee of the transaction, `None` if some inputs are not resolved
+    /// or if the outputs exceed the inputs (see `deficit`)
+    fee: Option<u64>,
+    /// how much the outputs exceed the inputs: the transaction is invalid
+    deficit: Option<u64>,
+    /// the minimal fee for the size of the transaction (see `LinearFee`)
+    minimal_fee: u64,
+}
+
+/// the bytes of the transaction: the content of the file (raw CBOR, or
+/// hexadecimal), or the hexadecimal string itself
+fn read_transaction_bytes(input: &str) -> Result<Vec<u8>, String> {
+    if Path::new(input).is_file() {
+        let bytes = fs::read(input).map_err(|err| format!("cannot read {}: {}", input, err))?;
+        let decoded = ::std::str::from_utf8(&bytes).ok().and_then(|text| hex::decode(text.trim()).ok());
+        return Ok(decoded.unwrap_or(bytes));
+    }
+    hex::decode(input.trim()).map_err(|_| format!("`{}' is neither a file nor a transaction in hexadecimal", input))
+}
+
+/// decode a signed transaction (`TxAux`) or only the transaction (`Tx`)
+fn decode_transaction(bytes: &[u8]) -> cbor_event::Result<(Tx, Vec<TxInWitness>)> {
+    match Deserializer::from(Cursor::new(bytes)).deserialize::<TxAux>() {
+        Ok(txaux) => {
+            let witnesses = txaux.witness.iter().cloned().collect();
+            Ok((txaux.tx, witnesses))
+        },
+        Err(_) => Ok((Deserializer::from(Cursor::new(bytes)).deserialize::<Tx>()?, Vec::new())),
+    }
+}
+
+fn decode_witness(witness: &TxInWitness) -> DecodedWitness {
+    match witness {
+        TxInWitness::PkWitness(xpub, signature) => DecodedWitness {
+            kind: "public key",
+            public_key: Some(format!("{}", xpub)),
+            signature: Some(format!("{}", signature)),
+        },
+        TxInWitness::RedeemWitness(public_key, signature) => DecodedWitness {
+            kind: "redeem",
+            public_key: Some(format!("{}", public_key)),
+            signature: Some(format!("{}", signature)),
+        },
+        TxInWitness::ScriptWitness(..) => DecodedWitness { kind: "script", public_key: None, signature: None },
+    }
+}
+
+/// decode the given transaction, in hexadecimal or in a file
+///
+/// the inputs are resolved with the indexes of the given blockchain, if
+/// any (see `blockchain index build`), to compute the fee.
+pub fn decode_tx( mut term: Term
+                , root_dir: PathBuf
+                , input: String
+                , blockchain_name: Option<String>
+                )
+{
+    let bytes = read_transaction_bytes(&input).unwrap_or_else(|err| {
+        term.error(&format!("{}\n", err)).unwrap();
+        ::std::process::exit(1)
+    });
+    let (tx, witnesses) = decode_transaction(&bytes).unwrap_or_else(|e| term.fail_with(e));
+    let blockchain = blockchain_name.map(|name| blockchain::commands::load_read_only(&mut term, root_dir, name));
+
+    let mut input_total = Some(0u64);
+    let mut inputs = Vec::new();
+    for txin in tx.inputs.iter() {
+        let output = match blockchain {
+            None => None,
+            Some(ref blockchain) => blockchain.query_output(txin).unwrap_or_else(|e| term.fail_with(e)),
+        };
+        let value = output.as_ref().map(|output| u64::from(output.value));
+        input_total = input_total.and_then(|total| value.map(|value| total + value));
+        inputs.push(DecodedInput {
+            txid: format!("{}", txin.id),
+            index: txin.index,
+            address: output.map(|output| format!("{}", output.address)),
+            value: value,
+        });
+    }
+    let outputs : Vec<DecodedOutput> = tx.outputs.iter().map(|txout| DecodedOutput {
+        address: format!("{}", txout.address),
+        value: u64::from(txout.value),
+    }).collect();
+    let output_total : u64 = outputs.iter().map(|output| output.value).sum();
+
+    // an unsigned transaction is estimated with one witness per input
+    let fee_witnesses = if witnesses.is_empty() {
+        ::std::iter::repeat(TxInWitness::fake()).take(tx.inputs.len()).collect()
+    } else {
+        witnesses.clone()
+    };
+    let (fee, deficit) = match input_total {
+        None => (None, None),
+        Some(input_total) if input_total < output_total => (None, Some(output_total - input_total)),
+        Some(input_total) => (Some(input_total - output_total), None),
+    };
+    let minimal_fee = LinearFee::default().calculate_for_txaux_component(&tx, &fee_witnesses)
+        .unwrap_or_else(|e| term.fail_with(e));
+
+    let decoded = DecodedTransaction {
+        txid: format!("{}", tx.id()),
+        size: tx::txaux_serialize_size(&tx, &fee_witnesses),
+        inputs: inputs,
+        outputs: outputs,
+        witnesses: witnesses.iter().map(decode_witness).collect(),
+        fee: fee,
+        deficit: deficit,
+        minimal_fee: minimal_fee.to_coin().into(),
+    };
+    if term.is_json() {
+        term.json(&decoded).unwrap();
+        return;
+    }
+
+    term.success(&format!("Transaction {}\n", decoded.txid)).unwrap();
+    term.info(&format!("  - size:        {} bytes\n", decoded.size)).unwrap();
+    term.info("  - inputs:\n").unwrap();
+    for input in decoded.inputs.iter() {
+        term.simply(&format!("     * {}.{}", input.txid, input.index)).unwrap();
+        if let (Some(address), Some(value)) = (&input.address, input.value) {
+            term.simply(&format!(" {} {}", address, value)).unwrap();
+        }
+        term.simply("\n").unwrap();
+    }
+    term.info("  - outputs:\n").unwrap();
+    for output in decoded.outputs.iter() {
+        term.simply(&format!("     * {} {}\n", output.address, output.value)).unwrap();
+    }
+    term.info("  - witnesses:\n").unwrap();
+    if decoded.witnesses.is_empty() {
+        term.simply("     none, the transaction is not signed\n").unwrap();
+    }
+    for witness in decoded.witnesses.iter() {
+        term.simply(&format!("     * {}", witness.kind)).unwrap();
+        if let (Some(public_key), Some(signature)) = (&witness.public_key, &witness.signature) {
+            term.simply(&format!(" {} (signature {})", public_key, signature)).unwrap();
+        }
+        term.simply("\n").unwrap();
+    }
+    match (decoded.fee, decoded.deficit) {
+        (Some(fee), _) => term.info(&format!("  - fee:         {} (at least {})\n", fee, decoded.minimal_fee)).unwrap(),
+        (None, Some(deficit)) => term.error(&format!("  - fee:         none, the outputs exceed the inputs by {}: the transaction is invalid\n", deficit)).unwrap(),
+        (None, None) => term.info(&format!("  - fee:         unknown, the inputs are not resolved (at least {})\n", decoded.minimal_fee)).unwrap(),
+    }
+}
+
 /// Read a JSON file from stdin and write its canonicalized form to stdout.
 pub fn canonicalize_json()
 {
@@ -54,3 +319,86 @@ pub fn hash()
     io::stdin().read_to_end(&mut data).expect("Cannot read stdin.");
     println!("{}", hash::Blake2b256::new(&data));
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{hdwallet, config::ProtocolMagic, tx::{TxId, TxOut, TxoPointer}, coin::Coin};
+    use cbor_event::se::Serializer;
+
+    fn xprv() -> hdwallet::XPrv {
+        hdwallet::XPrv::normalize_bytes([3; hdwallet::XPRV_SIZE])
+    }
+
+    #[test]
+    fn network_magic_of_mainnet_address() {
+        let address = ExtendedAddr::new_simple(xprv().public());
+        let mut se = Serializer::new_vec();
+        se.serialize(&address).unwrap();
+        assert_eq!(address_network_magic(&se.finalize()).unwrap(), None);
+    }
+
+    #[test]
+    fn network_magic_of_testnet_address() {
+        let mut magic = Serializer::new_vec();
+        magic.write_unsigned_integer(1097911063).unwrap();
+        let magic = magic.finalize();
+
+        let mut payload = Serializer::new_vec();
+        payload.write_array(Len::Len(3)).unwrap()
+            .write_bytes(&[0; 28]).unwrap()
+            .write_map(Len::Len(1)).unwrap()
+            .write_unsigned_integer(NETWORK_MAGIC_ATTRIBUTE).unwrap()
+            .write_bytes(&magic).unwrap()
+            .write_unsigned_integer(0).unwrap();
+        let payload = payload.finalize();
+
+        // the checksum is not verified
+        let mut address = Serializer::new_vec();
+        address.write_array(Len::Len(2)).unwrap()
+            .write_tag(24).unwrap()
+            .write_bytes(&payload).unwrap()
+            .write_unsigned_integer(0).unwrap();
+        assert_eq!(address_network_magic(&address.finalize()).unwrap(), Some(1097911063));
+    }
+
+    fn transaction() -> Tx {
+        let mut tx = Tx::new();
+        tx.inputs.push(TxoPointer { id: TxId::new(&[1, 2, 3]), index: 1 });
+        tx.outputs.push(TxOut { address: ExtendedAddr::new_simple(xprv().public()), value: Coin::new(1_000_000).unwrap() });
+        tx
+    }
+
+    #[test]
+    fn decode_unsigned_transaction() {
+        let tx = transaction();
+        let mut se = Serializer::new_vec();
+        se.serialize(&tx).unwrap();
+
+        let (decoded, witnesses) = decode_transaction(&se.finalize()).unwrap();
+        assert_eq!(decoded.id(), tx.id());
+        assert!(witnesses.is_empty());
+    }
+
+    #[test]
+    fn decode_signed_transaction() {
+        let tx = transaction();
+        let witness = TxInWitness::new(ProtocolMagic::from(764824073), &xprv(), &tx.id());
+        // a `TxAux` is `[ tx, [ witnesses ] ]`
+        let mut se = Serializer::new_vec();
+        se.write_array(Len::Len(2)).unwrap()
+            .serialize(&tx).unwrap()
+            .write_array(Len::Len(1)).unwrap()
+            .serialize(&witness).unwrap();
+
+        let (decoded, witnesses) = decode_transaction(&se.finalize()).unwrap();
+        assert_eq!(decoded.id(), tx.id());
+        assert_eq!(witnesses.len(), 1);
+        assert!(witnesses[0].verify_tx(ProtocolMagic::from(764824073), &decoded));
+    }
+
+    #[test]
+    fn decode_garbage() {
+        assert!(decode_transaction(&[0x82, 0x01]).is_err());
+    }
+}
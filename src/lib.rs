@@ -18,7 +18,14 @@ extern crate rand;
 #[macro_use]
 extern crate log;
 extern crate humantime;
+extern crate fs2;
 extern crate base64;
+extern crate tar;
+extern crate ctrlc;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "serve")]
+extern crate tiny_http;
 
 #[macro_use]
 pub mod utils;
@@ -26,3 +33,6 @@ pub mod blockchain;
 pub mod wallet;
 pub mod transaction;
 pub mod debug;
+pub mod daemon;
+#[cfg(feature = "serve")]
+pub mod serve;
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::io::Write;
 
 extern crate dirs;
 extern crate cardano_cli;
@@ -6,8 +7,10 @@ extern crate cardano;
 extern crate log;
 extern crate env_logger;
 
-use self::cardano_cli::utils::term;
-use self::cardano_cli::{blockchain, wallet, transaction, debug};
+use self::cardano_cli::utils::{term, logging, cancel};
+use self::cardano_cli::{blockchain, wallet, transaction, debug, daemon};
+#[cfg(feature = "serve")]
+use self::cardano_cli::serve;
 
 #[macro_use]
 extern crate clap;
@@ -16,7 +19,7 @@ use clap::{Arg, App, SubCommand, ArgMatches};
 fn main() {
     let default_root_dir = get_default_root_dir();
 
-    let matches = App::new(crate_name!())
+    let app = App::new(crate_name!())
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
@@ -24,15 +27,22 @@ fn main() {
         .arg(global_verbose_definition())
         .arg(global_quiet_definition())
         .arg(global_color_definition())
+        .arg(global_output_format_definition())
+        .arg(global_log_format_definition())
+        .arg(global_no_wait_definition())
         .arg(global_rootdir_definition(&default_root_dir))
 
         .subcommand(blockchain_commands_definition())
         .subcommand(wallet_commands_definition())
         .subcommand(transaction_commands_definition())
         .subcommand(debug_commands_definition())
-        .get_matches();
+        .subcommand(daemon_commands_definition());
+    #[cfg(feature = "serve")]
+    let app = app.subcommand(serve_commands_definition());
+    let matches = app.get_matches();
 
     let mut term = term::Term::new(configure_terminal(&matches));
+    blockchain::lock::init(global_no_wait_option(&matches));
 
     let root_dir = global_rootdir_match(&default_root_dir, &matches);
 
@@ -41,6 +51,9 @@ fn main() {
         (WALLET_COMMAND, Some(matches))      => { subcommand_wallet(term, root_dir, matches) },
         (TRANSACTION_COMMAND, Some(matches)) => { subcommand_transaction(term, root_dir, matches) },
         (DEBUG_COMMAND, Some(matches))       => { subcommand_debug(term, root_dir, matches) },
+        (DAEMON_COMMAND, Some(matches))      => { subcommand_daemon(term, root_dir, matches) },
+        #[cfg(feature = "serve")]
+        (SERVE_COMMAND, Some(matches))       => { subcommand_serve(term, root_dir, matches) },
         _ => {
             term.error(matches.usage()).unwrap();
             ::std::process::exit(1)
@@ -89,6 +102,16 @@ fn global_quiet_option<'a>(matches: &ArgMatches<'a>) -> bool {
     matches.is_present("QUIET")
 }
 
+fn global_no_wait_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("NO_WAIT")
+        .long("no-wait")
+        .global(true)
+        .help("fail at once if a blockchain is in use by another process, rather than waiting for it (60 seconds at most)")
+}
+fn global_no_wait_option<'a>(matches: &ArgMatches<'a>) -> bool {
+    matches.is_present("NO_WAIT")
+}
+
 fn global_color_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("COLOR")
         .long("color")
@@ -111,6 +134,39 @@ fn global_color_option<'a>(matches: &ArgMatches<'a>) -> term::ColorChoice {
         }
     }
 }
+fn global_output_format_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("OUTPUT_FORMAT")
+        .long("output-format")
+        .takes_value(true)
+        .default_value("text")
+        .possible_values(&["text", "json"])
+        .global(true)
+        .help("format of the output of the commands (`blockchain status', `wallet statement', `transaction status'...)")
+}
+fn global_output_format_option<'a>(matches: &ArgMatches<'a>) -> term::OutputFormat {
+    match matches.value_of("OUTPUT_FORMAT") {
+        None         => term::OutputFormat::Text,
+        Some("text") => term::OutputFormat::Text,
+        Some("json") => term::OutputFormat::Json,
+        Some(&_) => {
+            // this should not be reachable `clap` will perform validation
+            // checking of the possible_values given when creating the argument
+            unreachable!()
+        }
+    }
+}
+fn global_log_format_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("LOG_FORMAT")
+        .long("log-format")
+        .takes_value(true)
+        .default_value("text")
+        .possible_values(&["text", "json"])
+        .global(true)
+        .help("format of the logs (see `--verbose'): text, or one JSON object per line")
+}
+fn global_log_format_option<'a>(matches: &ArgMatches<'a>) -> logging::LogFormat {
+    value_t!(matches, "LOG_FORMAT", logging::LogFormat).unwrap_or_else(|e| e.exit())
+}
 fn global_verbose_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("VERBOSITY")
         .long("verbose")
@@ -126,7 +182,9 @@ fn global_verbose_option<'a>(matches: &ArgMatches<'a>) -> u64 {
 fn configure_terminal<'a>(matches: &ArgMatches<'a>) -> term::Config {
     let quiet = global_quiet_option(matches);
     let color = global_color_option(matches);
+    let output = global_output_format_option(matches);
     let verbosity = global_verbose_option(matches);
+    let log_format = global_log_format_option(matches);
 
     if ! quiet {
         let log_level = match verbosity {
@@ -135,14 +193,19 @@ fn configure_terminal<'a>(matches: &ArgMatches<'a>) -> term::Config {
             2 => log::LevelFilter::Debug,
             _ => log::LevelFilter::Trace,
         };
-        env_logger::Builder::from_default_env()
-            .filter_level(log_level)
-            .init();
+        let mut builder = env_logger::Builder::from_default_env();
+        builder.filter_level(log_level);
+        if log_format == logging::LogFormat::Json {
+            builder.format(|buf, record| writeln!(buf, "{}", logging::format_json(record)));
+        }
+        builder.init();
+        logging::init(log_format);
     }
 
     term::Config {
         color: color,
-        quiet: quiet
+        quiet: quiet,
+        output: output
     }
 }
 
@@ -220,15 +283,25 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
         ("new", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let net_config = blockchain_argument_template_match(&matches);
+            let checkpoint = if matches.is_present("BLOCKCHAIN_CHECKPOINT") {
+                Some(value_t!(matches, "BLOCKCHAIN_CHECKPOINT", blockchain::checkpoint::Checkpoint).unwrap_or_else(|e| e.exit()))
+            } else {
+                None
+            };
 
-            blockchain::commands::new(term, root_dir, name, net_config);
+            blockchain::commands::new(term, root_dir, name, net_config, checkpoint);
         },
         ("remote-add", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let alias = blockchain_argument_remote_alias_match(&matches);
             let endpoint = blockchain_argument_remote_endpoint_match(&matches);
+            let protocol = if matches.is_present("REMOTE_ADD_PROTOCOL") {
+                Some(value_t!(matches, "REMOTE_ADD_PROTOCOL", blockchain::protocol::Protocol).unwrap_or_else(|e| e.exit()))
+            } else {
+                None
+            };
 
-            blockchain::commands::remote_add(term, root_dir, name, alias, endpoint);
+            blockchain::commands::remote_add(term, root_dir, name, alias, endpoint, protocol);
         },
         ("remote-rm", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
@@ -254,7 +327,9 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
                 blockchain::commands::RemoteDetail::Short
             };
 
-            blockchain::commands::remote_ls(term, root_dir, name, detailed);
+            let timeout = value_t!(matches, "REMOTE_LS_TIMEOUT", u64).unwrap_or_else(|e| e.exit());
+
+            blockchain::commands::remote_ls(term, root_dir, name, detailed, ::std::time::Duration::from_secs(timeout));
         },
         ("forward", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
@@ -264,8 +339,93 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
         },
         ("pull", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
+            let pinned = matches.value_of("PULL_PEER").map(|alias| alias.to_owned());
 
-            blockchain::commands::pull(term, root_dir, name);
+            blockchain::commands::pull(term, root_dir, name, pinned, cancel::interrupted());
+        },
+        ("repair", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+
+            blockchain::commands::repair(term, root_dir, name);
+        },
+        ("export", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let output = PathBuf::from(matches.value_of("EXPORT_OUTPUT").unwrap());
+
+            blockchain::commands::export(term, root_dir, name, output);
+        },
+        ("import", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let input = PathBuf::from(matches.value_of("IMPORT_INPUT").unwrap());
+
+            blockchain::commands::import(term, root_dir, name, input);
+        },
+        ("export-blocks", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let from = matches.value_of("EXPORT_BLOCKS_FROM");
+            let to = matches.value_of("EXPORT_BLOCKS_TO");
+            let format = value_t!(matches, "EXPORT_BLOCKS_FORMAT", blockchain::export_blocks::Format).unwrap_or_else(|e| e.exit());
+            let output = matches.value_of("EXPORT_BLOCKS_OUTPUT").map(PathBuf::from);
+
+            blockchain::commands::export_blocks(term, root_dir, name, from, to, format, output);
+        },
+        ("peer", Some(matches)) => {
+            match matches.subcommand() {
+                ("status", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&matches);
+
+                    blockchain::commands::peer_status(term, root_dir, name);
+                },
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            }
+        },
+        ("index", Some(matches)) => {
+            match matches.subcommand() {
+                ("build", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&matches);
+
+                    blockchain::commands::index_build(term, root_dir, name);
+                },
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            }
+        },
+        ("query", Some(matches)) => {
+            match matches.subcommand() {
+                ("tx", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&matches);
+                    let txid = value_t!(matches, "QUERY_TXID", cardano::tx::TxId).unwrap_or_else(|e| e.exit());
+
+                    blockchain::commands::query_tx(term, root_dir, name, txid);
+                },
+                ("address", Some(matches)) => {
+                    let name = blockchain_argument_name_match(&matches);
+                    let address = value_t!(matches, "QUERY_ADDRESS", cardano::address::ExtendedAddr).unwrap_or_else(|e| e.exit());
+
+                    blockchain::commands::query_address(term, root_dir, name, address);
+                },
+                _ => {
+                    term.error(matches.usage()).unwrap();
+                    ::std::process::exit(1)
+                }
+            }
+        },
+        ("rollback", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let hash = matches.value_of("ROLLBACK_HASH");
+
+            blockchain::commands::rollback(term, root_dir, name, hash);
+        },
+        ("prune", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let keep_epochs = value_t!(matches, "PRUNE_KEEP_EPOCHS", u64).unwrap_or_else(|e| e.exit());
+
+            blockchain::commands::prune(term, root_dir, name, keep_epochs);
         },
         ("cat", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
@@ -299,7 +459,12 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
         },
         ("verify", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
-            blockchain::commands::verify_chain(term, root_dir, name);
+            if matches.is_present("VERIFY_LINKS_ONLY") {
+                let quarantine = matches.is_present("VERIFY_QUARANTINE");
+                blockchain::commands::verify_links(term, root_dir, name, quarantine, cancel::interrupted());
+            } else {
+                blockchain::commands::verify_chain(term, root_dir, name);
+            }
         },
         _ => {
             term.error(matches.usage()).unwrap();
@@ -323,6 +488,13 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("new")
             .about("create a new local blockchain")
             .arg(blockchain_argument_template_definition())
+            .arg(Arg::with_name("BLOCKCHAIN_CHECKPOINT")
+                .long("checkpoint")
+                .value_name("EPOCH:HASH")
+                .takes_value(true)
+                .required(false)
+                .help("start the blockchain from the given trusted epoch boundary block instead of the genesis")
+            )
             .arg(blockchain_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("remote-add")
@@ -330,6 +502,14 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(blockchain_argument_name_definition())
             .arg(blockchain_argument_remote_alias_definition())
             .arg(blockchain_argument_remote_endpoint_definition())
+            .arg(Arg::with_name("REMOTE_ADD_PROTOCOL")
+                .long("protocol")
+                .value_name("PROTOCOL")
+                .takes_value(true)
+                .required(false)
+                .possible_values(blockchain::protocol::Protocol::NAMES)
+                .help("the protocol of the remote node, guessed from the end point if not given (the http and https URLs are HTTP bridges)")
+            )
         )
         .subcommand(SubCommand::with_name("remote-rm")
             .about("Remove the given remote node from the local blockchain, we will no longer fetch blocks from this remote node.")
@@ -365,6 +545,13 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .required(false)
                 .help("print all local known information regarding the remotes as well as the details from the remote (needs a network connection)")
             )
+            .arg(Arg::with_name("REMOTE_LS_TIMEOUT")
+                .long("timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("5")
+                .help("with `--complete', the time given to each remote to send its tip")
+            )
         )
         .subcommand(SubCommand::with_name("forward")
             .about("Forward the local tip to what seems to be the consensus within the remote blocks. This function must be used combined with `remote-fetch'.")
@@ -378,10 +565,123 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("pull")
             .about("handy command to `remote-fetch' and `forward' the local blockchain.")
             .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("PULL_PEER")
+                .long("peer")
+                .value_name("ALIAS")
+                .takes_value(true)
+                .help("sync from the given remote only, instead of the best reachable one")
+            )
+        )
+        .subcommand(SubCommand::with_name("repair")
+            .about("download again the blocks missing between the last packed epoch and the local tip.")
+            .arg(blockchain_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name("export")
+            .about("write the blocks and the tags of the local blockchain in a tar archive.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("EXPORT_OUTPUT")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("the archive to write")
+            )
+        )
+        .subcommand(SubCommand::with_name("import")
+            .about("import the blocks and the tags of an archive created with `export'. The blocks are verified before being imported, the local tags are only moved forward.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("IMPORT_INPUT")
+                .long("input")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+                .help("the archive to import")
+            )
+        )
+        .subcommand(SubCommand::with_name("export-blocks")
+            .about("write the decoded blocks (headers, transaction inputs, outputs and fees) of the local blockchain, in the standard output or in a file.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("EXPORT_BLOCKS_FROM")
+                .long("from")
+                .value_name("HASH")
+                .takes_value(true)
+                .help("the first block to export, the genesis by default")
+            )
+            .arg(Arg::with_name("EXPORT_BLOCKS_TO")
+                .long("to")
+                .value_name("HASH")
+                .takes_value(true)
+                .help("the last block to export, the local tip by default")
+            )
+            .arg(Arg::with_name("EXPORT_BLOCKS_FORMAT")
+                .long("format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["json", "cbor-hex", "csv"])
+                .default_value("json")
+                .help("one JSON object per block, the hexadecimal of the raw blocks or one CSV line per transaction")
+            )
+            .arg(Arg::with_name("EXPORT_BLOCKS_OUTPUT")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("the file to write, the standard output by default")
+            )
+        )
+        .subcommand(SubCommand::with_name("peer")
+            .about("inspect the remote nodes of the local blockchain")
+            .subcommand(SubCommand::with_name("status")
+                .about("print the health of the remote nodes as recorded by the last syncs: last successful sync, latency, tip and failures")
+                .arg(blockchain_argument_name_definition())
+            )
+        )
+        .subcommand(SubCommand::with_name("index")
+            .about("manage the address and transaction indexes of the local blockchain")
+            .subcommand(SubCommand::with_name("build")
+                .about("walk the blockchain up to the local tip and write the indexes of the transactions by identifier and by address (for `query')")
+                .arg(blockchain_argument_name_definition())
+            )
+        )
+        .subcommand(SubCommand::with_name("query")
+            .about("look up the transactions of the local blockchain in its indexes (see `index build')")
+            .subcommand(SubCommand::with_name("tx")
+                .about("print the block containing the given transaction, and its position in the block")
+                .arg(blockchain_argument_name_definition())
+                .arg(Arg::with_name("QUERY_TXID")
+                    .value_name("TXID")
+                    .required(true)
+                    .help("the transaction identifier, in hexadecimal")
+                )
+            )
+            .subcommand(SubCommand::with_name("address")
+                .about("print the transactions paying to or spending from the given address")
+                .arg(blockchain_argument_name_definition())
+                .arg(Arg::with_name("QUERY_ADDRESS")
+                    .value_name("ADDRESS")
+                    .required(true)
+                    .help("the address, in base58")
+                )
+            )
+        )
+        .subcommand(SubCommand::with_name("rollback")
+            .about("move the local tip back to the given block, or to the block the remote nodes forked from. Run `pull' afterward to sync the new branch.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("ROLLBACK_HASH")
+                .value_name("HASH")
+                .required(false)
+                .help("the block to roll back to (instead of looking for a fork with the remote nodes)")
+            )
         )
-        .subcommand(SubCommand::with_name("gc")
-            .about("run garbage collection of lose blocks. This function might be a bit slow to run but it will free some disk space.")
+        .subcommand(SubCommand::with_name("prune")
+            .about("remove the loose blocks already packed in an epoch. This function might be a bit slow to run but it will free some disk space.")
             .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("PRUNE_KEEP_EPOCHS")
+                .long("keep-epochs")
+                .value_name("N")
+                .takes_value(true)
+                .default_value("0")
+                .help("keep the loose blocks of the last N packed epochs")
+            )
         )
         .subcommand(SubCommand::with_name("cat")
             .about("print the content of a block.")
@@ -429,6 +729,15 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("verify")
             .about("verify all blocks in the chain")
             .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("VERIFY_LINKS_ONLY")
+                .long("links-only")
+                .help("only verify the blocks and the hash links between them, not the ledger (faster, the packed epochs are verified in parallel if compiled with the `parallel' feature)")
+            )
+            .arg(Arg::with_name("VERIFY_QUARANTINE")
+                .long("quarantine")
+                .requires("VERIFY_LINKS_ONLY")
+                .help("move the corrupt loose blocks to the `quarantine' directory of the blockchain, and the local tip back before them (requires `--links-only')")
+            )
         )
 }
 
@@ -558,6 +867,15 @@ fn wallet_argument_daedalus_seed<'a, 'b>() -> Arg<'a, 'b> {
 fn wallet_argument_daedalus_seed_match<'a>(matches: &ArgMatches<'a>) -> bool {
     matches.is_present("DAEDALUS_SEED")
 }
+fn wallet_argument_unencrypted<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("UNENCRYPTED")
+        .help("store the private key of the wallet unencrypted, no spending password is asked (for automation only)")
+        .long("unencrypted")
+        .takes_value(false)
+}
+fn wallet_argument_unencrypted_match<'a>(matches: &ArgMatches<'a>) -> bool {
+    matches.is_present("UNENCRYPTED")
+}
 
 const WALLET_COMMAND : &'static str = "wallet";
 
@@ -569,8 +887,9 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
             let derivation_scheme = wallet_argument_derivation_scheme_match(&matches);
             let mnemonic_length = wallet_argument_mnemonic_size_match(&matches);
             let mnemonic_langs  = wallet_argument_mnemonic_languages_match(&matches);
+            let unencrypted = wallet_argument_unencrypted_match(&matches);
 
-            wallet::commands::new(term, root_dir, name, wallet_scheme, derivation_scheme, mnemonic_length, mnemonic_langs);
+            wallet::commands::new(term, root_dir, name, wallet_scheme, derivation_scheme, mnemonic_length, mnemonic_langs, unencrypted);
         },
         ("recover", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -580,6 +899,7 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
             let mnemonic_lang   = wallet_argument_mnemonic_language_match(&matches);
             let daedalus_seed   = wallet_argument_daedalus_seed_match(&matches);
             let interactive = matches.is_present("RECOVER_INTERACTIVE");
+            let unencrypted = wallet_argument_unencrypted_match(&matches);
 
             if daedalus_seed {
                 if wallet_scheme != wallet::HDWalletModel::RandomIndex2Levels {
@@ -593,7 +913,15 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
                 }
             }
 
-            wallet::commands::recover(term, root_dir, name, wallet_scheme, derivation_scheme, mnemonic_length, interactive, daedalus_seed, mnemonic_lang);
+            wallet::commands::recover(term, root_dir, name, wallet_scheme, derivation_scheme, mnemonic_length, interactive, daedalus_seed, mnemonic_lang, unencrypted);
+        },
+        ("attach-public", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let derivation_scheme = wallet_argument_derivation_scheme_match(&matches);
+            let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let public_key = matches.value_of("ACCOUNT_PUBLIC_KEY").unwrap();
+
+            wallet::commands::attach_public(term, root_dir, name, derivation_scheme, account, public_key);
         },
         ("address", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -617,7 +945,15 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
         ("sync", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
-            wallet::commands::sync(term, root_dir, name);
+            wallet::commands::sync(term, root_dir, name, cancel::interrupted());
+        },
+        ("discover", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let gap_limit = if matches.is_present("GAP_LIMIT") {
+                Some(value_t!(matches, "GAP_LIMIT", u32).unwrap_or_else(|e| e.exit()))
+            } else { None };
+
+            wallet::commands::discover(term, root_dir, name, gap_limit, cancel::interrupted());
         },
         ("status", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -627,7 +963,7 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
         ("log", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
-            wallet::commands::log(term, root_dir, name, false);
+            wallet::commands::log(term, root_dir, name);
         },
         ("utxos", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -636,8 +972,25 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
         },
         ("statement", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let format = value_t!(matches, "STATEMENT_FORMAT", wallet::statement::Format).unwrap_or_else(|e| e.exit());
+            let mut filter = wallet::statement::Filter::default();
+            if matches.is_present("STATEMENT_FROM_DATE") {
+                filter.from_date = Some(value_t!(matches, "STATEMENT_FROM_DATE", wallet::statement::Date).unwrap_or_else(|e| e.exit()));
+            }
+            if matches.is_present("STATEMENT_TO_DATE") {
+                filter.to_date = Some(value_t!(matches, "STATEMENT_TO_DATE", wallet::statement::Date).unwrap_or_else(|e| e.exit()));
+            }
+            if matches.is_present("STATEMENT_MIN_AMOUNT") {
+                filter.min_amount = Some(value_t!(matches, "STATEMENT_MIN_AMOUNT", u64).unwrap_or_else(|e| e.exit()));
+            }
+
+            wallet::commands::statement(term, root_dir, name, format, filter);
+        },
+        ("change-passphrase", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let unencrypted = wallet_argument_unencrypted_match(&matches);
 
-            wallet::commands::log(term, root_dir, name, true);
+            wallet::commands::change_passphrase(term, root_dir, name, unencrypted);
         },
         ("destroy", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -672,6 +1025,7 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(wallet_argument_derivation_scheme())
             .arg(wallet_argument_wallet_scheme())
             .arg(wallet_argument_mnemonic_languages())
+            .arg(wallet_argument_unencrypted())
             .arg(wallet_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("recover")
@@ -682,12 +1036,34 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(wallet_argument_wallet_scheme())
             .arg(wallet_argument_mnemonic_language())
             .arg(wallet_argument_daedalus_seed())
+            .arg(wallet_argument_unencrypted())
             .arg(Arg::with_name("RECOVER_INTERACTIVE")
                 .help("use interactive mode for recovering the mnemonic words")
                 .long("interactive")
                 .short("i")
             )
         )
+        .subcommand(SubCommand::with_name("attach-public")
+            .about("create a watch-only wallet from the public key of one of its accounts. The wallet cannot sign transactions.")
+            .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_derivation_scheme())
+            .arg(Arg::with_name("ACCOUNT_INDEX")
+                .help("the index of the account of the public key")
+                .long("account")
+                .takes_value(true)
+                .value_name("INDEX")
+                .default_value("0")
+            )
+            .arg(Arg::with_name("ACCOUNT_PUBLIC_KEY")
+                .help("the extended public key of the account, in hexadecimal")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("change-passphrase")
+            .about("encrypt the private key of the wallet with a new spending password")
+            .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_unencrypted())
+        )
         .subcommand(SubCommand::with_name("destroy")
             .about("delete all data associated to the given wallet.")
             .arg(wallet_argument_name_definition())
@@ -722,13 +1098,49 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             )
             .arg(wallet_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("discover")
+            .about("find all the addresses of the wallet used in the attached blockchain, and sync the wallet from the genesis (for the restored wallets)")
+            .arg(Arg::with_name("GAP_LIMIT")
+                .help("the number of unused addresses to look ahead of the last address found (default 20), saved in the wallet's configuration")
+                .long("gap-limit")
+                .value_name("NUMBER")
+                .takes_value(true)
+            )
+            .arg(wallet_argument_name_definition())
+        )
         .subcommand(SubCommand::with_name("status")
             .about("print some status information from the given wallet (funds, transactions...)")
             .arg(wallet_argument_name_definition())
         )
         .subcommand(SubCommand::with_name("statement")
-            .about("print the wallet statement")
+            .about("print the wallet statement: the transactions of the wallet, with their fee and the resulting balance")
             .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("STATEMENT_FORMAT")
+                .long("format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(wallet::statement::Format::NAMES)
+                .default_value("text")
+                .help("the format of the statement, `csv' for accounting software")
+            )
+            .arg(Arg::with_name("STATEMENT_FROM_DATE")
+                .long("from-date")
+                .value_name("DATE")
+                .takes_value(true)
+                .help("only the transactions from the given date (YYYY-MM-DD, or an RFC 3339 time), included")
+            )
+            .arg(Arg::with_name("STATEMENT_TO_DATE")
+                .long("to-date")
+                .value_name("DATE")
+                .takes_value(true)
+                .help("only the transactions up to the given date (YYYY-MM-DD, or an RFC 3339 time), included")
+            )
+            .arg(Arg::with_name("STATEMENT_MIN_AMOUNT")
+                .long("min-amount")
+                .value_name("LOVELACE")
+                .takes_value(true)
+                .help("only the transactions of at least the given amount, in lovelace")
+            )
         )
         .subcommand(SubCommand::with_name("log")
             .about("print the wallet logs")
@@ -748,7 +1160,7 @@ const TRANSACTION_COMMAND : &'static str = "transaction";
 
 #[derive(Debug,Clone,Copy)]
 pub enum TransactionCmd {
-    New, List, Destroy, Export, Import, Sign, Finalize, Send,
+    New, List, Destroy, Export, Import, Sign, Merge, Inspect, Finalize, Send, Build, Submit, EstimateFee,
     InputSelect, AddChange, AddInput, AddOutput, RmInput, RmOutput, RmChange, Status,
 }
 impl TransactionCmd {
@@ -761,7 +1173,12 @@ impl TransactionCmd {
             TransactionCmd::Import => "import",
             TransactionCmd::Send => "send",
             TransactionCmd::Sign => "sign",
+            TransactionCmd::Merge => "merge",
+            TransactionCmd::Inspect => "inspect",
             TransactionCmd::Finalize => "finalize",
+            TransactionCmd::Build => "build",
+            TransactionCmd::Submit => "submit",
+            TransactionCmd::EstimateFee => "estimate-fee",
             TransactionCmd::InputSelect => "input-select",
             TransactionCmd::AddChange => "add-change",
             TransactionCmd::AddInput => "add-input",
@@ -807,6 +1224,48 @@ fn transaction_argument_txin_match<'a>(matches: &ArgMatches<'a>) -> Option<(card
 
     Some((txid, index))
 }
+fn transaction_argument_selection_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("SELECTION_STRATEGY")
+        .help("the algorithm selecting the inputs among the wallets' UTxOs")
+        .long("selection")
+        .value_name("STRATEGY")
+        .takes_value(true)
+        .possible_values(&["first-match", "largest-first", "random-improve", "manual"])
+        .default_value("first-match")
+}
+fn transaction_argument_selection_input_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("SELECTION_INPUT")
+        .help("with `--selection=manual', an input to select (`<TXID>.<INDEX>')")
+        .long("input")
+        .value_name("TXID.INDEX")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .required_if("SELECTION_STRATEGY", "manual")
+}
+fn transaction_argument_selection_match<'a>(matches: &ArgMatches<'a>) -> (transaction::selection::Strategy, Vec<cardano::tx::TxoPointer>) {
+    let strategy = value_t!(matches, "SELECTION_STRATEGY", transaction::selection::Strategy).unwrap_or_else(|e| e.exit());
+    let inputs = matches.values_of("SELECTION_INPUT").map(|inputs| {
+        inputs.map(|input| transaction_txin_parse(input).unwrap_or_else(|e| clap::Error::value_validation_auto(e).exit())).collect()
+    }).unwrap_or(Vec::new());
+    (strategy, inputs)
+}
+fn transaction_output_parse(output: &str) -> Result<transaction::core::Output, String> {
+    let mut parts = output.rsplitn(2, ':');
+    let amount = parts.next().unwrap_or("").parse::<cardano::coin::Coin>()
+        .map_err(|_| format!("invalid amount in `{}'", output))?;
+    let address = parts.next().ok_or(format!("missing amount in `{}'", output))?
+        .parse::<cardano::address::ExtendedAddr>().map_err(|_| format!("invalid address in `{}'", output))?;
+    Ok(transaction::core::Output { address: address, amount: amount })
+}
+fn transaction_txin_parse(input: &str) -> Result<cardano::tx::TxoPointer, String> {
+    let mut parts = input.splitn(2, '.');
+    let txid = parts.next().unwrap_or("").parse::<cardano::tx::TxId>()
+        .map_err(|_| format!("invalid transaction identifier in `{}'", input))?;
+    let index = parts.next().ok_or(format!("missing output index in `{}'", input))?
+        .parse::<u32>().map_err(|_| format!("invalid output index in `{}'", input))?;
+    Ok(cardano::tx::TxoPointer { id: txid, index: index })
+}
 fn transaction_argument_input_match<'a>(matches: &ArgMatches<'a>) -> Option<(cardano::tx::TxId, u32, Option<cardano::coin::Coin>)> {
     let (txid, index) = transaction_argument_txin_match(&matches)?;
     let coin = value_t!(matches, "UTXO_AMOUNT", cardano::coin::Coin).ok();
@@ -868,9 +1327,49 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
                 .unwrap_or_else(|e| term.fail_with(e));
         },
         ("sign", Some(matches)) => {
+            if matches.is_present("UNSIGNED_FILE") {
+                let input = matches.value_of("UNSIGNED_FILE").filter(|file| file != &"-");
+                let output = matches.value_of("SIGNED_FILE");
+
+                let assume_yes = matches.is_present("SIGN_YES");
+
+                transaction::commands::sign_offline(&mut term, root_dir, input, output, assume_yes)
+                    .unwrap_or_else(|e| term.fail_with(e));
+            } else {
+                let id = transaction_argument_name_match(&matches);
+
+                let partial = matches.is_present("SIGN_PARTIAL");
+
+                transaction::commands::sign(&mut term, root_dir, id, partial)
+                    .unwrap_or_else(|e| term.fail_with(e));
+            }
+        },
+        ("merge", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+            let blockchain = blockchain_argument_name_match(&matches);
+            let files = matches.values_of("MERGE_FILES").unwrap().collect();
+
+            transaction::commands::merge(&mut term, root_dir, id, blockchain, files)
+                .unwrap_or_else(|e| term.fail_with(e));
+        },
+        ("inspect", Some(matches)) => {
+            let id = transaction_argument_name_match(&matches);
+
+            transaction::commands::inspect(&mut term, root_dir, id)
+                .unwrap_or_else(|e| term.fail_with(e));
+        },
+        ("build", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
+            let output = matches.value_of("UNSIGNED_FILE");
 
-            transaction::commands::sign(&mut term, root_dir, id)
+            transaction::commands::build(&mut term, root_dir, id, output)
+                .unwrap_or_else(|e| term.fail_with(e));
+        },
+        ("submit", Some(matches)) => {
+            let blockchain = blockchain_argument_name_match(&matches);
+            let input = matches.value_of("SIGNED_FILE");
+
+            transaction::commands::submit(&mut term, root_dir, blockchain, input)
                 .unwrap_or_else(|e| term.fail_with(e));
         },
         ("add-input", Some(matches)) => {
@@ -897,8 +1396,20 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         ("input-select", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
             let wallets = values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit());
+            let (strategy, inputs) = transaction_argument_selection_match(&matches);
+
+            transaction::commands::input_select(&mut term, root_dir, id, wallets, strategy, inputs)
+                .unwrap_or_else(|e| term.fail_with(e));
+        }
+        ("estimate-fee", Some(matches)) => {
+            let wallets = values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit());
+            let (strategy, inputs) = transaction_argument_selection_match(&matches);
+            let outputs = matches.values_of("ESTIMATE_OUTPUT").unwrap().map(|output| {
+                transaction_output_parse(output).unwrap_or_else(|e| clap::Error::value_validation_auto(e).exit())
+            }).collect();
+            let change = value_t!(matches, "CHANGE_ADDRESS", cardano::address::ExtendedAddr).unwrap_or_else(|e| e.exit());
 
-            transaction::commands::input_select(&mut term, root_dir, id, wallets)
+            transaction::commands::estimate_fee(&mut term, root_dir, wallets, strategy, inputs, outputs, change)
                 .unwrap_or_else(|e| term.fail_with(e));
         }
         ("rm-output", Some(matches)) => {
@@ -966,7 +1477,67 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Sign.as_string())
             .about("Finalize a staging a transaction into a transaction ready to send to the blockchain network")
+            .arg(transaction_argument_name_definition()
+                .required_unless("UNSIGNED_FILE")
+                .conflicts_with("UNSIGNED_FILE")
+            )
+            .arg(Arg::with_name("UNSIGNED_FILE")
+                .help("sign the given unsigned transaction file (see the `build' command) instead of a staging transaction, `-' to read the standard input")
+                .long("unsigned")
+                .value_name("FILE")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("SIGNED_FILE")
+                .help("optional file to write the signed transaction to (default will display it to stdout)")
+                .long("output")
+                .value_name("FILE")
+                .takes_value(true)
+                .requires("UNSIGNED_FILE")
+            )
+            .arg(Arg::with_name("SIGN_PARTIAL")
+                .help("only sign the inputs of the local wallets, the other inputs are left to the other parties (see the `merge' command)")
+                .long("partial")
+                .conflicts_with("UNSIGNED_FILE")
+            )
+            .arg(Arg::with_name("SIGN_YES")
+                .help("sign the unsigned transaction file without asking for confirmation (required to read it from the standard input)")
+                .long("yes")
+                .requires("UNSIGNED_FILE")
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Merge.as_string())
+            .about("Add the signatures of exported copies of the staging transaction, signed by the other parties")
+            .arg(transaction_argument_name_definition())
+            .arg(blockchain_argument_name_definition()
+                .help("the blockchain to find the addresses of the inputs in, to verify the signatures (see `blockchain index build')")
+            )
+            .arg(Arg::with_name("MERGE_FILES")
+                .help("the exported staging transactions (see the `export' command) to take the signatures from")
+                .multiple(true)
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Inspect.as_string())
+            .about("Display which inputs of the staging transaction are signed")
+            .arg(transaction_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Build.as_string())
+            .about("Build an unsigned transaction file from a finalized staging transaction, to sign it on another device")
             .arg(transaction_argument_name_definition())
+            .arg(Arg::with_name("UNSIGNED_FILE")
+                .help("optional file to write the unsigned transaction to (default will display it to stdout)")
+                .required(false)
+            )
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::Submit.as_string())
+            .about("Send a signed transaction file (see the `sign --unsigned' command) to the blockchain")
+            .arg(blockchain_argument_name_definition()
+                .help("The blockchain the send the transaction too (will contact the peers of this blockchain)")
+            )
+            .arg(Arg::with_name("SIGNED_FILE")
+                .help("optional file to read the signed transaction from (default will read stdin)")
+                .required(false)
+            )
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Send.as_string())
             .about("Send the transaction transaction to the blockchain")
@@ -984,6 +1555,30 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("Select input automatically using a wallet (or a set of wallets), and a input selection algorithm")
             .arg(transaction_argument_name_definition())
             .arg(Arg::with_name("WALLET_NAME").required(true).multiple(true).help("wallet name to use for the selection"))
+            .arg(transaction_argument_selection_definition())
+            .arg(transaction_argument_selection_input_definition())
+        )
+        .subcommand(SubCommand::with_name(TransactionCmd::EstimateFee.as_string())
+            .about("Compute the fee, the change and the size of the transaction sending the given outputs, without creating it")
+            .arg(Arg::with_name("WALLET_NAME").required(true).multiple(true).help("wallet name to use for the selection"))
+            .arg(Arg::with_name("ESTIMATE_OUTPUT")
+                .help("an output of the transaction (`<ADDRESS>:<AMOUNT>', the amount in lovelace)")
+                .long("output")
+                .value_name("ADDRESS:AMOUNT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+            )
+            .arg(Arg::with_name("CHANGE_ADDRESS")
+                .help("address to send the change to")
+                .long("change")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .required(true)
+            )
+            .arg(transaction_argument_selection_definition())
+            .arg(transaction_argument_selection_input_definition())
         )
         .subcommand(SubCommand::with_name(TransactionCmd::AddChange.as_string())
             .about("Add a change address to a transaction")
@@ -1031,13 +1626,24 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
 
 const DEBUG_COMMAND : &'static str = "debug";
 
-fn subcommand_debug<'a>(mut term: term::Term, _rootdir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_debug<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
     match matches.subcommand() {
         ("address", Some(matches)) => {
             let address = value_t!(matches, "ADDRESS", String).unwrap_or_else(|e| e.exit() );
 
             debug::command_address(term, address);
         },
+        ("decode-tx", Some(matches)) => {
+            let transaction = value_t!(matches, "TRANSACTION", String).unwrap_or_else(|e| e.exit() );
+            let blockchain = matches.value_of("DECODE_TX_BLOCKCHAIN").map(|name| name.to_owned());
+
+            debug::decode_tx(term, root_dir, transaction, blockchain);
+        },
+        ("inspect-address", Some(matches)) => {
+            let address = value_t!(matches, "ADDRESS", String).unwrap_or_else(|e| e.exit() );
+
+            debug::inspect_address(term, address);
+        },
         ("canonicalize-json", Some(_)) => {
             debug::canonicalize_json();
         },
@@ -1061,6 +1667,28 @@ fn debug_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .required(true)
             )
         )
+        .subcommand(SubCommand::with_name("decode-tx")
+            .about("decode a transaction (signed or not): its inputs, outputs, witnesses and fee.")
+            .arg(Arg::with_name("TRANSACTION")
+                .help("the transaction, in hexadecimal, or the file holding it (raw CBOR or hexadecimal)")
+                .value_name("HEX|FILE")
+                .required(true)
+            )
+            .arg(Arg::with_name("DECODE_TX_BLOCKCHAIN")
+                .long("blockchain")
+                .value_name("BLOCKCHAIN_NAME")
+                .takes_value(true)
+                .help("resolve the inputs with the indexes of the blockchain (see `blockchain index build'), to compute the fee")
+            )
+        )
+        .subcommand(SubCommand::with_name("inspect-address")
+            .about("decode the given address (in base58): its type, derivation payload, stake distribution and network magic.")
+            .arg(Arg::with_name("ADDRESS")
+                .help("base58 encoded address")
+                .value_name("ADDRESS")
+                .required(true)
+            )
+        )
         .subcommand(SubCommand::with_name("log-dump")
             .about("pretty print the content of the wallet log file")
             .arg(Arg::with_name("LOG_FILE")
@@ -1076,3 +1704,86 @@ fn debug_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("compute the Blake2b256 hash of the data on stdin.")
         )
 }
+
+/* ------------------------------------------------------------------------- *
+ *            Daemon Sub Commands and helpers                                *
+ * ------------------------------------------------------------------------- */
+
+const DAEMON_COMMAND : &'static str = "daemon";
+
+fn subcommand_daemon<'a>(term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+    let names = values_t!(matches, "BLOCKCHAIN_NAME", String).unwrap_or_else(|e| e.exit());
+    let interval = value_t!(matches, "DAEMON_INTERVAL", u64).unwrap_or_else(|e| e.exit());
+    let max_backoff = value_t!(matches, "DAEMON_MAX_BACKOFF", u64).unwrap_or_else(|e| e.exit());
+    let pidfile = match matches.value_of("DAEMON_PIDFILE") {
+        None => root_dir.join(daemon::DEFAULT_PIDFILE),
+        Some(pidfile) => PathBuf::from(pidfile),
+    };
+
+    let config = daemon::Config {
+        interval: ::std::time::Duration::from_secs(interval),
+        max_backoff: ::std::time::Duration::from_secs(max_backoff),
+        pidfile: pidfile,
+        metrics: matches.value_of("DAEMON_METRICS").map(|listen| listen.to_owned()),
+    };
+    daemon::run(term, root_dir, names, config, cancel::interrupted())
+}
+fn daemon_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(DAEMON_COMMAND)
+        .about("keep the given local blockchains synchronised: `pull' them periodically, until interrupted.")
+        .arg(blockchain_argument_name_definition()
+            .multiple(true)
+        )
+        .arg(Arg::with_name("DAEMON_INTERVAL")
+            .long("interval")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .default_value("60")
+            .help("the time between two pulls")
+        )
+        .arg(Arg::with_name("DAEMON_MAX_BACKOFF")
+            .long("max-backoff")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .default_value("3600")
+            .help("the maximum time before retrying a failing peer")
+        )
+        .arg(Arg::with_name("DAEMON_PIDFILE")
+            .long("pidfile")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("the file to write the pid of the daemon in [default: <ROOT_DIR>/daemon.pid]")
+        )
+        .arg(Arg::with_name("DAEMON_METRICS")
+            .long("metrics")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .help("serve the metrics of the syncs on http://<HOST:PORT>/metrics (Prometheus text format)")
+        )
+}
+
+/* ------------------------------------------------------------------------- *
+ *            Serve Sub Commands and helpers                                 *
+ * ------------------------------------------------------------------------- */
+
+#[cfg(feature = "serve")]
+const SERVE_COMMAND : &'static str = "serve";
+
+#[cfg(feature = "serve")]
+fn subcommand_serve<'a>(term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+    let listen = matches.value_of("SERVE_LISTEN").unwrap();
+
+    serve::run(term, root_dir, listen)
+}
+#[cfg(feature = "serve")]
+fn serve_commands_definition<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name(SERVE_COMMAND)
+        .about("serve the local blockchains and wallets over HTTP (read only, JSON replies), until killed.")
+        .arg(Arg::with_name("SERVE_LISTEN")
+            .long("listen")
+            .value_name("HOST:PORT")
+            .takes_value(true)
+            .default_value("127.0.0.1:8080")
+            .help("the address to listen on")
+        )
+}
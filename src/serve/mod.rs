@@ -0,0 +1,279 @@
+//! read only HTTP server over the local blockchains and wallets
+//!
+//! Every reply is a JSON document, the errors are `{ "error": "..." }`
+//! with the matching status code. The requests are served one at a
+//! time, the blockchains and the wallets are loaded for every request:
+//!
+//! * `GET /blockchains/<name>/tip`
+//! * `GET /blockchains/<name>/blocks/<hash>`
+//! * `GET /blockchains/<name>/blocks?from=<hash>[&to=<hash>][&limit=<n>]`,
+//!   `to` defaults to the local tip, at most `limit` blocks (default and
+//!   maximum `MAX_RANGE`) are listed;
+//! * `GET /wallets/<name>/balance`
+//! * `GET /wallets/<name>/addresses`, the addresses holding funds;
+//! * `GET /metrics`, the tips of the local blockchains in the Prometheus
+//!   text format (see `blockchain::metrics`), not in JSON.
+//!
+//! The blockchains are loaded with a shared lock (see `blockchain::lock`),
+//! a blockchain being modified by another process (`pull`...) is replied
+//! with a `503`, the lock is not waited for.
+//!
+
+use std::{collections::BTreeSet, path::PathBuf, str::FromStr, time::Duration};
+use cardano::{block::{Block, HeaderHash}, util::hex};
+use cardano_storage as storage;
+use serde::Serialize;
+use serde_json;
+use tiny_http::{Server, Request, Response, Header, Method};
+
+use utils::term::Term;
+use blockchain::{self, Blockchain, lock};
+use wallet::{Wallets, WalletName, state::{log, lookup, state}};
+
+/// the maximum number of blocks listed in a range
+pub const MAX_RANGE : usize = 1000;
+
+/// a JSON value, or the status code and message of the error
+type Reply = Result<serde_json::Value, (u16, String)>;
+
+fn json<T: Serialize>(value: &T) -> Reply {
+    serde_json::to_value(value).map_err(|err| (500, format!("{}", err)))
+}
+
+fn not_found(what: &str) -> (u16, String) { (404, format!("{} not found", what)) }
+
+fn internal<E: ::std::fmt::Display>(err: E) -> (u16, String) { (500, format!("{}", err)) }
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct BlockInfo {
+    hash: String,
+    date: String,
+    parent: String,
+}
+impl<'a> From<&'a Block> for BlockInfo {
+    fn from(block: &'a Block) -> Self {
+        let header = block.get_header();
+        BlockInfo {
+            hash: format!("{}", header.compute_hash()),
+            date: format!("{}", header.get_blockdate()),
+            parent: format!("{}", header.get_previous_header()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BlockDetails {
+    #[serde(flatten)]
+    info: BlockInfo,
+    transactions: usize,
+    /// the block, as stored, in hexadecimal
+    raw: String,
+}
+
+#[derive(Serialize)]
+struct Balance {
+    /// in lovelace
+    balance: u64,
+    /// the last block the wallet has been synced to
+    synced_to: String,
+}
+
+fn parse_hash(hash: &str) -> Result<HeaderHash, (u16, String)> {
+    HeaderHash::from_str(hash).map_err(|_| (400, format!("invalid block hash `{}'", hash)))
+}
+
+/// split the query string into its `key=value` parameters
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&').filter_map(|param| {
+        let mut kv = param.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) if k == key => Some(v),
+            _ => None,
+        }
+    }).next()
+}
+
+fn load_blockchain(root_dir: &PathBuf, name: &str) -> Result<Blockchain, (u16, String)> {
+    if ! blockchain::config::directory(root_dir.clone(), name).is_dir() {
+        return Err(not_found(&format!("blockchain `{}'", name)));
+    }
+    Blockchain::load_with(root_dir.clone(), name.to_owned(), lock::Mode::Shared, Duration::from_secs(0))
+        .and_then(|blockchain| { blockchain.open_storage()?; Ok(blockchain) })
+        .map_err(|err| match err {
+            blockchain::Error::Locked(_) => (503, format!("{}, retry later", err)),
+            err => internal(err),
+        })
+}
+
+fn tip(blockchain: &Blockchain) -> Reply {
+    let (tip, _is_genesis) = blockchain.load_tip_from_tag(blockchain::LOCAL_BLOCKCHAIN_TIP_TAG).map_err(internal)?;
+    json(&BlockInfo {
+        hash: format!("{}", tip.hash),
+        date: format!("{}", tip.date),
+        parent: format!("{}", tip.parent),
+    })
+}
+
+fn block(blockchain: &Blockchain, hash: &str) -> Reply {
+    let hash = parse_hash(hash)?;
+    let raw_block = storage::block_read(blockchain.open_storage().map_err(internal)?, &hash)
+        .ok_or_else(|| not_found(&format!("block {}", hash)))?;
+    let block = raw_block.decode().map_err(internal)?;
+    json(&BlockDetails {
+        info: BlockInfo::from(&block),
+        transactions: block.get_transactions().map(|txs| txs.len()).unwrap_or(0),
+        raw: hex::encode(raw_block.as_ref()),
+    })
+}
+
+fn blocks(blockchain: &Blockchain, query: &str) -> Reply {
+    let from = parse_hash(query_param(query, "from").ok_or((400, "missing `from' parameter".to_owned()))?)?;
+    let to = match query_param(query, "to") {
+        None => blockchain.load_tip_from_tag(blockchain::LOCAL_BLOCKCHAIN_TIP_TAG).map_err(internal)?.0.hash,
+        Some(to) => parse_hash(to)?,
+    };
+    let limit = match query_param(query, "limit") {
+        None => MAX_RANGE,
+        Some(limit) => limit.parse::<usize>().map_err(|_| (400, format!("invalid limit `{}'", limit)))?.min(MAX_RANGE),
+    };
+
+    // an unknown hash is the client's mistake, not the server's
+    let storage = blockchain.open_storage().map_err(internal)?;
+    for hash in [&from, &to].iter() {
+        if storage::block_location(storage, hash).is_none() {
+            return Err(not_found(&format!("block {}", hash)));
+        }
+    }
+
+    let mut infos = Vec::new();
+    for res in blockchain.iter(from, to).map_err(internal)?.take(limit) {
+        let (_raw_block, block) = res.map_err(internal)?;
+        infos.push(BlockInfo::from(&block));
+    }
+    json(&infos)
+}
+
+/// the state of the wallet from its log, `None` if the log is empty
+fn wallet_state(root_dir: &PathBuf, name: &str) -> Result<Option<state::State<lookup::accum::Accum>>, (u16, String)> {
+    let name = WalletName::from_str(name).map_err(|err| (400, err.to_owned()))?;
+    let wallets = Wallets::load(root_dir.clone()).map_err(internal)?;
+    let wallet = wallets.get(&name).ok_or_else(|| not_found(&format!("wallet `{}'", name)))?;
+
+    let reader = log::LogReader::open(wallet.log().map_err(internal)?).map_err(internal)?;
+    let reader : log::LogIterator<lookup::Address> = reader.into_iter();
+    let logs = reader.collect::<Result<Vec<_>, _>>().map_err(internal)?;
+    let state = state::State::from_logs(lookup::accum::Accum::default(), logs).map_err(internal)?;
+    Ok(state.ok())
+}
+
+fn balance(root_dir: &PathBuf, name: &str) -> Reply {
+    match wallet_state(root_dir, name)? {
+        None => json(&Balance { balance: 0, synced_to: String::new() }),
+        Some(state) => json(&Balance {
+            balance: state.total().map_err(internal)?.into(),
+            synced_to: format!("{}", state.ptr.latest_known_hash),
+        }),
+    }
+}
+
+fn addresses(root_dir: &PathBuf, name: &str) -> Reply {
+    let addresses : BTreeSet<String> = match wallet_state(root_dir, name)? {
+        None => BTreeSet::new(),
+        Some(state) => state.utxos.values().map(|utxo| format!("{}", utxo.credited_address)).collect(),
+    };
+    json(&addresses)
+}
+
+/// the metrics of all the local blockchains
+fn metrics(root_dir: &PathBuf) -> Result<String, (u16, String)> {
+    let mut blockchains = Vec::new();
+    for entry in ::std::fs::read_dir(blockchain::config::blockchains_directory(root_dir)).map_err(internal)? {
+        let entry = entry.map_err(internal)?;
+        if ! entry.file_type().map_err(internal)?.is_dir() { continue; }
+        if let Ok(name) = entry.file_name().into_string() {
+            match Blockchain::load_from(entry.path(), name) {
+                Ok(blockchain) => blockchains.push(blockchain),
+                Err(err) => warn!("cannot load the blockchain {:?}: {}", entry.path(), err),
+            }
+        }
+    }
+    Ok(blockchain::metrics::render(&blockchains))
+}
+
+fn route(root_dir: &PathBuf, request: &Request) -> Reply {
+    if request.method() != &Method::Get {
+        return Err((405, "only GET requests are served".to_owned()));
+    }
+
+    let mut url = request.url().splitn(2, '?');
+    let path = url.next().unwrap_or("");
+    let query = url.next().unwrap_or("");
+    let segments : Vec<&str> = path.split('/').filter(|s| ! s.is_empty()).collect();
+
+    match segments.as_slice() {
+        ["blockchains", name, "tip"] => tip(&load_blockchain(root_dir, name)?),
+        ["blockchains", name, "blocks"] => blocks(&load_blockchain(root_dir, name)?, query),
+        ["blockchains", name, "blocks", hash] => block(&load_blockchain(root_dir, name)?, hash),
+        ["wallets", name, "balance"] => balance(root_dir, name),
+        ["wallets", name, "addresses"] => addresses(root_dir, name),
+        _ => Err(not_found(&format!("`{}'", path))),
+    }
+}
+
+/// serve the requests on the given address (`host:port`), until killed
+pub fn run( mut term: Term
+          , root_dir: PathBuf
+          , listen: &str
+          ) -> !
+{
+    let server = Server::http(listen).unwrap_or_else(|err| {
+        term.error(&format!("cannot listen on {}: {}\n", listen, err)).unwrap();
+        ::std::process::exit(1)
+    });
+    term.info(&format!("listening on http://{}\n", listen)).unwrap();
+
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let metrics_content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap();
+    loop {
+        let request = match server.recv() {
+            Err(err) => {
+                warn!("cannot receive the request: {}", err);
+                continue;
+            },
+            Ok(request) => request,
+        };
+
+        if request.method() == &Method::Get && request.url() == "/metrics" {
+            let (status, body) = match metrics(&root_dir) {
+                Ok(body) => (200, body),
+                Err((status, error)) => (status, error),
+            };
+            info!("{} {} -> {}", request.method(), request.url(), status);
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(metrics_content_type.clone());
+            if let Err(err) = request.respond(response) {
+                warn!("cannot send the response: {}", err);
+            }
+            continue;
+        }
+
+        let (status, body) = match route(&root_dir, &request) {
+            Ok(value) => (200, value),
+            Err((status, error)) => (status, json(&ErrorReply { error }).unwrap_or(serde_json::Value::Null)),
+        };
+        info!("{} {} -> {}", request.method(), request.url(), status);
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        if let Err(err) = request.respond(response) {
+            warn!("cannot send the response: {}", err);
+        }
+    }
+}
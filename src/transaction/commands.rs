@@ -1,7 +1,8 @@
 use std::{path::PathBuf, io::{self, Write}, iter, collections::BTreeMap, fmt, error};
 use utils::term::{Term, style::{Style}};
 use super::core::{self, StagingId, StagingTransaction};
-use super::super::blockchain::{Blockchain};
+use super::{offline, selection};
+use super::super::blockchain::{self, Blockchain};
 use super::super::wallet::{Wallets, Wallet, self, WalletName};
 use cardano::{self, tx::{self, Tx, TxId, TxoPointer, TxInWitness}, coin::{self, Coin, sum_coins}, address::{ExtendedAddr}, fee::{LinearFee, FeeAlgorithm}};
 use storage_units;
@@ -42,8 +43,32 @@ pub enum Error {
     CannotImportStaging(core::staging_transaction::StagingUpdateError),
 
     CannotInputSelectNoChangeOption,
-    CannotInputSelectSelectionFailed(cardano::input_selection::Error),
+    CannotInputSelectSelectionFailed(selection::Error),
     CannotInputSelectCannotAddInput(core::staging_transaction::StagingUpdateError),
+
+    CannotBuildTransactionNotFinalized(core::transaction::Error),
+    CannotBuildTransactionInvalidTx(cardano::txbuild::Error),
+    CannotBuildTransactionUnknownInput(TxoPointer),
+    CannotWriteTransactionFileCannotOpenOutFile(io::Error),
+    CannotWriteTransactionFile(::serde_yaml::Error),
+    CannotReadTransactionFileCannotOpenInputFile(io::Error),
+    CannotReadTransactionFile(::serde_yaml::Error),
+    CannotRebuildTransaction(cardano::txbuild::Error),
+    CannotRebuildTransactionTxIdMismatch,
+    CannotSignTransactionUnknownWallet(String),
+    CannotSignTransactionSigner(wallet::Error),
+    CannotSignTransactionInvalidBalance(coin::Error),
+    CannotSignTransactionNotConfirmed,
+    CannotSubmitTransactionInvalidProtocolMagic,
+    CannotSendTransactionMissingSignatures(usize),
+    CannotSignTransactionUnknownInput(TxoPointer),
+    CannotMergeTransactionNotFinalized(core::transaction::Error),
+    CannotMergeTransactionInvalidTx(cardano::txbuild::Error),
+    CannotMergeTransactionMismatch,
+    CannotMergeTransactionCannotAddSignature(core::staging_transaction::StagingUpdateError),
+    CannotMergeTransactionCannotFindInput(blockchain::Error),
+    CannotMergeTransactionUnknownInput(TxoPointer),
+    CannotMergeTransactionInvalidSignature(TxoPointer),
 }
 impl From<::std::io::Error> for Error {
     fn from(e: ::std::io::Error) -> Self { Error::IoError(e) }
@@ -89,6 +114,29 @@ impl fmt::Display for Error {
             CannotInputSelectNoChangeOption            => write!(f, "Add change before trying to run the input selection algorithm"),
             CannotInputSelectSelectionFailed(_)        => write!(f, "Input selection algorithm failed to run"),
             CannotInputSelectCannotAddInput(_)         => write!(f, "Cannot add input to the staging transaction"),
+            CannotBuildTransactionNotFinalized(_)      => write!(f, "Cannot build transaction, finalize it first"),
+            CannotBuildTransactionInvalidTx(_)         => write!(f, "Cannot build transaction"),
+            CannotBuildTransactionUnknownInput(txin)   => write!(f, "Cannot build transaction, input {}.{} is not in the local wallets", txin.id, txin.index),
+            CannotWriteTransactionFileCannotOpenOutFile(_) => write!(f, "Cannot write the transaction: cannot open output file"),
+            CannotWriteTransactionFile(_)              => write!(f, "Cannot write the transaction"),
+            CannotReadTransactionFileCannotOpenInputFile(_) => write!(f, "Cannot read the transaction: cannot open input file"),
+            CannotReadTransactionFile(_)               => write!(f, "Cannot read the transaction: invalid or corrupted"),
+            CannotRebuildTransaction(_)                => write!(f, "Cannot rebuild the transaction from its inputs and outputs"),
+            CannotRebuildTransactionTxIdMismatch       => write!(f, "The rebuilt transaction does not match the transaction identifier"),
+            CannotSignTransactionUnknownWallet(name)   => write!(f, "Cannot sign transaction, wallet `{}' not found", name),
+            CannotSignTransactionSigner(_)             => write!(f, "Cannot sign transaction"),
+            CannotSignTransactionInvalidBalance(_)     => write!(f, "Cannot sign transaction, its outputs are more than its inputs"),
+            CannotSignTransactionNotConfirmed          => write!(f, "Cannot sign the transaction of the standard input without confirmation, see `--yes'"),
+            CannotSubmitTransactionInvalidProtocolMagic => write!(f, "Cannot submit transaction, it is for another blockchain"),
+            CannotSendTransactionMissingSignatures(n)  => write!(f, "Cannot send transaction, {} input(s) not signed yet", n),
+            CannotSignTransactionUnknownInput(txin)    => write!(f, "Cannot sign transaction, input {}.{} is not in the local wallets (see `--partial')", txin.id, txin.index),
+            CannotMergeTransactionNotFinalized(_)      => write!(f, "Cannot merge the signatures, finalize the transactions first"),
+            CannotMergeTransactionInvalidTx(_)         => write!(f, "Cannot merge the signatures"),
+            CannotMergeTransactionMismatch             => write!(f, "Cannot merge the signatures of a different transaction"),
+            CannotMergeTransactionCannotAddSignature(_) => write!(f, "Cannot add signature to the transaction"),
+            CannotMergeTransactionCannotFindInput(_)   => write!(f, "Cannot merge the signatures, the transaction index of the blockchain cannot be read (see `blockchain index build')"),
+            CannotMergeTransactionUnknownInput(txin)   => write!(f, "Cannot merge the signatures, input {}.{} is not in the transaction index of the blockchain (see `blockchain index build')", txin.id, txin.index),
+            CannotMergeTransactionInvalidSignature(txin) => write!(f, "Cannot merge the signatures, the signature of input {}.{} is not of this transaction or not of the input's address", txin.id, txin.index),
         }
     }
 }
@@ -130,6 +178,29 @@ impl error::Error for Error {
             CannotInputSelectNoChangeOption            => None,
             CannotInputSelectSelectionFailed(ref err)        => Some(err),
             CannotInputSelectCannotAddInput(ref err)         => Some(err),
+            CannotBuildTransactionNotFinalized(ref err)      => Some(err),
+            CannotBuildTransactionInvalidTx(ref err)         => Some(err),
+            CannotBuildTransactionUnknownInput(_)      => None,
+            CannotWriteTransactionFileCannotOpenOutFile(ref err) => Some(err),
+            CannotWriteTransactionFile(ref err)              => Some(err),
+            CannotReadTransactionFileCannotOpenInputFile(ref err) => Some(err),
+            CannotReadTransactionFile(ref err)               => Some(err),
+            CannotRebuildTransaction(ref err)                => Some(err),
+            CannotRebuildTransactionTxIdMismatch       => None,
+            CannotSignTransactionUnknownWallet(_)      => None,
+            CannotSignTransactionSigner(ref err)             => Some(err),
+            CannotSignTransactionInvalidBalance(ref err)     => Some(err),
+            CannotSignTransactionNotConfirmed          => None,
+            CannotSubmitTransactionInvalidProtocolMagic => None,
+            CannotSendTransactionMissingSignatures(_)  => None,
+            CannotSignTransactionUnknownInput(_)       => None,
+            CannotMergeTransactionNotFinalized(ref err)      => Some(err),
+            CannotMergeTransactionInvalidTx(ref err)         => Some(err),
+            CannotMergeTransactionMismatch             => None,
+            CannotMergeTransactionCannotAddSignature(ref err) => Some(err),
+            CannotMergeTransactionCannotFindInput(ref err)   => Some(err),
+            CannotMergeTransactionUnknownInput(_)      => None,
+            CannotMergeTransactionInvalidSignature(_)  => None,
         }
     }
 }
@@ -141,7 +212,7 @@ pub fn new( term: &mut Term
           )
     -> Result<(), Error>
 {
-    let blockchain = Blockchain::load(root_dir.clone(), blockchain);
+    let blockchain = blockchain::commands::load_read_only(term, root_dir.clone(), blockchain);
 
     let staging = StagingTransaction::new(root_dir, blockchain.config.protocol_magic)
         .map_err(Error::CannotCreateNewTransaction)?;
@@ -195,23 +266,40 @@ pub fn send( term: &mut Term
            )
     -> Result<(), Error>
 {
-    let blockchain = Blockchain::load(root_dir.clone(), blockchain);
+    let blockchain = blockchain::commands::load_read_only(term, root_dir.clone(), blockchain);
     let staging = load_staging(root_dir.clone(), id_str)?;
 
     let (finalized, changes) = staging.transaction().mk_finalized()
         .map_err(Error::CannotSendTransactionNotFinalized)?;
+    let missing = staging.transaction().missing_witnesses();
+    if ! missing.is_empty() {
+        return Err(Error::CannotSendTransactionMissingSignatures(missing.len()));
+    }
     let txaux = finalized.make_txaux()
         .map_err(Error::CannotSendTransactionInvalidTxAux)?;
 
+    send_txaux(term, &blockchain, txaux)
+}
+
+/// send the transaction to the peers of the blockchain accepting them (the
+/// native peers, see `Blockchain::transaction_peers`), succeeds if
+/// at least one of them accepted it
+fn send_txaux(term: &mut Term, blockchain: &Blockchain, txaux: tx::TxAux) -> Result<(), Error> {
     writeln!(term, "sending transaction {}", style!(txaux.tx.id()))?;
 
     let mut sent = false;
-    for np in blockchain.peers() {
-        if ! np.is_native() { continue; }
-
-        let peer = super::super::blockchain::peer::Peer::prepare(&blockchain, np.name().to_owned());
+    for alias in blockchain.transaction_peers() {
+        let peer = match super::super::blockchain::peer::Peer::prepare(&blockchain, alias) {
+            Ok(peer) => peer,
+            Err(err) => {
+                term.warn(&format!("{}\n", err))?;
+                continue;
+            },
+        };
 
-        sent = sent || peer.connect(term).unwrap().send_txaux(txaux.clone());
+        if let Ok(connected_peer) = peer.connect(term) {
+            sent = connected_peer.send_txaux(txaux.clone()) || sent;
+        }
     }
 
     if sent { Ok(()) } else {
@@ -219,9 +307,15 @@ pub fn send( term: &mut Term
     }
 }
 
+/// sign the inputs of the staging transaction owned by the local wallets
+///
+/// With `partial` the inputs of the other parties are left unsigned, for
+/// them to add their signatures (see `merge`). The inputs already signed
+/// are skipped.
 pub fn sign( term: &mut Term
            , root_dir: PathBuf
            , id_str: &str
+           , partial: bool
            )
     -> Result<(), Error>
 {
@@ -234,7 +328,7 @@ pub fn sign( term: &mut Term
     }
 
     let mut staging = load_staging(root_dir.clone(), id_str)?;
-    let (finalized, changes) = staging.transaction().mk_finalized()
+    staging.transaction().mk_finalized()
         .map_err(Error::CannotSignTransactionNotFinalized)?;
     let tx = staging.transaction().mk_txbuilder()
         .map_err(Error::CannotSignTransactionNotFinalized)?
@@ -243,8 +337,10 @@ pub fn sign( term: &mut Term
     let txid = tx.id();
     let protocol_magic = staging.protocol_magic;
 
-    // TODO: ignore already signed inputs
-    for input in staging.transaction().inputs() {
+    let mut signers = BTreeMap::new();
+    for (index, input) in staging.transaction().inputs().iter().enumerate() {
+        if staging.transaction().witness(index).is_some() { continue; }
+
         let txin = input.extract_txin();
         let mut signature = None;
         for (name, (wallet, state)) in wallets.iter() {
@@ -258,26 +354,340 @@ pub fn sign( term: &mut Term
                     )
                 ).unwrap();
 
-                signature = Some(wallet::utils::wallet_sign_tx(
-                    term, wallet, protocol_magic, &txid, &utxo.credited_addressing
-                ));
+                let signer = signer_of(&mut signers, name, wallet);
+                signature = Some(
+                    signer.sign(term, protocol_magic, &txid, &utxo.credited_addressing)
+                        .map_err(Error::CannotSignTransactionSigner)?
+                );
+                break;
             }
         }
 
-        if let Some(signature) = signature {
-            signatures.push(signature);
-        } else {
-            panic!("cannot sign input {:#?}", input)
+        match signature {
+            Some(signature) => signatures.push((index, signature)),
+            None if partial => {
+                term.info(
+                    &format!(
+                        "skipping input {}.{} (not in the local wallets)\n",
+                        style!(input.transaction_id),
+                        style!(input.index_in_transaction)
+                    )
+                )?;
+            },
+            None => return Err(Error::CannotSignTransactionUnknownInput(txin)),
         }
     }
 
-    for signature in signatures {
-        staging.add_signature(signature)
+    for (index, signature) in signatures {
+        staging.add_input_signature(index as u32, signature)
             .map_err(Error::CannotSignTransactionCannotAddSignature)?;
     }
+
+    let missing = staging.transaction().missing_witnesses().len();
+    if missing > 0 {
+        term.warn(&format!("{} input(s) still need to be signed\n", missing))?;
+    }
+    Ok(())
+}
+
+/// add the signatures of the exported staging transactions (see `export`),
+/// signed by the other parties, to the staging transaction
+///
+/// The exports need to be of the same transaction: same inputs and same
+/// outputs. The inputs already signed are kept as they are. Every
+/// signature merged is verified: it signs the transaction, with the key
+/// of the address of its input (found in the transaction index of the
+/// blockchain, see `Blockchain::query_output`).
+pub fn merge( term: &mut Term
+            , root_dir: PathBuf
+            , id_str: &str
+            , blockchain: String
+            , input_files: Vec<&str>
+            )
+    -> Result<(), Error>
+{
+    let blockchain = blockchain::commands::load_read_only(term, root_dir.clone(), blockchain);
+    let mut staging = load_staging(root_dir, id_str)?;
+    let tx = merged_tx(staging.transaction())?;
+    let txid = tx.id();
+
+    for input_file in input_files {
+        let export : core::staging_transaction::Export = read_transaction_file(Some(input_file))?;
+        if merged_tx(export.transaction())?.id() != txid {
+            return Err(Error::CannotMergeTransactionMismatch);
+        }
+
+        let mut merged = 0;
+        for index in staging.transaction().missing_witnesses() {
+            if let Some(witness) = export.transaction().witness(index) {
+                let txin = staging.transaction().inputs()[index].extract_txin();
+                verify_witness(&blockchain, staging.protocol_magic, &tx, txin, witness)?;
+                staging.add_input_signature(index as u32, witness.clone())
+                    .map_err(Error::CannotMergeTransactionCannotAddSignature)?;
+                merged += 1;
+            }
+        }
+        term.info(&format!("{} signature(s) merged from {}\n", merged, style!(input_file)))?;
+    }
+
+    let missing = staging.transaction().missing_witnesses().len();
+    if missing > 0 {
+        term.warn(&format!("{} input(s) still need to be signed\n", missing))?;
+    } else {
+        term.success("all the inputs are signed\n")?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct InspectInput {
+    id: String,
+    index: u32,
+    /// in lovelace
+    value: u64,
+    signed: bool,
+}
+
+/// the output of `inspect` in JSON
+#[derive(Serialize)]
+struct InspectReport {
+    finalized: bool,
+    /// the number of inputs still to sign
+    missing_signatures: usize,
+    inputs: Vec<InspectInput>,
+}
+
+/// the signatures of the staging transaction, per input
+pub fn inspect( term: &mut Term
+              , root_dir: PathBuf
+              , id_str: &str
+              )
+    -> Result<(), Error>
+{
+    let staging = load_staging(root_dir, id_str)?;
+    let trans = staging.transaction();
+
+    let report = InspectReport {
+        finalized: trans.is_finalized(),
+        missing_signatures: trans.missing_witnesses().len(),
+        inputs: trans.inputs().iter().enumerate().map(|(index, input)| InspectInput {
+            id: format!("{}", input.transaction_id),
+            index: input.index_in_transaction,
+            value: input.expected_value.into(),
+            signed: trans.witness(index).is_some(),
+        }).collect(),
+    };
+
+    if term.is_json() {
+        term.json(&report)?;
+        return Ok(());
+    }
+
+    writeln!(term, "finalized: {}", report.finalized)?;
+    writeln!(term, "inputs:")?;
+    for input in report.inputs.iter() {
+        let status = if input.signed { "signed" } else { "missing signature" };
+        writeln!(term, "  {}.{} {} {}", style!(&input.id), style!(input.index), style!(input.value), status)?;
+    }
+    if report.missing_signatures > 0 {
+        writeln!(term, "{} of {} input(s) still need to be signed", report.missing_signatures, report.inputs.len())?;
+    } else if ! report.inputs.is_empty() {
+        writeln!(term, "all the inputs are signed")?;
+    }
     Ok(())
 }
 
+/// resolve the finalized staging transaction into an unsigned transaction
+/// file, to be signed on another device (see `sign_offline`)
+///
+/// The inputs need to be in the UTxOs of the local wallets, for the
+/// derivation paths of their addresses. Watch-only wallets will do.
+pub fn build( term: &mut Term
+            , root_dir: PathBuf
+            , id_str: &str
+            , output_file: Option<&str>
+            )
+    -> Result<(), Error>
+{
+    let staging = load_staging(root_dir.clone(), id_str)?;
+    staging.transaction().mk_finalized()
+        .map_err(Error::CannotBuildTransactionNotFinalized)?;
+    let (builder, changes) = staging.transaction().mk_txbuilder()
+        .map_err(Error::CannotBuildTransactionNotFinalized)?;
+    let tx = builder.make_tx()
+        .map_err(Error::CannotBuildTransactionInvalidTx)?;
+
+    let mut wallets = Vec::new();
+    for (name, wallet) in Wallets::load(root_dir.clone()).unwrap() {
+        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, root_dir.clone(), wallet::state::lookup::accum::Accum::default());
+        wallets.push((name, state));
+    }
+
+    let mut inputs = Vec::with_capacity(tx.inputs.len());
+    for input in staging.transaction().inputs() {
+        let txin = input.extract_txin();
+        let owner = wallets.iter().filter_map(|(name, state)| {
+            state.utxos.get(&txin).map(|utxo| (name, utxo))
+        }).next();
+        let (name, utxo) = owner.ok_or(Error::CannotBuildTransactionUnknownInput(txin.clone()))?;
+
+        inputs.push(offline::UnsignedInput {
+            transaction_id: input.transaction_id,
+            index_in_transaction: input.index_in_transaction,
+            expected_value: input.expected_value,
+            wallet: format!("{}", name),
+            addressing: utxo.credited_addressing.clone(),
+        });
+    }
+
+    let unsigned = offline::UnsignedTransaction {
+        protocol_magic: staging.protocol_magic,
+        txid: tx.id(),
+        inputs: inputs,
+        outputs: tx.outputs.iter().map(|output| core::Output {
+            address: output.address.clone(),
+            amount: output.value,
+        }).collect(),
+        change: changes.first().map(|change| change.address.clone()),
+    };
+
+    write_transaction_file(term, output_file, &unsigned)?;
+    term.info(&format!("transaction {} ready to be signed\n", style!(unsigned.txid)))?;
+    Ok(())
+}
+
+/// sign an unsigned transaction file (see `build`) with the local wallets
+///
+/// Only the keys of the wallets are needed: neither the staging
+/// transaction nor the wallets' logs, nor a blockchain.
+///
+/// The file is prepared on another device: the outputs, the change and
+/// the fee (from the values of the inputs of the file) are displayed and
+/// the signing needs to be confirmed, unless `assume_yes`. The
+/// transaction identifier of the file is only checked against the
+/// transaction rebuilt from the file itself, it detects a corrupted file
+/// but says nothing of what the transaction does.
+pub fn sign_offline( term: &mut Term
+                   , root_dir: PathBuf
+                   , input_file: Option<&str>
+                   , output_file: Option<&str>
+                   , assume_yes: bool
+                   )
+    -> Result<(), Error>
+{
+    let unsigned : offline::UnsignedTransaction = read_transaction_file(input_file)?;
+    let tx = unsigned.make_tx().map_err(Error::CannotRebuildTransaction)?;
+    let txid = tx.id();
+    if txid != unsigned.txid {
+        return Err(Error::CannotRebuildTransactionTxIdMismatch);
+    }
+
+    let fee = unsigned.fee().map_err(Error::CannotSignTransactionInvalidBalance)?;
+    term.info(&format!("transaction {}\n", style!(txid)))?;
+    for output in unsigned.outputs.iter() {
+        let kind = if unsigned.is_change(output) { "change" } else { "output" };
+        term.info(&format!("  {} {} {}\n", kind, style!(&output.address), style!(output.amount)))?;
+    }
+    term.info(&format!("  fee {}\n", style!(fee)))?;
+
+    if ! assume_yes {
+        // the standard input is the transaction file, there is nothing
+        // left to read the answer from
+        if input_file.is_none() {
+            return Err(Error::CannotSignTransactionNotConfirmed);
+        }
+        let confirmation = ::dialoguer::Confirmation::new("Sign this transaction?")
+            .use_line_input(true)
+            .clear(false)
+            .default(false)
+            .interact()?;
+        if ! confirmation {
+            term.info("the transaction has not been signed\n")?;
+            return Ok(());
+        }
+    }
+
+    let wallets = Wallets::load(root_dir).unwrap();
+    let mut signers = BTreeMap::new();
+    let mut witnesses = Vec::with_capacity(unsigned.inputs.len());
+    for input in unsigned.inputs.iter() {
+        let (name, wallet) = input.wallet.parse::<WalletName>().ok()
+            .and_then(|name| wallets.get_key_value(&name))
+            .ok_or(Error::CannotSignTransactionUnknownWallet(input.wallet.clone()))?;
+
+        term.info(
+            &format!(
+                "signing input {}.{} ({})\n",
+                style!(input.transaction_id),
+                style!(input.index_in_transaction),
+                style!(&input.wallet)
+            )
+        )?;
+        let signer = signer_of(&mut signers, name, wallet);
+        witnesses.push(
+            signer.sign(term, unsigned.protocol_magic, &txid, &input.addressing)
+                .map_err(Error::CannotSignTransactionSigner)?
+        );
+    }
+
+    let signed = offline::SignedTransaction {
+        transaction: unsigned,
+        witnesses: witnesses,
+    };
+    write_transaction_file(term, output_file, &signed)
+}
+
+/// send a signed transaction file (see `sign_offline`) to the peers of the
+/// blockchain
+pub fn submit( term: &mut Term
+             , root_dir: PathBuf
+             , blockchain: String
+             , input_file: Option<&str>
+             )
+    -> Result<(), Error>
+{
+    let blockchain = blockchain::commands::load_read_only(term, root_dir, blockchain);
+    let signed : offline::SignedTransaction = read_transaction_file(input_file)?;
+
+    if signed.transaction.protocol_magic != blockchain.config.protocol_magic {
+        return Err(Error::CannotSubmitTransactionInvalidProtocolMagic);
+    }
+    let txaux = signed.make_txaux()
+        .map_err(Error::CannotSendTransactionInvalidTxAux)?;
+    if txaux.tx.id() != signed.transaction.txid {
+        return Err(Error::CannotRebuildTransactionTxIdMismatch);
+    }
+
+    send_txaux(term, &blockchain, txaux)
+}
+
+#[derive(Serialize)]
+struct StatusInput {
+    id: String,
+    index: u32,
+}
+
+#[derive(Serialize)]
+struct StatusOutput {
+    address: String,
+    /// in lovelace
+    value: u64,
+}
+
+/// the output of `status` in JSON, the amounts are in lovelace
+#[derive(Serialize)]
+struct StatusReport {
+    input_total: u64,
+    output_total: u64,
+    /// the difference between the inputs and the outputs
+    actual_fee: i64,
+    /// the minimal fee of the transaction
+    fee: u64,
+    tx_bytes: usize,
+    inputs: Vec<StatusInput>,
+    outputs: Vec<StatusOutput>,
+}
+
 pub fn status( term: &mut Term
              , root_dir: PathBuf
              , id_str: &str
@@ -309,6 +719,26 @@ pub fn status( term: &mut Term
 
     let txbytes_length = tx::txaux_serialize_size(&tx, &fake_witnesses);
 
+    if term.is_json() {
+        let report = StatusReport {
+            input_total: input_total.into(),
+            output_total: output_total.into(),
+            actual_fee: difference,
+            fee: fee.to_coin().into(),
+            tx_bytes: txbytes_length,
+            inputs: tx.inputs.iter().map(|input| StatusInput {
+                id: format!("{}", input.id),
+                index: input.index,
+            }).collect(),
+            outputs: tx.outputs.iter().map(|output| StatusOutput {
+                address: format!("{}", output.address),
+                value: output.value.into(),
+            }).collect(),
+        };
+        term.json(&report)?;
+        return Ok(());
+    }
+
     writeln!(term, "input-total: {}", input_total)?;
     writeln!(term, "output-total: {}", output_total)?;
     writeln!(term, "actual fee: {}.{}", difference / 1000000, difference % 1000000)?;
@@ -498,18 +928,20 @@ pub fn import( term: &mut Term
     Ok(())
 }
 
+/// select inputs among the UTxOs of the given wallets to cover the outputs
+/// and the fee of the staging transaction (see `selection`)
+///
+/// The inputs already in the staging transaction are kept, and accounted
+/// for. With `Strategy::Manual` only the given inputs are selected.
 pub fn input_select( term: &mut Term
                    , root_dir: PathBuf
                    , id_str: &str
                    , wallets: Vec<WalletName>
+                   , strategy: selection::Strategy
+                   , manual_inputs: Vec<TxoPointer>
                    )
     -> Result<(), Error>
 {
-    use ::cardano::{fee::{self}, input_selection::{SelectionAlgorithm, SelectionPolicy}, txutils};
-
-    let alg = fee::LinearFee::default();
-    let selection_policy = SelectionPolicy::default();
-
     let mut staging = load_staging(root_dir.clone(), id_str)?;
 
     if ! staging.transaction().has_change() {
@@ -517,26 +949,197 @@ pub fn input_select( term: &mut Term
     }
 
     let change_address = staging.transaction().changes()[0].address.clone();
-    let output_policy = txutils::OutputPolicy::One(change_address.clone());
-
-    let outputs = staging.transaction().outputs().iter().map(|output| {
-        output.into()
-    }).collect::<Vec<_>>();
-    let inputs = list_input_inputs(term, root_dir.clone(), wallets);
-
-    let (_, selected_inputs, change) = alg.compute(
-        selection_policy,
-        inputs.iter(),
-        outputs.iter(),
-        &output_policy
-    ).map_err(Error::CannotInputSelectSelectionFailed)?;
-
-    for input in selected_inputs {
-        staging.add_input(core::Input {
-            transaction_id: input.ptr.id,
-            index_in_transaction: input.ptr.index,
-            expected_value: input.value.value
-        }).map_err(Error::CannotInputSelectCannotAddInput)?;
+    let selection = select_inputs(
+        term, root_dir, wallets, strategy, manual_inputs,
+        staging.transaction().inputs(),
+        staging.transaction().outputs(),
+        &change_address
+    )?;
+
+    term.info(&format!("{} inputs selected ({}), estimated fee {} for {} bytes\n",
+        selection.inputs.len(), strategy, selection.fee, selection.size))?;
+
+    for input in selection.inputs {
+        staging.add_input(input).map_err(Error::CannotInputSelectCannotAddInput)?;
+    }
+    Ok(())
+}
+
+/// the output of `estimate_fee` in JSON, the amounts are in lovelace
+#[derive(Serialize)]
+struct FeeEstimate {
+    fee: u64,
+    change: u64,
+    tx_bytes: usize,
+    inputs: Vec<StatusInput>,
+    /// the outputs, the change included
+    outputs: Vec<StatusOutput>,
+}
+
+/// compute the fee, the change and the size of the transaction sending the
+/// given outputs from the given wallets, without staging nor signing it
+///
+/// The inputs are selected as `input_select` would, the fee is the one
+/// of the transaction once signed.
+pub fn estimate_fee( term: &mut Term
+                   , root_dir: PathBuf
+                   , wallets: Vec<WalletName>
+                   , strategy: selection::Strategy
+                   , manual_inputs: Vec<TxoPointer>
+                   , outputs: Vec<core::Output>
+                   , change_address: ExtendedAddr
+                   )
+    -> Result<(), Error>
+{
+    let selection = select_inputs(term, root_dir, wallets, strategy, manual_inputs, &[], &outputs, &change_address)?;
+
+    let mut transaction = core::Transaction::new();
+    transaction.inputs = selection.inputs;
+    transaction.outputs = outputs;
+    transaction.changes.push(change_address.into());
+
+    let (builder, changes) = transaction.mk_txbuilder()
+        .map_err(Error::CannotReportStatusInvalidTxBuilder)?;
+    let tx = builder.make_tx()
+        .map_err(Error::CannotReportStatusInvalidTx)?;
+
+    let fake_witnesses : Vec<TxInWitness> = iter::repeat(TxInWitness::fake()).take(tx.inputs.len()).collect();
+    let fee = LinearFee::default().calculate_for_txaux_component(&tx, &fake_witnesses)
+        .map_err(Error::CannotReportStatusInvalidFee)?;
+    let txbytes_length = tx::txaux_serialize_size(&tx, &fake_witnesses);
+    let change = sum_coins(changes.iter().map(|change| change.value))
+        .map_err(Error::CannotReportStatusInvalidOutputTotal)?;
+
+    if term.is_json() {
+        let estimate = FeeEstimate {
+            fee: fee.to_coin().into(),
+            change: change.into(),
+            tx_bytes: txbytes_length,
+            inputs: tx.inputs.iter().map(|input| StatusInput {
+                id: format!("{}", input.id),
+                index: input.index,
+            }).collect(),
+            outputs: tx.outputs.iter().map(|output| StatusOutput {
+                address: format!("{}", output.address),
+                value: output.value.into(),
+            }).collect(),
+        };
+        term.json(&estimate)?;
+        return Ok(());
+    }
+
+    writeln!(term, "fee: {}", fee.to_coin())?;
+    writeln!(term, "change: {}", change)?;
+    writeln!(term, "tx-bytes: {}", txbytes_length)?;
+
+    writeln!(term, "inputs:")?;
+    for input in tx.inputs.iter() {
+        writeln!(term, "  {}.{}", style!(input.id), style!(input.index))?;
+    }
+    writeln!(term, "outputs:")?;
+    for output in tx.outputs.iter() {
+        writeln!(term, "  {} {}", style!(&output.address), style!(output.value))?;
+    }
+
+    Ok(())
+}
+
+/// helper function to run the input selection over the UTxOs of the given
+/// wallets, the inputs already `selected` are left out
+fn select_inputs( term: &mut Term
+                , root_dir: PathBuf
+                , wallets: Vec<WalletName>
+                , strategy: selection::Strategy
+                , manual_inputs: Vec<TxoPointer>
+                , selected: &[core::Input]
+                , outputs: &[core::Output]
+                , change_address: &ExtendedAddr
+                )
+    -> Result<selection::Selection, Error>
+{
+    let mut available : Vec<core::Input> = list_input_inputs(term, root_dir, wallets).into_iter()
+        .filter(|input| ! selected.iter().any(|s| s.extract_txin() == input.extract_txin()))
+        .collect();
+    if strategy == selection::Strategy::Manual {
+        let mut picked = Vec::with_capacity(manual_inputs.len());
+        for txin in manual_inputs {
+            let index = available.iter().position(|input| input.extract_txin() == txin)
+                .ok_or(Error::CannotFindInputsInAllLocalUtxos)?;
+            picked.push(available.remove(index));
+        }
+        available = picked;
+    }
+
+    selection::select(&mut *strategy.boxed(), selected, available, outputs, change_address)
+        .map_err(Error::CannotInputSelectSelectionFailed)
+}
+
+/// helper function to get the signer of the wallet, created on its first use
+/// (so the password is only asked once per wallet)
+fn signer_of<'a, 'b>( signers: &'b mut BTreeMap<&'a WalletName, Box<wallet::signer::Signer + 'a>>
+                    , name: &'a WalletName
+                    , wallet: &'a Wallet
+                    )
+    -> &'b mut Box<wallet::signer::Signer + 'a>
+{
+    signers.entry(name).or_insert_with(|| wallet::signer::signer(wallet))
+}
+
+/// helper function to write a transaction file, or to the standard output
+fn write_transaction_file<T: ::serde::Serialize>(term: &mut Term, output_file: Option<&str>, value: &T) -> Result<(), Error> {
+    if let Some(output_file) = output_file {
+        let mut file = ::std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(output_file)
+            .map_err(Error::CannotWriteTransactionFileCannotOpenOutFile)?;
+        ::serde_yaml::to_writer(&mut file, value)
+            .map_err(Error::CannotWriteTransactionFile)
+    } else {
+        ::serde_yaml::to_writer(term, value)
+            .map_err(Error::CannotWriteTransactionFile)
+    }
+}
+
+/// helper function to read a transaction file, or the standard input
+fn read_transaction_file<T>(input_file: Option<&str>) -> Result<T, Error>
+    where for<'de> T: ::serde::Deserialize<'de>
+{
+    if let Some(input_file) = input_file {
+        let mut file = ::std::fs::OpenOptions::new().read(true).open(input_file)
+            .map_err(Error::CannotReadTransactionFileCannotOpenInputFile)?;
+        ::serde_yaml::from_reader(&mut file)
+            .map_err(Error::CannotReadTransactionFile)
+    } else {
+        let mut stdin = ::std::io::stdin();
+        ::serde_yaml::from_reader(&mut stdin)
+            .map_err(Error::CannotReadTransactionFile)
+    }
+}
+
+/// helper function to get the identifier of a finalized transaction
+fn merged_tx(transaction: &core::Transaction) -> Result<Tx, Error> {
+    transaction.mk_finalized()
+        .map_err(Error::CannotMergeTransactionNotFinalized)?;
+    let tx = transaction.mk_txbuilder()
+        .map_err(Error::CannotMergeTransactionNotFinalized)?
+        .0.make_tx()
+        .map_err(Error::CannotMergeTransactionInvalidTx)?;
+    Ok(tx)
+}
+
+/// the witness signs the transaction, with the key of the address of the
+/// output spent by the input
+fn verify_witness( blockchain: &Blockchain
+                 , protocol_magic: cardano::config::ProtocolMagic
+                 , tx: &Tx
+                 , txin: TxoPointer
+                 , witness: &TxInWitness
+                 ) -> Result<(), Error>
+{
+    let output = match blockchain.query_output(&txin).map_err(Error::CannotMergeTransactionCannotFindInput)? {
+        None => return Err(Error::CannotMergeTransactionUnknownInput(txin)),
+        Some(output) => output,
+    };
+    if ! witness.verify_tx(protocol_magic, tx) || ! witness.verify_address(&output.address) {
+        return Err(Error::CannotMergeTransactionInvalidSignature(txin));
     }
     Ok(())
 }
@@ -570,7 +1173,7 @@ fn find_input_in_all_utxos(term: &mut Term, root_dir: PathBuf, txid: TxId, index
     Err(Error::CannotFindInputsInAllLocalUtxos)
 }
 
-fn list_input_inputs(term: &mut Term, root_dir: PathBuf, wallets: Vec<WalletName>) -> Vec<::cardano::txutils::Input<ExtendedAddr>> {
+fn list_input_inputs(term: &mut Term, root_dir: PathBuf, wallets: Vec<WalletName>) -> Vec<core::Input> {
     let mut inputs = Vec::new();
     for wallet in wallets {
         let wallet = Wallet::load(root_dir.clone(), wallet);
@@ -578,12 +1181,11 @@ fn list_input_inputs(term: &mut Term, root_dir: PathBuf, wallets: Vec<WalletName
 
         inputs.extend(state.utxos.iter().map(|(_, utxo)| {
             let txin = utxo.extract_txin();
-            let txout = utxo.extract_txout();
-            ::cardano::txutils::Input::new(
-                txin,
-                txout,
-                utxo.credited_address.clone()
-            )
+            core::Input {
+                transaction_id: txin.id,
+                index_in_transaction: txin.index,
+                expected_value: utxo.credited_value,
+            }
         }))
     }
 
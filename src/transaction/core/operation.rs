@@ -49,9 +49,12 @@ pub enum Operation {
     ///
     RemoveOutput(u32),
 
-    /// add a transaction signature
+    /// add a transaction signature, for the first input not signed yet
     Signature(TxInWitness),
 
+    /// add the signature of the input at the given index
+    InputSignature(u32, TxInWitness),
+
     /// operation to finalize a transaction
     Finalize
 }
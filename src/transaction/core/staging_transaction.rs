@@ -91,7 +91,15 @@ impl StagingTransaction {
         for output in export.transaction.outputs {
             st.add_output(output)?;
         }
+        for change in export.transaction.changes {
+            st.add_change(change)?;
+        }
         if export.transaction.finalized { st.finalize()?; }
+        for (index, witness) in export.transaction.witnesses.into_iter().enumerate() {
+            if let Some(witness) = witness {
+                st.add_input_signature(index as u32, witness)?;
+            }
+        }
 
         Ok(st)
     }
@@ -188,6 +196,12 @@ impl StagingTransaction {
         self.append(Operation::Signature(signature))
     }
 
+    /// add the signature of the input at the given index, the inputs can
+    /// be signed in any order (see `transaction sign --partial`)
+    pub fn add_input_signature(&mut self, index: u32, signature: TxInWitness) -> Result<(), StagingUpdateError> {
+        self.append(Operation::InputSignature(index, signature))
+    }
+
     /// add the given input to the transaction
     ///
     /// # panic
@@ -388,6 +402,11 @@ pub struct Export {
     protocol_magic: ProtocolMagic,
     transaction: Transaction,
 }
+impl Export {
+    pub fn staging_id(&self) -> &StagingId { &self.staging_id }
+
+    pub fn transaction(&self) -> &Transaction { &self.transaction }
+}
 impl From<StagingTransaction> for Export {
     fn from(st: StagingTransaction) -> Self {
         Export {
@@ -1,5 +1,5 @@
 use super::{Operation, Input, Output, Change};
-use cardano::{tx::{TxoPointer, TxOut, TxWitness, TxInWitness}, address::{ExtendedAddr}};
+use cardano::{tx::{TxoPointer, TxOut, TxInWitness}, address::{ExtendedAddr}};
 use cardano::{txbuild::{self, TxBuilder, TxFinalized}, fee::{LinearFee}, txutils::{OutputPolicy}};
 use std::{fmt, error};
 
@@ -13,6 +13,12 @@ pub enum Error {
 
     CannotAddMoreWitnessesThanInputs,
 
+    /// the index of the witness is not the index of one of the inputs
+    CannotAddWitnessToAnUnknownInput(u32),
+
+    /// the input has already been signed
+    InputAlreadySigned(u32),
+
     CannotAddInputsToAFinalizedTransaction,
 
     CannotAddOutputToAFinalizedTransaction,
@@ -45,6 +51,8 @@ impl fmt::Display for Error {
             Error::CannotFinalizeAFinalizedTransaction => write!(f, "Transaction is already in a finalized state"),
             Error::CannotAddWitnessesToAnOpenedTransaction => write!(f, "Transaction is not finalized, finalize the transaction before adding witnesses"),
             Error::CannotAddMoreWitnessesThanInputs => write!(f, "There is already enough witness for the transaction, cannot add more witnesses than inputs."),
+            Error::CannotAddWitnessToAnUnknownInput(index) => write!(f, "Cannot add witness, there is no input {} in the transaction", index),
+            Error::InputAlreadySigned(index) => write!(f, "Input {} is already signed", index),
             Error::CannotAddInputsToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more inputs"),
             Error::CannotAddOutputToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more outputs"),
             Error::CannotAddChangeToAFinalizedTransaction => write!(f, "Transaction is in a finalized state, cannot add more change addresses"),
@@ -65,6 +73,8 @@ impl error::Error for Error {
             Error::CannotFinalizeAFinalizedTransaction => None,
             Error::CannotAddWitnessesToAnOpenedTransaction => None,
             Error::CannotAddMoreWitnessesThanInputs => None,
+            Error::CannotAddWitnessToAnUnknownInput(_) => None,
+            Error::InputAlreadySigned(_) => None,
             Error::CannotAddInputsToAFinalizedTransaction => None,
             Error::CannotAddOutputToAFinalizedTransaction => None,
             Error::CannotAddChangeToAFinalizedTransaction => None,
@@ -101,7 +111,9 @@ pub struct Transaction {
     pub inputs:    Vec<Input>,
     pub outputs:   Vec<Output>,
     pub changes:   Vec<Change>,
-    pub witnesses: TxWitness,
+    /// the witnesses of the inputs, by index. The inputs may be signed in
+    /// any order, by different parties (see `Operation::InputSignature`).
+    pub witnesses: Vec<Option<TxInWitness>>,
     pub finalized: bool
 }
 impl Transaction {
@@ -111,7 +123,7 @@ impl Transaction {
             inputs: Vec::new(),
             outputs: Vec::new(),
             changes: Vec::new(),
-            witnesses: TxWitness::new(),
+            witnesses: Vec::new(),
             finalized: false,
         }
     }
@@ -136,6 +148,8 @@ impl Transaction {
         Ok((builder, changes_used))
     }
 
+    /// the witnesses are added in the order of the inputs, up to the first
+    /// input not signed yet (see `missing_witnesses`)
     pub fn mk_finalized(&self) -> Result<(TxFinalized, Vec<TxOut>)> {
         let (builder, changes_used) = self.mk_txbuilder()?;
 
@@ -145,7 +159,7 @@ impl Transaction {
         let tx = builder.make_tx().map_err(Error::CannotBuildTxFromBuilder)?;
         let mut finalized = TxFinalized::new(tx);
 
-        for signature in self.witnesses.iter() {
+        for signature in self.witnesses.iter().take_while(|w| w.is_some()).filter_map(|w| w.as_ref()) {
             finalized.add_witness(signature.clone())
                 .map_err(Error::CannotBuildTxFromBuilder)?;
         }
@@ -163,6 +177,7 @@ impl Transaction {
             Operation::RemoveOutput(index) => self.remove_output(index),
             Operation::RemoveChange(addr)  => self.remove_change(addr),
             Operation::Signature(witness)  => self.add_witness(witness),
+            Operation::InputSignature(index, witness) => self.add_input_witness(index, witness),
             Operation::Finalize            => self.finalize(),
         }
     }
@@ -177,7 +192,17 @@ impl Transaction {
     /// returns reference to the change
     pub fn changes<'a>(&'a self) -> &'a [Change] { self.changes.as_ref() }
 
-    pub fn signature<'a>(&'a self) -> &'a [TxInWitness] { self.witnesses.as_ref() }
+    /// the witness of the input at the given index, if signed
+    pub fn witness<'a>(&'a self, index: usize) -> Option<&'a TxInWitness> {
+        self.witnesses.get(index).and_then(|w| w.as_ref())
+    }
+
+    /// the indices of the inputs not signed yet
+    pub fn missing_witnesses(&self) -> Vec<usize> {
+        (0..self.inputs.len()).filter(|index| self.witness(*index).is_none()).collect()
+    }
+
+    pub fn is_fully_signed(&self) -> bool { self.missing_witnesses().is_empty() }
 
     pub fn has_change(&self) -> bool { ! self.changes.is_empty() }
 
@@ -204,12 +229,23 @@ impl Transaction {
         self.changes.push(change);
         Ok(self)
     }
+    /// the witness of the first input not signed yet
     fn add_witness(&mut self, witness: TxInWitness) -> Result<&mut Self> {
-        if ! self.is_finalized() { return Err(Error::CannotAddWitnessesToAnOpenedTransaction); }
-        if self.inputs.len() <= self.witnesses.len() {
-            return Err(Error::CannotAddMoreWitnessesThanInputs);
+        match self.missing_witnesses().first() {
+            None => {
+                if ! self.is_finalized() { return Err(Error::CannotAddWitnessesToAnOpenedTransaction); }
+                Err(Error::CannotAddMoreWitnessesThanInputs)
+            },
+            Some(index) => self.add_input_witness(*index as u32, witness),
         }
-        self.witnesses.push(witness);
+    }
+    fn add_input_witness(&mut self, index: u32, witness: TxInWitness) -> Result<&mut Self> {
+        if ! self.is_finalized() { return Err(Error::CannotAddWitnessesToAnOpenedTransaction); }
+        let i = index as usize;
+        if i >= self.inputs.len() { return Err(Error::CannotAddWitnessToAnUnknownInput(index)); }
+        if self.witness(i).is_some() { return Err(Error::InputAlreadySigned(index)); }
+        if self.witnesses.len() <= i { self.witnesses.resize(i + 1, None); }
+        self.witnesses[i] = Some(witness);
         Ok(self)
     }
 
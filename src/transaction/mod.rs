@@ -1,2 +1,4 @@
 pub mod core;
 pub mod commands;
+pub mod offline;
+pub mod selection;
@@ -0,0 +1,99 @@
+//! the transaction files exchanged between an online and an offline device
+//!
+//! 1. `transaction build`, on the online device, resolves a finalized
+//!    staging transaction into an `UnsignedTransaction`: the inputs with
+//!    the wallet and the derivation path of their addresses, and all the
+//!    outputs (the change included);
+//! 2. `transaction sign`, on the offline device, only needs the keys of the
+//!    wallets to add the witnesses, resulting in a `SignedTransaction`. The
+//!    outputs, the change and the fee (what the inputs do not pay to the
+//!    outputs) are displayed to be confirmed first;
+//! 3. `transaction submit`, on the online device, sends the signed
+//!    transaction to the peers of a blockchain.
+//!
+//! The files are in YAML, as the staging transaction exports.
+//!
+
+use cardano::{tx::{Tx, TxId, TxAux, TxOut, TxoPointer, TxInWitness}, address::ExtendedAddr, coin::{self, Coin, sum_coins}, config::ProtocolMagic};
+use cardano::txbuild::{self, TxBuilder, TxFinalized};
+
+use super::core::{Output};
+use super::super::wallet::state::lookup;
+
+/// an input of the transaction, with what is needed to sign it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedInput {
+    pub transaction_id: TxId,
+    pub index_in_transaction: u32,
+    pub expected_value: Coin,
+    /// the name of the wallet owning the input
+    pub wallet: String,
+    /// the derivation path of the address of the input, within the wallet
+    pub addressing: lookup::Address,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    pub protocol_magic: ProtocolMagic,
+    /// the identifier of the transaction, to check it is rebuilt unchanged
+    pub txid: TxId,
+    pub inputs: Vec<UnsignedInput>,
+    pub outputs: Vec<Output>,
+    /// the address of the change output (it is one of the `outputs`),
+    /// `None` if the transaction has no change
+    #[serde(default)]
+    pub change: Option<ExtendedAddr>,
+}
+impl UnsignedTransaction {
+    /// the sum of the values of the inputs
+    pub fn input_total(&self) -> coin::Result<Coin> {
+        sum_coins(self.inputs.iter().map(|input| input.expected_value))
+    }
+
+    /// the sum of the values of the outputs, the change included
+    pub fn output_total(&self) -> coin::Result<Coin> {
+        sum_coins(self.outputs.iter().map(|output| output.amount))
+    }
+
+    /// the fee of the transaction: what the inputs do not pay to the
+    /// outputs. Fails if the outputs are more than the inputs.
+    pub fn fee(&self) -> coin::Result<Coin> {
+        self.input_total()? - self.output_total()?
+    }
+
+    /// the given output is the change
+    pub fn is_change(&self, output: &Output) -> bool {
+        self.change.as_ref().map(|change| change == &output.address).unwrap_or(false)
+    }
+
+    /// rebuild the transaction from the inputs and the outputs
+    ///
+    /// the identifier of the result needs to be checked against `txid`.
+    pub fn make_tx(&self) -> Result<Tx, txbuild::Error> {
+        let mut builder = TxBuilder::new();
+        for input in self.inputs.iter() {
+            let ptr = TxoPointer { id: input.transaction_id, index: input.index_in_transaction };
+            builder.add_input(&ptr, input.expected_value);
+        }
+        for output in self.outputs.iter() {
+            builder.add_output_value(&TxOut { address: output.address.clone(), value: output.amount });
+        }
+        builder.make_tx()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    pub transaction: UnsignedTransaction,
+    /// the witnesses, in the order of the inputs
+    pub witnesses: Vec<TxInWitness>,
+}
+impl SignedTransaction {
+    pub fn make_txaux(&self) -> Result<TxAux, txbuild::Error> {
+        let mut finalized = TxFinalized::new(self.transaction.make_tx()?);
+        for witness in self.witnesses.iter() {
+            finalized.add_witness(witness.clone())?;
+        }
+        finalized.make_txaux()
+    }
+}
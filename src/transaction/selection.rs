@@ -0,0 +1,340 @@
+//! the selection of the inputs of a transaction among the wallets' UTxOs
+//!
+//! The inputs are picked one at a time by a `SelectionStrategy` until they
+//! cover the outputs and the fee. The fee is estimated again after every
+//! pick: every input makes the transaction (hence its fee) larger. The
+//! estimation accounts for a change output and a witness per input.
+//!
+
+use std::{fmt, error, iter, str::FromStr};
+use cardano::{tx::{self, Tx, TxOut, TxInWitness}, coin::{self, Coin}, address::ExtendedAddr, fee::{self, LinearFee, FeeAlgorithm}};
+use rand::{self, Rng};
+
+use super::core::{Input, Output};
+
+/// the maximum size of a transaction, witnesses included, accepted by the
+/// network
+pub const MAX_TX_SIZE : usize = 65536;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the inputs available (in lovelace) do not cover the outputs and
+    /// the fee (in lovelace)
+    NotEnoughFunds { available: u64, needed: u64 },
+    /// the selected inputs make a transaction larger than `MAX_TX_SIZE`
+    TransactionTooLarge(usize),
+    InvalidFee(fee::Error),
+    InvalidCoin(coin::Error),
+}
+impl From<fee::Error> for Error {
+    fn from(e: fee::Error) -> Self { Error::InvalidFee(e) }
+}
+impl From<coin::Error> for Error {
+    fn from(e: coin::Error) -> Self { Error::InvalidCoin(e) }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotEnoughFunds { available, needed } => write!(f, "Not enough funds, {} lovelace available for {} lovelace needed", available, needed),
+            Error::TransactionTooLarge(size)            => write!(f, "The selected inputs make a transaction of {} bytes (maximum {})", size, MAX_TX_SIZE),
+            Error::InvalidFee(_)                        => write!(f, "Fee computation returned an error"),
+            Error::InvalidCoin(_)                       => write!(f, "Invalid value"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<& error::Error> {
+        match self {
+            Error::NotEnoughFunds { .. }   => None,
+            Error::TransactionTooLarge(_)  => None,
+            Error::InvalidFee(ref err)     => Some(err),
+            Error::InvalidCoin(ref err)    => Some(err),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// the selection algorithms supported by `transaction input-select`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    FirstMatch,
+    LargestFirst,
+    RandomImprove,
+    Manual,
+}
+impl Strategy {
+    pub fn as_string(self) -> &'static str {
+        match self {
+            Strategy::FirstMatch    => "first-match",
+            Strategy::LargestFirst  => "largest-first",
+            Strategy::RandomImprove => "random-improve",
+            Strategy::Manual        => "manual",
+        }
+    }
+
+    pub fn boxed(self) -> Box<SelectionStrategy> {
+        match self {
+            Strategy::FirstMatch    => Box::new(FirstMatch),
+            Strategy::LargestFirst  => Box::new(LargestFirst),
+            Strategy::RandomImprove => Box::new(RandomImprove),
+            Strategy::Manual        => Box::new(Manual),
+        }
+    }
+}
+impl FromStr for Strategy {
+    type Err = &'static str;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "first-match"    => Ok(Strategy::FirstMatch),
+            "largest-first"  => Ok(Strategy::LargestFirst),
+            "random-improve" => Ok(Strategy::RandomImprove),
+            "manual"         => Ok(Strategy::Manual),
+            _                => Err("Invalid selection strategy"),
+        }
+    }
+}
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_string())
+    }
+}
+
+pub trait SelectionStrategy {
+    /// the index, in `available`, of the next input to select, `None` to
+    /// stop the selection
+    ///
+    /// `selected` is the total of the inputs already selected and `needed`
+    /// the total of the outputs plus the fee of the current selection.
+    fn next(&mut self, available: &[Input], selected: u64, needed: u64) -> Option<usize>;
+}
+
+fn value(input: &Input) -> u64 { input.expected_value.into() }
+
+/// the inputs in the order of the wallets' UTxOs
+pub struct FirstMatch;
+impl SelectionStrategy for FirstMatch {
+    fn next(&mut self, available: &[Input], selected: u64, needed: u64) -> Option<usize> {
+        if selected >= needed || available.is_empty() { None } else { Some(0) }
+    }
+}
+
+/// the largest inputs first, for as few inputs as possible (hence the
+/// smallest fee)
+pub struct LargestFirst;
+impl SelectionStrategy for LargestFirst {
+    fn next(&mut self, available: &[Input], selected: u64, needed: u64) -> Option<usize> {
+        if selected >= needed { return None; }
+        available.iter().enumerate().max_by_key(|(_, input)| value(input)).map(|(index, _)| index)
+    }
+}
+
+/// random inputs until the outputs are covered, then more random inputs
+/// as long as they bring the change closer to the outputs' amount
+///
+/// The change outputs are then about the size of the payments, instead of
+/// dust or of the whole wallet.
+pub struct RandomImprove;
+impl SelectionStrategy for RandomImprove {
+    fn next(&mut self, available: &[Input], selected: u64, needed: u64) -> Option<usize> {
+        if available.is_empty() { return None; }
+        if selected < needed {
+            return Some(rand::thread_rng().gen_range(0, available.len()));
+        }
+
+        let ideal = needed.saturating_mul(2);
+        let improving : Vec<usize> = available.iter().enumerate()
+            .filter(|(_, input)| selected + value(input) <= ideal)
+            .map(|(index, _)| index)
+            .collect();
+        if improving.is_empty() { return None; }
+        Some(improving[rand::thread_rng().gen_range(0, improving.len())])
+    }
+}
+
+/// all the given inputs, and only them
+pub struct Manual;
+impl SelectionStrategy for Manual {
+    fn next(&mut self, available: &[Input], _selected: u64, _needed: u64) -> Option<usize> {
+        if available.is_empty() { None } else { Some(0) }
+    }
+}
+
+/// the fee and the size of a transaction with the given inputs and
+/// outputs, plus a change output
+pub fn estimate(inputs: &[Input], outputs: &[Output], change: &ExtendedAddr) -> Result<(Coin, usize)> {
+    let mut tx = Tx::new();
+    for input in inputs {
+        tx.inputs.push(input.extract_txin());
+    }
+    for output in outputs {
+        tx.outputs.push(TxOut { address: output.address.clone(), value: output.amount });
+    }
+    // the change is at most the total of the inputs
+    let change_value = coin::sum_coins(inputs.iter().map(|input| input.expected_value))?;
+    tx.outputs.push(TxOut { address: change.clone(), value: change_value });
+
+    let fake_witnesses : Vec<TxInWitness> = iter::repeat(TxInWitness::fake()).take(inputs.len()).collect();
+    let fee = LinearFee::default().calculate_for_txaux_component(&tx, &fake_witnesses)?;
+    Ok((fee.to_coin(), tx::txaux_serialize_size(&tx, &fake_witnesses)))
+}
+
+/// the result of a selection
+#[derive(Debug)]
+pub struct Selection {
+    /// the inputs newly selected
+    pub inputs: Vec<Input>,
+    /// the estimated fee of the transaction
+    pub fee: Coin,
+    /// the estimated size of the transaction, in bytes
+    pub size: usize,
+}
+
+/// select the inputs, among `available`, to complete the inputs already in
+/// the transaction (`selected`)
+pub fn select( strategy: &mut SelectionStrategy
+             , selected: &[Input]
+             , mut available: Vec<Input>
+             , outputs: &[Output]
+             , change: &ExtendedAddr
+             ) -> Result<Selection>
+{
+    let output_total : u64 = coin::sum_coins(outputs.iter().map(|output| output.amount))?.into();
+
+    let mut inputs : Vec<Input> = selected.to_vec();
+    let mut new_inputs = Vec::new();
+    loop {
+        let (fee, size) = estimate(&inputs, outputs, change)?;
+        let input_total : u64 = coin::sum_coins(inputs.iter().map(|input| input.expected_value))?.into();
+        let needed = output_total + u64::from(fee);
+
+        match strategy.next(&available, input_total, needed) {
+            Some(index) => {
+                let input = available.remove(index);
+                inputs.push(input.clone());
+                new_inputs.push(input);
+            },
+            None => {
+                if input_total < needed {
+                    return Err(Error::NotEnoughFunds { available: input_total, needed: needed });
+                }
+                if size > MAX_TX_SIZE {
+                    return Err(Error::TransactionTooLarge(size));
+                }
+                return Ok(Selection { inputs: new_inputs, fee: fee, size: size });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{hdwallet, tx::TxId};
+
+    fn change_address() -> ExtendedAddr {
+        ExtendedAddr::new_simple(hdwallet::XPrv::normalize_bytes([7; hdwallet::XPRV_SIZE]).public())
+    }
+
+    fn input(index: u32, value: u64) -> Input {
+        Input {
+            transaction_id: TxId::new(&index.to_le_bytes()),
+            index_in_transaction: index,
+            expected_value: Coin::new(value).unwrap(),
+        }
+    }
+
+    fn outputs(value: u64) -> Vec<Output> {
+        vec![Output { address: change_address(), amount: Coin::new(value).unwrap() }]
+    }
+
+    fn values(inputs: &[Input]) -> Vec<u64> { inputs.iter().map(value).collect() }
+
+    /// the fee of the selection is the fee of its final inputs, and they
+    /// cover the outputs and the fee: the change is returned
+    fn change_of(selection: &Selection, outputs: &[Output]) -> u64 {
+        let (fee, size) = estimate(&selection.inputs, outputs, &change_address()).unwrap();
+        assert_eq!(selection.fee, fee);
+        assert_eq!(selection.size, size);
+
+        let input_total : u64 = values(&selection.inputs).iter().sum();
+        let output_total : u64 = outputs.iter().map(|output| u64::from(output.amount)).sum();
+        assert!(input_total >= output_total + u64::from(fee));
+        input_total - output_total - u64::from(fee)
+    }
+
+    fn select_with(strategy: Strategy, available: Vec<Input>, outputs: &[Output]) -> Result<Selection> {
+        select(&mut *strategy.boxed(), &[], available, outputs, &change_address())
+    }
+
+    #[test]
+    fn not_enough_funds() {
+        for strategy in [Strategy::FirstMatch, Strategy::LargestFirst, Strategy::RandomImprove, Strategy::Manual].iter() {
+            match select_with(*strategy, vec![input(0, 600_000), input(1, 400_000)], &outputs(1_000_000)) {
+                Err(Error::NotEnoughFunds { available, needed }) => {
+                    assert_eq!(available, 1_000_000, "{}", strategy);
+                    assert!(needed > 1_000_000, "{}", strategy);
+                },
+                result => panic!("{}: unexpected result {:?}", strategy, result),
+            }
+        }
+    }
+
+    #[test]
+    fn first_match_exact() {
+        let outputs = outputs(1_000_000);
+        let (fee, _) = estimate(&[input(0, 1_200_000)], &outputs, &change_address()).unwrap();
+        let exact = 1_000_000 + u64::from(fee);
+
+        let selection = select_with(Strategy::FirstMatch, vec![input(0, exact), input(1, 5_000_000)], &outputs).unwrap();
+        assert_eq!(values(&selection.inputs), vec![exact]);
+        assert_eq!(change_of(&selection, &outputs), 0);
+    }
+
+    #[test]
+    fn first_match_in_order() {
+        let outputs = outputs(1_500_000);
+        let selection = select_with(Strategy::FirstMatch, vec![input(0, 1_000_000), input(1, 1_000_000), input(2, 5_000_000)], &outputs).unwrap();
+        assert_eq!(values(&selection.inputs), vec![1_000_000, 1_000_000]);
+        assert!(change_of(&selection, &outputs) > 0);
+    }
+
+    #[test]
+    fn largest_first_change() {
+        let outputs = outputs(3_000_000);
+        let selection = select_with(Strategy::LargestFirst, vec![input(0, 1_000_000), input(1, 5_000_000), input(2, 2_000_000)], &outputs).unwrap();
+        assert_eq!(values(&selection.inputs), vec![5_000_000]);
+        assert_eq!(change_of(&selection, &outputs), 5_000_000 - 3_000_000 - u64::from(selection.fee));
+    }
+
+    #[test]
+    fn largest_first_fee_convergence() {
+        // the first input covers the outputs but not the fee, the fee
+        // grows with the second input
+        let outputs = outputs(2_000_000);
+        let selection = select_with(Strategy::LargestFirst, vec![input(0, 2_000_000), input(1, 100_000), input(2, 150_000)], &outputs).unwrap();
+        assert_eq!(values(&selection.inputs), vec![2_000_000, 150_000, 100_000]);
+        change_of(&selection, &outputs);
+    }
+
+    #[test]
+    fn random_improve() {
+        let outputs = outputs(2_500_000);
+        let available : Vec<Input> = (0..20).map(|index| input(index, 1_000_000)).collect();
+        for _ in 0..10 {
+            let selection = select_with(Strategy::RandomImprove, available.clone(), &outputs).unwrap();
+            change_of(&selection, &outputs);
+            // more than the outputs, but not more than twice the amount needed
+            let input_total : u64 = values(&selection.inputs).iter().sum();
+            assert!(input_total <= 2 * (2_500_000 + u64::from(selection.fee)));
+        }
+    }
+
+    #[test]
+    fn manual() {
+        let outputs = outputs(1_000_000);
+        let selection = select_with(Strategy::Manual, vec![input(0, 3_000_000), input(1, 1_000_000), input(2, 2_000_000)], &outputs).unwrap();
+        assert_eq!(values(&selection.inputs), vec![3_000_000, 1_000_000, 2_000_000]);
+        assert_eq!(change_of(&selection, &outputs), 5_000_000 - u64::from(selection.fee));
+    }
+}
@@ -0,0 +1,46 @@
+//! cooperative cancellation of the long running operations
+//!
+//! The operations (sync, verify...) check the token between two steps
+//! and stop at the first consistent point once it has been cancelled.
+//! The token can be cloned and cancelled from another thread: the
+//! commands use the token of `interrupted`, cancelled by the signal
+//! handler of the process.
+//!
+
+use std::process;
+use std::sync::{Arc, OnceLock, atomic::{AtomicBool, Ordering}};
+use ctrlc;
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+impl CancelToken {
+    pub fn new() -> Self { CancelToken::default() }
+
+    /// request the operations holding this token (or a clone) to stop
+    pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst) }
+
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+}
+
+/// the token cancelled once the process is interrupted (`SIGINT`, or
+/// `SIGTERM`), shared by all the callers
+///
+/// the signal handler is installed by the first call. A second
+/// interruption exits the process without waiting for the operations to
+/// stop.
+pub fn interrupted() -> CancelToken {
+    static INTERRUPTED : OnceLock<CancelToken> = OnceLock::new();
+
+    INTERRUPTED.get_or_init(|| {
+        let token = CancelToken::new();
+        let handler_token = token.clone();
+        let installed = ctrlc::set_handler(move || {
+            if handler_token.is_cancelled() { process::exit(130); }
+            handler_token.cancel();
+        });
+        if let Err(err) = installed {
+            warn!("cannot handle the interruptions of the process: {}", err);
+        }
+        token
+    }).clone()
+}
@@ -0,0 +1,87 @@
+//! the format of the logs (`--log-format`)
+//!
+//! With `--log-format json` every log line is a JSON object with the
+//! `time`, the `level`, the `target` (the module) and the `message`. The
+//! events of the sync, the peers and the wallets (see `event`) carry
+//! their fields as keys of the object instead of a message:
+//!
+//! ```text
+//! {"time":"...","level":"INFO","event":"sync.finished","blockchain":"mainnet","peer":"iohk-hosts","blocks":"2160",...}
+//! ```
+//!
+
+use std::{fmt, str::FromStr, time::SystemTime, sync::atomic::{AtomicBool, Ordering}};
+use log::{Level, Record};
+use humantime;
+use serde_json::{self, Map, Value};
+
+/// the target of the records logged by `event`
+pub const EVENT_TARGET : &'static str = "cardano_cli::event";
+
+static JSON_FORMAT : AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _      => Err("invalid log format, expected `text' or `json'"),
+        }
+    }
+}
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// to be called once, when the logger is set up
+pub fn init(format: LogFormat) {
+    JSON_FORMAT.store(format == LogFormat::Json, Ordering::SeqCst)
+}
+
+fn is_json() -> bool { JSON_FORMAT.load(Ordering::SeqCst) }
+
+/// log the given event with its fields
+///
+/// in text, the record is `<event>: key=value, ...`; in JSON the fields
+/// are kept apart (see `format_json`).
+pub fn event(level: Level, name: &str, fields: &[(&str, &fmt::Display)]) {
+    if is_json() {
+        let mut object = Map::new();
+        object.insert("event".to_owned(), Value::String(name.to_owned()));
+        for (key, value) in fields.iter() {
+            object.insert((*key).to_owned(), Value::String(format!("{}", value)));
+        }
+        log!(target: EVENT_TARGET, level, "{}", Value::Object(object));
+    } else {
+        let fields : Vec<String> = fields.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        log!(target: EVENT_TARGET, level, "{}: {}", name, fields.join(", "));
+    }
+}
+
+/// the line of the given record, in JSON
+pub fn format_json(record: &Record) -> String {
+    let message = format!("{}", record.args());
+
+    let mut object = Map::new();
+    object.insert("time".to_owned(), Value::String(format!("{}", humantime::format_rfc3339(SystemTime::now()))));
+    object.insert("level".to_owned(), Value::String(format!("{}", record.level())));
+    match serde_json::from_str(&message) {
+        Ok(Value::Object(fields)) if record.target() == EVENT_TARGET => object.extend(fields),
+        _ => {
+            object.insert("target".to_owned(), Value::String(record.target().to_owned()));
+            object.insert("message".to_owned(), Value::String(message));
+        },
+    }
+    format!("{}", Value::Object(object))
+}
@@ -4,3 +4,5 @@ pub mod term;
 pub mod password_encrypted;
 pub mod pretty;
 pub mod prompt;
+pub mod cancel;
+pub mod logging;
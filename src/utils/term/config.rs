@@ -4,6 +4,15 @@ pub enum ColorChoice {
     Never
 }
 
+/// the format of the output of the commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// human readable, possibly colored, text
+    Text,
+    /// JSON, for scripting (see `Term::json`)
+    Json,
+}
+
 /// Configuration for the output options
 pub struct Config {
     /// when to display color or not
@@ -12,12 +21,15 @@ pub struct Config {
     ///
     /// Warning, this does not hide potential logging
     pub quiet: bool,
+    /// the format of the commands' output
+    pub output: OutputFormat,
 }
 impl Default for Config {
     fn default() -> Self {
         Config {
             color: ColorChoice::Auto,
             quiet: false,
+            output: OutputFormat::Text,
         }
     }
 }
@@ -10,9 +10,11 @@ use console;
 use indicatif;
 use dialoguer;
 
-pub use self::config::{Config, ColorChoice};
+pub use self::config::{Config, ColorChoice, OutputFormat};
 
 use std::{io::{self, Write}, error::{Error}};
+use serde::Serialize;
+use serde_json;
 
 pub const DEFAULT_TERM_WIDTH : usize = 80;
 pub const DEFAULT_TERM_HEIGHT: usize = 24;
@@ -72,6 +74,19 @@ impl Term {
         pb
     }
 
+    /// a progress bar followed by its message, for the operations
+    /// computing their own estimate of the remaining time
+    pub fn progress_bar_with_message(&self, count: u64) -> indicatif::ProgressBar {
+        let pb = indicatif::ProgressBar::new(count);
+        pb.enable_steady_tick(100);
+        pb.set_style(
+            indicatif::ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {msg}")
+                .progress_chars("#>-")
+        );
+        pb
+    }
+
     pub fn prompt(&mut self, prompt: &str) -> io::Result<String> {
         dialoguer::Input::new(prompt).interact()
     }
@@ -137,10 +152,26 @@ impl Term {
         write!(&mut self.term, "{}", self.style.error.apply_to(msg))
     }
 
+    /// the commands need to print their result with `json` rather than
+    /// the text functions (`simply`, `info`...)
+    pub fn is_json(&self) -> bool { self.config.output == OutputFormat::Json }
+
+    /// print the given value in JSON, on a single line
+    pub fn json<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let json = serde_json::to_string(value).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        writeln!(&mut self.term, "{}", json)
+    }
+
     pub fn fail_with<E>(&mut self, e: E) -> !
         where E: Error
     {
-        let mut error : &Error = &e;
+        self.report_error(&e);
+        ::std::process::exit(1)
+    }
+
+    /// print the error and its causes, see `fail_with`
+    pub fn report_error(&mut self, e: &Error) {
+        let mut error : &Error = e;
         let formated = format!("{}", e);
         writeln!(&mut self.term, "{}", self.style.error.apply_to(formated));
         while let Some(err) = error.cause() {
@@ -148,7 +179,6 @@ impl Term {
             let formated = format!("{}", err);
             writeln!(& mut self.term, "  |-> {}", self.style.warning.apply_to(formated));
         }
-        ::std::process::exit(1)
     }
 }
 impl ::std::ops::Deref for Term {
@@ -1,15 +1,16 @@
 use super::config::{encrypt_primary_key, Config, HDWalletModel};
 use super::{WalletName, Wallet, Wallets};
-use super::state::{lookup};
+use super::state::{log, lookup, state::State, ptr::StatePtr};
+use super::statement;
 use super::utils::{*};
 
 use std::{path::PathBuf, io::Write};
-use cardano::{hdwallet::{self, DerivationScheme}, wallet, bip::bip39};
+use cardano::{hdwallet::{self, DerivationScheme}, wallet, bip::bip39, util::hex};
 use rand::random;
 
-use utils::{term::{Term, style::{Style}}, prompt};
+use utils::{term::{Term, style::{Style}}, prompt, cancel::CancelToken};
 
-use blockchain::{self, Blockchain};
+use blockchain;
 
 pub fn list( mut term: Term
            , root_dir: PathBuf
@@ -49,13 +50,17 @@ pub fn new<D>( mut term: Term
              , derivation_scheme: DerivationScheme
              , mnemonic_size: bip39::Type
              , languages: Vec<D>
+             , unencrypted: bool
              )
     where D: bip39::dictionary::Language
 {
     let config = Config {
         attached_blockchain: None,
         derivation_scheme: derivation_scheme,
-        hdwallet_model: wallet_scheme
+        hdwallet_model: wallet_scheme,
+        watch_only: None,
+        gap_limit: None,
+        unencrypted: unencrypted
     };
 
     // 1. generate the mnemonics
@@ -86,9 +91,7 @@ pub fn new<D>( mut term: Term
     };
 
     // 4. encrypt the private key
-    term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
-    let password = term.new_password("spending password", "confirm spending password", "password mismatch").unwrap();
-    let encrypted_xprv = encrypt_primary_key(password.as_bytes(), &xprv);
+    let encrypted_xprv = protect_primary_key(&mut term, &xprv, unencrypted);
 
     // 5. create the wallet
     let wallet = Wallet::new(root_dir, name, config, encrypted_xprv, public_key);
@@ -99,6 +102,19 @@ pub fn new<D>( mut term: Term
     term.success(&format!("wallet `{}' successfully created.\n", &wallet.name)).unwrap();
 }
 
+/// encrypt the private key with the spending password, or keep it as is
+/// for the wallets created with `--unencrypted` (see `Config::unencrypted`)
+fn protect_primary_key(term: &mut Term, xprv: &hdwallet::XPrv, unencrypted: bool) -> Vec<u8> {
+    if unencrypted {
+        term.warn("The wallet's private key is stored unencrypted, anyone with access to this device can spend the funds of the wallet.\n").unwrap();
+        return xprv.as_ref().to_vec();
+    }
+
+    term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
+    let password = term.new_password("spending password", "confirm spending password", "password mismatch").unwrap();
+    encrypt_primary_key(password.as_bytes(), xprv)
+}
+
 pub fn recover<D>( mut term: Term
                  , root_dir: PathBuf
                  , name: WalletName
@@ -108,13 +124,17 @@ pub fn recover<D>( mut term: Term
                  , interactive: bool
                  , daedalus_seed: bool
                  , language: D
+                 , unencrypted: bool
                  )
     where D: bip39::dictionary::Language
 {
     let config = Config {
         attached_blockchain: None,
         derivation_scheme: derivation_scheme,
-        hdwallet_model: wallet_scheme
+        hdwallet_model: wallet_scheme,
+        watch_only: None,
+        gap_limit: None,
+        unencrypted: unencrypted
     };
 
     // 1. generate the mnemonics
@@ -152,9 +172,7 @@ pub fn recover<D>( mut term: Term
     };
 
     // 4. encrypt the private key
-    term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
-    let password = term.new_password("spending password", "confirm spending password", "password mismatch").unwrap();
-    let encrypted_xprv = encrypt_primary_key(password.as_bytes(), &xprv);
+    let encrypted_xprv = protect_primary_key(&mut term, &xprv, unencrypted);
 
     // 5. create the wallet
     let wallet = Wallet::new(root_dir, name, config, encrypted_xprv, public_key);
@@ -165,6 +183,70 @@ pub fn recover<D>( mut term: Term
     term.success(&format!("wallet `{}' successfully recovered.\n", &wallet.name)).unwrap();
 }
 
+/// create a watch-only wallet from the public key of one of its accounts
+///
+/// the private key stays on another device: the wallet can be synced and
+/// can derive the addresses of the account, but whatever needs to sign
+/// is refused (see `utils::ensure_can_sign`).
+pub fn attach_public( mut term: Term
+                    , root_dir: PathBuf
+                    , name: WalletName
+                    , derivation_scheme: DerivationScheme
+                    , account: u32
+                    , public_key: &str
+                    )
+{
+    if let Err(err) = ::cardano::bip::bip44::Account::new(account) {
+        term.fail_with(err);
+    }
+    let xpub = match hex::decode(public_key) {
+        Err(err) => {
+            term.error(&format!("Invalid account public key, expecting hexadecimal: {}\n", err)).unwrap();
+            ::std::process::exit(1);
+        },
+        Ok(bytes) => hdwallet::XPub::from_slice(&bytes).unwrap_or_else(|e| term.fail_with(e)),
+    };
+
+    let config = Config {
+        attached_blockchain: None,
+        derivation_scheme: derivation_scheme,
+        hdwallet_model: HDWalletModel::BIP44,
+        watch_only: Some(account),
+        gap_limit: None,
+        unencrypted: false
+    };
+
+    let wallet = Wallet::new(root_dir, name, config, Vec::new(), Some(xpub));
+    wallet.save();
+
+    term.success(&format!("watch-only wallet `{}' successfully created.\n", &wallet.name)).unwrap();
+}
+
+/// encrypt the private key of the wallet with a new spending password, or
+/// store it unencrypted
+pub fn change_passphrase( mut term: Term
+                        , root_dir: PathBuf
+                        , name: WalletName
+                        , unencrypted: bool
+                        )
+{
+    let mut wallet = Wallet::load(root_dir, name);
+    ensure_can_sign(&mut term, &wallet);
+
+    let password = ask_wallet_password(&mut term, &wallet);
+    let xprv = wallet.primary_key(password.as_bytes()).unwrap_or_else(|e| term.fail_with(e));
+
+    wallet.encrypted_key = protect_primary_key(&mut term, &xprv, unencrypted);
+    wallet.config.unencrypted = unencrypted;
+    wallet.save();
+
+    if unencrypted {
+        term.success(&format!("wallet `{}' is no longer encrypted.\n", &wallet.name)).unwrap();
+    } else {
+        term.success(&format!("wallet `{}' encrypted with the new password.\n", &wallet.name)).unwrap();
+    }
+}
+
 pub fn destroy( mut term: Term
               , root_dir: PathBuf
               , name: WalletName
@@ -215,7 +297,7 @@ pub fn attach( mut term: Term
         term.error(&format!("   |-> {}\n", err)).unwrap();
         ::std::process::exit(2);
     }
-    let _ = Blockchain::load(root_dir, blockchain_name.clone());
+    let _ = blockchain::commands::load_read_only(&mut term, root_dir, blockchain_name.clone());
 
     // 3. save the attached wallet
     wallet.config.attached_blockchain = Some(blockchain_name);
@@ -273,6 +355,11 @@ pub fn status( mut term: Term
     term.simply(" * derivation scheme ").unwrap();
     term.warn(&format!("{:?}", &wallet.config.derivation_scheme)).unwrap();
     term.simply("\n").unwrap();
+    if let Some(account) = wallet.config.watch_only {
+        term.simply(" * watch-only, account ").unwrap();
+        term.warn(&format!("{}", account)).unwrap();
+        term.simply("\n").unwrap();
+    }
 
     let state = create_wallet_state_from_logs(&mut term, &wallet, root_dir, lookup::accum::Accum::default());
 
@@ -289,7 +376,6 @@ pub fn status( mut term: Term
 pub fn log( mut term: Term
           , root_dir: PathBuf
           , name: WalletName
-          , pretty: bool
           )
 {
     // load the wallet
@@ -297,7 +383,60 @@ pub fn log( mut term: Term
 
     let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir, lookup::accum::Accum::default());
 
-    display_wallet_state_logs(&mut term, &wallet, &mut state, pretty);
+    display_wallet_state_logs(&mut term, &wallet, &mut state);
+}
+
+/// print the transactions of the wallet accepted by the filter, see
+/// `statement`
+///
+/// the statement is made of the transactions recorded in the wallet log
+/// by `sync`, the logs written before the transactions were recorded are
+/// rebuilt by attaching the wallet again.
+pub fn statement( mut term: Term
+                , root_dir: PathBuf
+                , name: WalletName
+                , format: statement::Format
+                , filter: statement::Filter
+                )
+{
+    let wallet = Wallet::load(root_dir, name);
+
+    let logs : Vec<log::Log<lookup::Address>> = {
+        let log_lock = lock_wallet_log(&wallet);
+        let reader = log::LogReader::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+        reader.into_iter().map(|r| r.unwrap_or_else(|e| term.fail_with(e))).collect()
+    };
+    let has_funds = logs.iter().any(|log| match log { log::Log::ReceivedFund(..) => true, _ => false });
+    let has_transactions = logs.iter().any(|log| match log { log::Log::Transaction(..) => true, _ => false });
+    if has_funds && ! has_transactions {
+        warn!("the log of wallet `{}' has no transaction recorded, detach and attach the wallet again then sync it to rebuild its log", wallet.name);
+    }
+
+    let rows = statement::rows(logs, &filter).unwrap_or_else(|e| term.fail_with(e));
+
+    if term.is_json() {
+        term.json(&rows).unwrap();
+        return;
+    }
+
+    match format {
+        statement::Format::Csv => statement::write_csv(&mut term, &rows).unwrap(),
+        statement::Format::Text => {
+            for row in rows {
+                let date = row.date.clone().unwrap_or_else(|| "unknown date".to_owned());
+                let amount = if row.direction == "in" {
+                    style!(format!("+{}", row.amount)).green()
+                } else {
+                    style!(format!("-{}", row.amount)).red()
+                };
+                write!(term, "{} {} {}", style!(date), style!(row.transaction_id), amount).unwrap();
+                if let Some(fee) = row.fee {
+                    write!(term, " (fee {})", style!(fee).red()).unwrap();
+                }
+                writeln!(term, " balance {}", style!(row.balance).yellow()).unwrap();
+            }
+        },
+    }
 }
 
 pub fn utxos( mut term: Term
@@ -316,6 +455,7 @@ pub fn utxos( mut term: Term
 pub fn sync( mut term: Term
            , root_dir: PathBuf
            , name: WalletName
+           , cancel: CancelToken
            )
 
 {
@@ -331,17 +471,73 @@ pub fn sync( mut term: Term
             lookup_struct.prepare_next_account().unwrap_or_else(|e| term.fail_with(e));
             let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), lookup_struct);
 
-            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state, &cancel);
         },
         HDWalletModel::RandomIndex2Levels => {
             let lookup_struct = load_randomindex_lookup_structure(&mut term, &wallet);
             let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), lookup_struct);
 
-            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state, &cancel);
         },
     };
 }
 
+/// find all the addresses of a BIP44 wallet used in the attached
+/// blockchain, then rebuild the wallet's log from the genesis
+///
+/// `sync` only recognises the addresses within the gap limit of the
+/// addresses already found, in the order of the blockchain: an address
+/// used before a lower address is missed. The blockchain is scanned again
+/// until no new address is found, then the accounts and addresses found
+/// are replayed from the genesis (a restored wallet gets its full history).
+/// Once `cancel` is cancelled, the discovery stops.
+pub fn discover( mut term: Term
+               , root_dir: PathBuf
+               , name: WalletName
+               , gap_limit: Option<u32>
+               , cancel: CancelToken
+               )
+{
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+
+    if wallet.config.hdwallet_model != HDWalletModel::BIP44 {
+        term.info("The addresses of the random index wallets are recognised without being generated, `wallet sync' finds all of them.\n").unwrap();
+        return;
+    }
+    if gap_limit.is_some() {
+        wallet.config.gap_limit = gap_limit;
+        wallet.save();
+    }
+
+    let blockchain = load_attached_blockchain(&mut term, root_dir.clone(), wallet.config.attached_blockchain.clone());
+
+    let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
+    lookup_struct.prepare_next_account().unwrap_or_else(|e| term.fail_with(e));
+    term.info(&format!("discovering the addresses of `{}' (gap limit: {})\n", wallet.name, lookup_struct.gap_limit())).unwrap();
+
+    let mut pass = 1;
+    loop {
+        let known = lookup_struct.number_of_expected_addresses();
+        if ! scan_blockchain_addresses(&mut term, &blockchain, &mut lookup_struct, &cancel) {
+            term.warn("address discovery cancelled, the wallet's log is unchanged\n").unwrap();
+            return;
+        }
+        let found = lookup_struct.number_of_expected_addresses();
+        term.info(&format!("pass {}: {} addresses in {} account(s)\n", pass, found, lookup_struct.number_of_accounts())).unwrap();
+        if found == known { break; }
+        pass += 1;
+    }
+
+    // the log is rebuilt from the genesis, with all the addresses found
+    wallet.delete_log().unwrap_or_else(|e| term.fail_with(e));
+    let initial_ptr = StatePtr::new_before_genesis(blockchain.config.genesis.clone());
+    let mut state = State::new(initial_ptr, lookup_struct);
+    update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state, &cancel);
+
+    let total = state.total().unwrap_or_else(|e| term.fail_with(e));
+    term.success(&format!("wallet `{}' synced, {} UTxO(s) for a total of {}\n", wallet.name, state.utxos.len(), total)).unwrap();
+}
+
 pub fn address( mut term: Term
               , root_dir: PathBuf
               , name: WalletName
@@ -360,6 +556,10 @@ pub fn address( mut term: Term
                 Err(err) => term.fail_with(err),
                 Ok(account) => account
             };
+            if ! lookup_struct.has_account(&account) {
+                term.error(&format!("Watch-only wallet `{}' only has the addresses of its account {}\n", wallet.name, wallet.config.watch_only.unwrap_or(0))).unwrap();
+                ::std::process::exit(1);
+            }
             let change = if is_internal {
                 account.internal().unwrap_or_else(|e| term.fail_with(e))
             } else {
@@ -52,14 +52,40 @@ pub struct Config {
 
     /// This is needed so we know what kind of wallet HD we are dealing with
     ///
-    pub hdwallet_model: HDWalletModel
+    pub hdwallet_model: HDWalletModel,
+
+    /// set for the watch-only wallets: the account of the public key the
+    /// wallet has been created from (see `wallet attach-public`).
+    ///
+    /// The private key is not on this device, the addresses are derived
+    /// from the account public key and the wallet cannot sign transactions.
+    #[serde(default)]
+    pub watch_only: Option<u32>,
+
+    /// the number of unused addresses looked ahead of the last address
+    /// found in the blockchain, for the BIP44 wallets (see `wallet discover`)
+    ///
+    /// `None` for the default gap limit (20).
+    #[serde(default)]
+    pub gap_limit: Option<u32>,
+
+    /// set for the wallets created with `--unencrypted`: the private key
+    /// is stored as is in `wallet.key`, no spending password is asked.
+    ///
+    /// Only the private key is ever encrypted: the log and the snapshot of
+    /// the wallet state (the addresses and the UTxOs) are in plaintext.
+    #[serde(default)]
+    pub unencrypted: bool
 }
 impl Default for Config {
     fn default() -> Self {
         Config {
             attached_blockchain: None,
             derivation_scheme: DerivationScheme::V2,
-            hdwallet_model: HDWalletModel::BIP44
+            hdwallet_model: HDWalletModel::BIP44,
+            watch_only: None,
+            gap_limit: None,
+            unencrypted: false
         }
     }
 }
@@ -85,6 +111,16 @@ pub fn decrypt_primary_key(password: &Password, encrypted_key: &[u8]) -> Result<
         Some(bytes) => bytes
     };
 
+    read_primary_key(&xprv_vec)
+}
+
+/// the HDWallet XPrv of a wallet stored unencrypted (`--unencrypted`)
+///
+/// # Errors
+///
+/// This function may fail if the value did not represent a HDWallet XPrv
+///
+pub fn read_primary_key(xprv_vec: &[u8]) -> Result<hdwallet::XPrv> {
     if xprv_vec.len() != hdwallet::XPRV_SIZE {
         return Err(
             Error::CannotRetrievePrivateKey(
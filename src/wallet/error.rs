@@ -2,13 +2,15 @@ use cardano::hdwallet;
 use storage_units::utils::lock;
 use std::{error, fmt};
 
-use super::state::log;
+use super::state::{log, lookup};
 
 /// wallet errors
 #[derive(Debug)]
 pub enum Error {
     CannotRetrievePrivateKeyInvalidPassword,
     CannotRetrievePrivateKey(hdwallet::Error),
+    WatchOnlyWallet,
+    CannotSignUnsupportedAddress(lookup::Address),
     WalletLogAlreadyLocked(u32),
     WalletLogNotFound,
     WalletLogError(log::Error)
@@ -30,6 +32,8 @@ impl fmt::Display for Error {
         match self {
             Error::CannotRetrievePrivateKeyInvalidPassword => write!(f, "Invalid spending password"),
             Error::CannotRetrievePrivateKey(_)             => write!(f, "Unsupported private key serialisation"),
+            Error::WatchOnlyWallet                         => write!(f, "Watch-only wallet, the private key is not on this device"),
+            Error::CannotSignUnsupportedAddress(addr)      => write!(f, "Cannot sign for address {}, unexpected for the wallet scheme", addr),
             Error::WalletLogAlreadyLocked(pid)             => write!(f, "Wallet is already being used by another process (process id: {})", pid),
             Error::WalletLogNotFound                       => write!(f, "No wallet log Found"),
             Error::WalletLogError(_)                       => write!(f, "Error with the wallet log")
@@ -42,6 +46,8 @@ impl error::Error for Error {
             Error::CannotRetrievePrivateKey(ref err)       => Some(err),
             Error::WalletLogError(ref err)                 => Some(err),
             Error::CannotRetrievePrivateKeyInvalidPassword => None,
+            Error::WatchOnlyWallet                         => None,
+            Error::CannotSignUnsupportedAddress(_)         => None,
             Error::WalletLogAlreadyLocked(_)               => None,
             Error::WalletLogNotFound                       => None,
         }
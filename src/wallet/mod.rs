@@ -4,17 +4,19 @@ mod error;
 mod result;
 pub mod state;
 pub mod utils;
+pub mod signer;
+pub mod statement;
 
 pub use self::error::{Error};
 pub use self::result::{Result};
 pub use self::config::{HDWalletModel, Config};
 
-use self::config::{decrypt_primary_key};
+use self::config::{decrypt_primary_key, read_primary_key};
 
 use self::state::log::{LogLock, LogWriter};
 
 use std::{fmt, path::PathBuf, fs, io::{Read, Write}, collections::{BTreeMap}};
-use cardano::{wallet, hdwallet::{XPub, XPUB_SIZE}};
+use cardano::{wallet, hdwallet::{XPrv, XPub, XPUB_SIZE}};
 use storage_units::utils::{tmpfile::{TmpFile}};
 use serde_yaml;
 
@@ -70,6 +72,9 @@ pub struct Wallet {
     ///
     /// Then we will need to use the selected `HDWalletModel` to retrieve
     /// what kind of wallet we are dealing with.
+    ///
+    /// The wallets created with `--unencrypted` store the private key as
+    /// is, see `Config::unencrypted`.
     pub encrypted_key: Vec<u8>,
 
     /// in some cases, we might want to store the public key in the wallet
@@ -117,12 +122,14 @@ impl Wallet {
         tmpfile.render_permanent(&dir.join(WALLET_CONFIG_FILE))
             .unwrap();
 
-        // 2. save the encrypted key
-        let mut tmpfile = TmpFile::create(dir.clone())
-            .unwrap();
-        tmpfile.write(&self.encrypted_key).unwrap();
-        tmpfile.render_permanent(&dir.join(WALLET_PRIMARY_KEY))
-            .unwrap();
+        // 2. save the encrypted key (there is none for watch-only wallets)
+        if ! self.is_watch_only() {
+            let mut tmpfile = TmpFile::create(dir.clone())
+                .unwrap();
+            tmpfile.write(&self.encrypted_key).unwrap();
+            tmpfile.render_permanent(&dir.join(WALLET_PRIMARY_KEY))
+                .unwrap();
+        }
 
         // 3. save the public key
         if let Some(ref xpub) = self.public_key {
@@ -139,12 +146,14 @@ impl Wallet {
 
         let mut file = fs::File::open(&dir.join(WALLET_CONFIG_FILE))
             .unwrap();
-        let cfg = serde_yaml::from_reader(&mut file).unwrap();
+        let cfg : Config = serde_yaml::from_reader(&mut file).unwrap();
 
-        let mut file = fs::File::open(&dir.join(WALLET_PRIMARY_KEY))
-            .unwrap();
         let mut key = Vec::with_capacity(150);
-        file.read_to_end(&mut key).unwrap();
+        if cfg.watch_only.is_none() {
+            let mut file = fs::File::open(&dir.join(WALLET_PRIMARY_KEY))
+                .unwrap();
+            file.read_to_end(&mut key).unwrap();
+        }
 
         let xpub = match fs::File::open(&dir.join(WALLET_PUBLIC_KEY)) {
             Err(_err) => None, // TODO, check for file does not exists
@@ -158,19 +167,54 @@ impl Wallet {
         Self::new(root_dir, name, cfg, key, xpub)
     }
 
+    /// the wallet has been created from an account public key, it can
+    /// derive addresses but cannot sign (see `Config::watch_only`)
+    pub fn is_watch_only(&self) -> bool {
+        self.config.watch_only.is_some()
+    }
+
+    /// the private key is encrypted with the spending password (the
+    /// default), otherwise no password is asked to use it (see
+    /// `Config::unencrypted`)
+    pub fn is_encrypted(&self) -> bool {
+        ! self.config.unencrypted
+    }
+
+    /// the root private key of the wallet, the password is ignored if the
+    /// wallet is not encrypted (see `is_encrypted`)
+    pub fn primary_key(&self, password: &Password) -> Result<XPrv> {
+        if self.is_watch_only() { return Err(Error::WatchOnlyWallet); }
+        if self.is_encrypted() {
+            decrypt_primary_key(password, &self.encrypted_key)
+        } else {
+            read_primary_key(&self.encrypted_key)
+        }
+    }
+
+    /// the directory of the wallet's files (configuration, log...)
+    pub fn directory(&self) -> PathBuf {
+        config::directory(self.root_dir.clone(), &self.name.as_dirname())
+    }
+
     /// lock the LOG file of the wallet for Read and/or Write operations
     pub fn log(&self) -> Result<LogLock> {
-        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let dir = self.directory();
         let lock = LogLock::acquire_wallet_log_lock(dir)?;
 
         let writer = LogWriter::open(lock)?;
         Ok(writer.release_lock())
     }
 
+    /// delete the LOG file of the wallet, and the snapshot of the state
+    /// built from it
     pub fn delete_log(&self) -> ::std::io::Result<()> {
-        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let dir = self.directory();
         let lock = LogLock::acquire_wallet_log_lock(dir.clone()).unwrap();
-        lock.delete_wallet_log_lock(dir)
+        state::snapshot::Snapshot::delete(&dir)?;
+        match lock.delete_wallet_log_lock(dir) {
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
     }
 
     /// convenient function to reconstruct a BIP44 wallet from the encrypted key and password
@@ -181,9 +225,10 @@ impl Wallet {
     ///
     /// * the password in invalid;
     /// * the encrypted value did not represent a HDWallet XPrv
+    /// * the wallet is watch-only
     ///
     pub fn get_wallet_bip44(&self, password: &Password) -> Result<wallet::bip44::Wallet> {
-        let xprv = decrypt_primary_key(password, &self.encrypted_key)?;
+        let xprv = self.primary_key(password)?;
         Ok(wallet::bip44::Wallet::from_root_key(
             xprv,
             self.config.derivation_scheme
@@ -198,9 +243,10 @@ impl Wallet {
     ///
     /// * the password in invalid;
     /// * the encrypted value did not represent a HDWallet XPrv
+    /// * the wallet is watch-only
     ///
     pub fn get_wallet_rindex(&self, password: &Password) -> Result<wallet::rindex::Wallet> {
-        let xprv = decrypt_primary_key(password, &self.encrypted_key)?;
+        let xprv = self.primary_key(password)?;
         let root_key = wallet::rindex::RootKey::new(xprv, self.config.derivation_scheme);
         Ok(wallet::rindex::Wallet::from_root_key(
             self.config.derivation_scheme,
@@ -0,0 +1,78 @@
+//! the backends signing the inputs of the transactions
+//!
+//! The wallet's private key is decrypted with the spending password
+//! (`SoftwareSigner`), once per wallet.
+//!
+
+use cardano::{config::ProtocolMagic, tx::{TxId, TxInWitness}};
+
+use utils::term::Term;
+
+use super::{Wallet, Error, Result, HDWalletModel};
+use super::state::lookup::{self, sequentialindex::SequentialBip44Lookup, randomindex::RandomIndexLookup};
+use super::utils::{ensure_can_sign, load_bip44_lookup_structure, load_randomindex_lookup_structure};
+
+pub trait Signer {
+    /// the witness of the input spending from the given address of the
+    /// wallet
+    fn sign( &mut self
+           , term: &mut Term
+           , protocol_magic: ProtocolMagic
+           , txid: &TxId
+           , address: &lookup::Address
+           ) -> Result<TxInWitness>;
+}
+
+/// the signer of the given wallet
+pub fn signer<'a>(wallet: &'a Wallet) -> Box<Signer + 'a> {
+    Box::new(SoftwareSigner::new(wallet))
+}
+
+enum Keys {
+    Bip44(SequentialBip44Lookup),
+    RIndex(RandomIndexLookup),
+}
+
+/// sign with the wallet's private key, the password is asked once, on
+/// the first input to sign
+pub struct SoftwareSigner<'a> {
+    wallet: &'a Wallet,
+    keys: Option<Keys>,
+}
+impl<'a> SoftwareSigner<'a> {
+    pub fn new(wallet: &'a Wallet) -> Self {
+        SoftwareSigner { wallet: wallet, keys: None }
+    }
+
+    fn keys(&mut self, term: &mut Term) -> &Keys {
+        if self.keys.is_none() {
+            ensure_can_sign(term, self.wallet);
+            self.keys = Some(match self.wallet.config.hdwallet_model {
+                HDWalletModel::BIP44 => Keys::Bip44(load_bip44_lookup_structure(term, self.wallet)),
+                HDWalletModel::RandomIndex2Levels => Keys::RIndex(load_randomindex_lookup_structure(term, self.wallet)),
+            });
+        }
+        self.keys.as_ref().unwrap()
+    }
+}
+impl<'a> Signer for SoftwareSigner<'a> {
+    fn sign( &mut self
+           , term: &mut Term
+           , protocol_magic: ProtocolMagic
+           , txid: &TxId
+           , address: &lookup::Address
+           ) -> Result<TxInWitness>
+    {
+        match (self.keys(term), address) {
+            (Keys::Bip44(wallet), lookup::Address::Bip44(addressing)) => {
+                let xprv = wallet.get_private_key(addressing).ok_or(Error::WatchOnlyWallet)?;
+                Ok(TxInWitness::new(protocol_magic, &*xprv, txid))
+            },
+            (Keys::RIndex(wallet), lookup::Address::RIndex(addressing)) => {
+                let xprv = wallet.get_private_key(addressing);
+                Ok(TxInWitness::new(protocol_magic, &xprv, txid))
+            },
+            (_, address) => Err(Error::CannotSignUnsupportedAddress(address.clone())),
+        }
+    }
+}
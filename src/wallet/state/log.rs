@@ -2,7 +2,7 @@ use storage_units::{append, utils::{serialize, lock::{self, Lock}}};
 use std::{path::{PathBuf}, fmt, result, io::{self, Read, Write}, error};
 use cardano::{block::{BlockDate, HeaderHash, types::EpochSlotId}};
 
-use super::{ptr::{StatePtr}, utxo::{UTxO}};
+use super::{ptr::{StatePtr}, utxo::{UTxO}, txsummary::{TxSummary}};
 
 use serde;
 use serde_yaml;
@@ -65,7 +65,9 @@ const MAGIC : &'static [u8] = b"EVT1";
 pub enum Log<A> {
     Checkpoint(StatePtr),
     ReceivedFund(StatePtr, UTxO<A>),
-    SpentFund(StatePtr, UTxO<A>)
+    SpentFund(StatePtr, UTxO<A>),
+    /// the summary of a transaction, after its `ReceivedFund` and `SpentFund`
+    Transaction(StatePtr, TxSummary)
 }
 impl<A: serde::Serialize> Log<A> {
     fn serialise(&self) -> Result<Vec<u8>> {
@@ -106,6 +108,13 @@ impl<A: serde::Serialize> Log<A> {
                     Error::LogFormatError(format!("log format error: {:?}", e))
                 })?;
             },
+            Log::Transaction(_, summary) => {
+                serialize::utils::write_u32(&mut writer, 4)?;
+                serialize::utils::write_u64(&mut writer, 0)?;
+                serde_yaml::to_writer(&mut writer, summary).map_err(|e| {
+                    Error::LogFormatError(format!("log format error: {:?}", e))
+                })?;
+            },
         }
 
         Ok(writer)
@@ -162,6 +171,12 @@ impl<A> Log<A>
                 )?;
                 Ok(Log::SpentFund(ptr, utxo))
             },
+            4 => {
+                let summary = serde_yaml::from_slice(reader).map_err(|e|
+                    Error::LogFormatError(format!("log format error: {:?}", e))
+                )?;
+                Ok(Log::Transaction(ptr, summary))
+            },
             _ => {
                 panic!("cannot parse log event of type: `{}'", t)
             }
@@ -175,6 +190,7 @@ impl<A> Log<A>
             Log::Checkpoint(ptr) => ptr,
             Log::ReceivedFund(ptr, _) => ptr,
             Log::SpentFund(ptr, _) => ptr,
+            Log::Transaction(ptr, _) => ptr,
         }
     }
     pub fn map<F, U>(self, f: F) -> Log<U>
@@ -184,6 +200,7 @@ impl<A> Log<A>
             Log::Checkpoint(ptr)    => Log::Checkpoint(ptr),
             Log::ReceivedFund(ptr, utxo) => Log::ReceivedFund(ptr, utxo.map(f)),
             Log::SpentFund(ptr, utxo)    => Log::SpentFund(ptr, utxo.map(f)),
+            Log::Transaction(ptr, summary) => Log::Transaction(ptr, summary),
         }
     }
 }
@@ -193,6 +210,7 @@ impl<A: fmt::Display> fmt::Display for Log<A> {
             Log::Checkpoint(ptr)         => write!(f, "Checkpoint at: {}", ptr),
             Log::ReceivedFund(ptr, utxo) => write!(f, "Received funds at: {} {}", ptr, utxo),
             Log::SpentFund(ptr, utxo)    => write!(f, "Spent funds at: {} {}", ptr, utxo),
+            Log::Transaction(ptr, summary) => write!(f, "Transaction at: {} {}", ptr, summary),
         }
     }
 }
@@ -223,6 +241,19 @@ impl LogReader {
 
     pub fn release_lock(self) -> LogLock { LogLock(self.0.close()) }
 
+    /// skip the next `count` logs without decoding them, returns the
+    /// number of logs skipped (less than `count` at the end of the log)
+    pub fn skip(&mut self, count: u64) -> Result<u64> {
+        let mut skipped = 0;
+        while skipped < count {
+            match self.0.next()? {
+                None => break,
+                Some(_) => skipped += 1,
+            }
+        }
+        Ok(skipped)
+    }
+
     pub fn into_iter<A>(self) -> LogIterator<A>
         where for<'de> A: serde::Deserialize<'de>
     {
@@ -1,6 +1,6 @@
 use cardano::wallet::{bip44};
 use std::collections::BTreeMap;
-use cardano::{address::ExtendedAddr, hdwallet::XPrv};
+use cardano::{address::ExtendedAddr, hdwallet::{XPrv, XPub, DerivationScheme}};
 
 use super::{AddressLookup, Address};
 use super::super::{utxo::{UTxO}};
@@ -9,6 +9,15 @@ pub const DEFAULT_GAP_LIMIT: u32 = 20;
 
 type Result<T> = bip44::bip44::Result<T>;
 
+/// the keys the addresses are derived from
+enum Keys {
+    /// the root private key, all the accounts are available
+    Private(bip44::Wallet),
+    /// the public key of a single account (watch-only wallets), the
+    /// change and index levels are soft derivations.
+    Public(bip44::bip44::Account, XPub, DerivationScheme),
+}
+
 pub struct SequentialBip44Lookup {
     // cryptographic wallet
     //
@@ -17,41 +26,98 @@ pub struct SequentialBip44Lookup {
     // to create new addresses and they need hard derivation (which cannot be
     // done through the public key).
     //
-    wallet: bip44::Wallet,
+    // The watch-only wallets only have the public key of one account, they
+    // are limited to the addresses of this account.
+    //
+    keys: Keys,
     // all the known expected addresses, that includes
     // all different accounts, and also the next not yet live
     // account's addresses
     expected: BTreeMap<ExtendedAddr, bip44::Addressing>,
 
-    // accounts threshold index for internal and external addresses
+    // accounts threshold index for internal and external addresses: the
+    // index of the first address not generated yet
     accounts: Vec<[bip44::Index;2]>,
 
-    // gap limit
+    // gap limit: the number of addresses generated after the last address
+    // found in the blockchain
     gap_limit: u32,
 }
 
 impl SequentialBip44Lookup {
     pub fn new(wallet: bip44::Wallet) -> Self {
+        Self::with_keys(Keys::Private(wallet))
+    }
+
+    /// lookup of the addresses of the given account only, from its public key
+    pub fn watch_only(account: bip44::bip44::Account, xpub: XPub, derivation_scheme: DerivationScheme) -> Self {
+        Self::with_keys(Keys::Public(account, xpub, derivation_scheme))
+    }
+
+    fn with_keys(keys: Keys) -> Self {
         SequentialBip44Lookup {
-            wallet: wallet,
+            keys: keys,
             expected: BTreeMap::new(),
             accounts: Vec::new(),
             gap_limit: DEFAULT_GAP_LIMIT,
         }
     }
 
-    pub fn get_private_key(&self, addr: &bip44::Addressing) -> bip44::IndexLevel<XPrv> {
-        self.wallet.account(self.wallet.derivation_scheme(), addr.account.get_scheme_value())
-                   .change(self.wallet.derivation_scheme(), addr.address_type())
-                   .index(self.wallet.derivation_scheme(), addr.index.get_scheme_value())
+    /// use the given gap limit instead of `DEFAULT_GAP_LIMIT`, to be set
+    /// before `prepare_next_account`
+    pub fn with_gap_limit(mut self, gap_limit: u32) -> Self {
+        self.gap_limit = gap_limit;
+        self
     }
 
-    pub fn get_address(&self, addr: &bip44::Addressing) -> ExtendedAddr {
-        let xprv = self.get_private_key(addr);
-        let xpub = xprv.public();
-        ExtendedAddr::new_simple(*xpub)
+    pub fn gap_limit(&self) -> u32 { self.gap_limit }
+
+    /// the number of addresses generated so far, they grow as the used
+    /// addresses are found (see `threshold_generate`)
+    pub fn number_of_expected_addresses(&self) -> usize { self.expected.len() }
+
+    /// the number of accounts prepared so far (see `prepare_next_account`)
+    pub fn number_of_accounts(&self) -> usize { self.accounts.len() }
+
+    /// the addresses of the given account can be derived
+    pub fn has_account(&self, account: &bip44::bip44::Account) -> bool {
+        match self.keys {
+            Keys::Private(_) => true,
+            Keys::Public(ref watched, _, _) => watched == account,
+        }
     }
 
+    /// `None` for watch-only wallets
+    pub fn get_private_key(&self, addr: &bip44::Addressing) -> Option<bip44::IndexLevel<XPrv>> {
+        match self.keys {
+            Keys::Private(ref wallet) => Some(
+                wallet.account(wallet.derivation_scheme(), addr.account.get_scheme_value())
+                      .change(wallet.derivation_scheme(), addr.address_type())
+                      .index(wallet.derivation_scheme(), addr.index.get_scheme_value())
+            ),
+            Keys::Public(..) => None,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// if the account of the address is not available (see `has_account`)
+    pub fn get_address(&self, addr: &bip44::Addressing) -> ExtendedAddr {
+        match self.keys {
+            Keys::Private(_) => {
+                let xprv = self.get_private_key(addr).unwrap();
+                let xpub = xprv.public();
+                ExtendedAddr::new_simple(*xpub)
+            },
+            Keys::Public(ref account, ref xpub, derivation_scheme) => {
+                assert!(account == &addr.account, "address of an account not watched");
+                let xpub = xpub.derive(derivation_scheme, addr.change)
+                               .and_then(|xpub| xpub.derive(derivation_scheme, addr.index.get_scheme_value()))
+                               .expect("soft derivation of the account public key");
+                ExtendedAddr::new_simple(xpub)
+            },
+        }
+    }
 
     fn mut_generate_from(&mut self, account: &bip44::bip44::Account, change: u32, start: &bip44::Index, nb: u32) -> Result<()> {
         let max = start.incr(nb)?;
@@ -72,32 +138,55 @@ impl SequentialBip44Lookup {
         let account = bip44::bip44::Account::new(account_nb)?;
         let start = bip44::Index::new(0)?;
         let n = self.gap_limit;
-        self.mut_generate_from(&account, 0, &start, n)?;
-        self.mut_generate_from(&account, 1, &start, n)?;
-        self.accounts.push([start, start]);
+        if self.has_account(&account) {
+            self.mut_generate_from(&account, 0, &start, n)?;
+            self.mut_generate_from(&account, 1, &start, n)?;
+            let threshold = start.incr(n)?;
+            self.accounts.push([threshold, threshold]);
+        } else {
+            self.accounts.push([start, start]);
+        }
+
+        // the only account of a watch-only wallet may not be the first one
+        if let Keys::Public(watched, _, _) = self.keys {
+            if watched.get_account_number() > account_nb {
+                return self.prepare_next_account();
+            }
+        }
         Ok(())
     }
 
     // every time we find our address, we check if
     // the threshold for the next windows of address is met,
     // and if so, populate the expected cache with the new addresses and update the new threshold
+    //
+    // as the BIP44 account discovery, the next account is prepared once an
+    // address of the last account is found.
     pub fn threshold_generate(&mut self, addressing: bip44::Addressing) -> Result<()> {
-        if addressing.account.get_account_number() as usize >= self.accounts.len() {
+        if ! self.has_account(&addressing.account) {
             return Ok(());
         }
-        let mut limits = self.accounts[addressing.account.get_account_number() as usize];
         if addressing.change != 0 && addressing.change != 1 {
             return Ok(());
         }
+        let account_nb = addressing.account.get_account_number() as usize;
+        if let Keys::Private(_) = self.keys {
+            while self.accounts.len() <= account_nb + 1 {
+                self.prepare_next_account()?;
+            }
+        } else if account_nb >= self.accounts.len() {
+            return Ok(());
+        }
+
         let lidx = addressing.change as usize;
-        let current_threshold = limits[lidx];
-        if addressing.index <= current_threshold {
+        let current_threshold = self.accounts[account_nb][lidx];
+        let new_threshold = addressing.index.incr(self.gap_limit + 1)?;
+        if new_threshold <= current_threshold {
             return Ok(());
         }
-        let new_threshold = current_threshold.incr(self.gap_limit)?;
-        let gap = self.gap_limit;
-        self.mut_generate_from(&addressing.account, addressing.change, &new_threshold, gap)?;
-        limits[lidx] = new_threshold;
+        let gap = new_threshold.get_scheme_value() - current_threshold.get_scheme_value();
+        self.mut_generate_from(&addressing.account, addressing.change, &current_threshold, gap)?;
+        self.accounts[account_nb][lidx] = new_threshold;
         Ok(())
     }
 }
@@ -124,3 +213,49 @@ impl AddressLookup for SequentialBip44Lookup {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::hdwallet::{XPRV_SIZE};
+
+    fn wallet() -> bip44::Wallet {
+        bip44::Wallet::from_root_key(XPrv::normalize_bytes([5; XPRV_SIZE]), DerivationScheme::V2)
+    }
+
+    /// the public key of the account, as given to `wallet attach-public`
+    fn account_public_key(account: &bip44::bip44::Account) -> XPub {
+        let wallet = wallet();
+        *wallet.account(wallet.derivation_scheme(), account.get_scheme_value()).public()
+    }
+
+    #[test]
+    fn watch_only_addresses_match_private_ones() {
+        let private = SequentialBip44Lookup::new(wallet());
+        for account_nb in [0, 3].iter() {
+            let account = bip44::bip44::Account::new(*account_nb).unwrap();
+            let watch_only = SequentialBip44Lookup::watch_only(account, account_public_key(&account), DerivationScheme::V2);
+
+            for change in 0..2 {
+                for index in [0, 1, DEFAULT_GAP_LIMIT, 1000].iter() {
+                    let addressing = bip44::Addressing { account: account, change: change, index: bip44::Index::new(*index).unwrap() };
+                    assert_eq!(watch_only.get_address(&addressing), private.get_address(&addressing),
+                               "account {}, change {}, index {}", account_nb, change, index);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn watch_only_expects_the_private_addresses() {
+        let mut private = SequentialBip44Lookup::new(wallet());
+        private.prepare_next_account().unwrap();
+
+        let account = bip44::bip44::Account::new(0).unwrap();
+        let mut watch_only = SequentialBip44Lookup::watch_only(account, account_public_key(&account), DerivationScheme::V2);
+        watch_only.prepare_next_account().unwrap();
+
+        assert_eq!(watch_only.number_of_expected_addresses(), 2 * DEFAULT_GAP_LIMIT as usize);
+        assert_eq!(watch_only.expected, private.expected);
+    }
+}
@@ -6,4 +6,6 @@ pub mod lookup;
 pub mod state;
 pub mod ptr;
 pub mod utxo;
+pub mod txsummary;
 pub mod iter;
+pub mod snapshot;
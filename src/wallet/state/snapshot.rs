@@ -0,0 +1,117 @@
+//! a snapshot of the wallet state, not to replay the whole log
+//!
+//! The snapshot is written in the `STATE` file of the wallet directory at
+//! the end of every sync: the UTxOs, the pointer of the last block synced
+//! and the number of entries of the wallet log it covers. Loading the
+//! state only replays the entries appended since (see
+//! `utils::create_wallet_state_from_logs`).
+//!
+//! The log stays the reference: the snapshot is ignored, and the whole
+//! log replayed, if it is missing, invalid or ahead of the log.
+//!
+
+use std::{fs, io, collections::BTreeMap, path::{Path, PathBuf}};
+use cardano::wallet::bip44;
+use serde_yaml;
+
+use super::{ptr::StatePtr, utxo::UTxO, lookup::Address};
+
+const WALLET_SNAPSHOT_FILE : &'static str = "STATE";
+
+/// the addresses the lookup structure needs to acknowledge to resume from
+/// a snapshot (see `lookup::AddressLookup::acknowledge`)
+///
+/// Only the sequential indices need it: the last address used in each
+/// account and chain is enough to generate the addresses after it.
+#[derive(Debug, Clone, Default)]
+pub struct KnownAddresses(BTreeMap<(u32, u32), bip44::Addressing>);
+impl KnownAddresses {
+    pub fn new() -> Self { KnownAddresses(BTreeMap::new()) }
+
+    pub fn record(&mut self, address: &Address) {
+        if let Address::Bip44(addressing) = address {
+            let key = (addressing.account.get_account_number(), addressing.change);
+            let is_later = match self.0.get(&key) {
+                None => true,
+                Some(known) => known.index < addressing.index,
+            };
+            if is_later { self.0.insert(key, addressing.clone()); }
+        }
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.0.values().cloned().map(Address::Bip44).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub ptr: StatePtr,
+    /// the number of entries of the wallet log applied to the snapshot
+    pub log_entries: u64,
+    pub utxos: Vec<UTxO<Address>>,
+    pub addresses: Vec<Address>,
+}
+impl Snapshot {
+    fn file(wallet_path: &Path) -> PathBuf { wallet_path.join(WALLET_SNAPSHOT_FILE) }
+
+    /// `None` if no snapshot has been written yet
+    pub fn load(wallet_path: &Path) -> io::Result<Option<Self>> {
+        let file = Self::file(wallet_path);
+        if ! file.is_file() { return Ok(None); }
+
+        let content = fs::read(file)?;
+        serde_yaml::from_slice(&content).map(Some).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })
+    }
+
+    /// written in `<file>.tmp` first then renamed, a snapshot is never
+    /// half written
+    pub fn save(&self, wallet_path: &Path) -> io::Result<()> {
+        let file = Self::file(wallet_path);
+        let tmp = file.with_extension("tmp");
+
+        let content = serde_yaml::to_vec(self).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })?;
+        fs::write(&tmp, content)?;
+        fs::rename(tmp, file)
+    }
+
+    /// to be called whenever the log is deleted or rewritten
+    pub fn delete(wallet_path: &Path) -> io::Result<()> {
+        let file = Self::file(wallet_path);
+        if file.is_file() { fs::remove_file(file)?; }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addressing(account: u32, change: u32, index: u32) -> Address {
+        Address::Bip44(bip44::Addressing {
+            account: bip44::bip44::Account::new(account).unwrap(),
+            change: change,
+            index: bip44::Index::new(index).unwrap(),
+        })
+    }
+
+    #[test]
+    fn known_addresses_keep_the_last_index() {
+        let mut known = KnownAddresses::new();
+        known.record(&addressing(0, 0, 3));
+        known.record(&addressing(0, 0, 12));
+        known.record(&addressing(0, 0, 7));
+        known.record(&addressing(0, 1, 2));
+        known.record(&addressing(1, 0, 0));
+
+        let indices : Vec<(u32, u32, u32)> = known.addresses().into_iter().map(|address| match address {
+            Address::Bip44(a) => (a.account.get_account_number(), a.change, a.index.get_scheme_value()),
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(indices, vec![(0, 0, 12), (0, 1, 2), (1, 0, 0)]);
+    }
+}
@@ -1,58 +1,107 @@
 use super::utxo::{UTxO, UTxOs};
 use super::log::{Log};
-use super::{lookup::{AddressLookup, Address}, ptr::StatePtr};
+use super::{lookup::{AddressLookup, Address}, ptr::StatePtr, snapshot::{Snapshot, KnownAddresses}};
 use cardano::{tx::TxoPointer, coin::{self, Coin}, address::ExtendedAddr};
 
 #[derive(Debug)]
 pub struct State<T: AddressLookup> {
     pub ptr: StatePtr,
     pub lookup_struct: T,
-    pub utxos: UTxOs<Address>
+    pub utxos: UTxOs<Address>,
+    /// the addresses to acknowledge when resuming from a snapshot
+    pub known_addresses: KnownAddresses,
 }
 
 impl<T: AddressLookup> State<T> {
     pub fn new(ptr: StatePtr, lookup_struct: T) -> Self {
-        State { ptr: ptr, lookup_struct: lookup_struct, utxos: UTxOs::new() }
+        State { ptr: ptr, lookup_struct: lookup_struct, utxos: UTxOs::new(), known_addresses: KnownAddresses::new() }
     }
 
     pub fn from_logs<I: IntoIterator<Item = Log<Address>>>(mut lookup_struct: T, iter: I) -> Result<Result<Self, T>, T::Error>
     {
         let mut ptr = None;
         let mut utxos = UTxOs::new();
+        let mut known_addresses = KnownAddresses::new();
 
         for log in iter {
-            match log {
-                Log::Checkpoint(known_ptr) => ptr = Some(known_ptr),
-                Log::ReceivedFund(known_ptr, utxo) => {
-                    lookup_struct.acknowledge(utxo.credited_addressing.clone())?;
-                    ptr = Some(known_ptr);
-
-                    if let Some(utxo) = utxos.insert(utxo.extract_txin(), utxo) {
-                        error!("This UTxO was already in the UTxOs collection `{}'", utxo);
-                        panic!("The Wallet LOG file seems corrupted");
-                    };
-                },
-                Log::SpentFund(known_ptr, utxo) => {
-                    match utxos.remove(&utxo.extract_txin()) {
-                        Some(_) => { },
-                        None    => {
-                            error!("UTxO not in the known UTxOs collection `{}'", utxo);
-                            panic!("The Wallet LOG file seems corrupted");
-                        }
-                    };
-                    lookup_struct.acknowledge(utxo.credited_addressing.clone())?;
-                    ptr = Some(known_ptr);
-                },
-            }
+            ptr = Some(Self::apply_log(&mut lookup_struct, &mut utxos, &mut known_addresses, log)?);
         }
 
         if let Some(ptr) = ptr {
-           Ok(Ok(State { ptr: ptr, lookup_struct: lookup_struct, utxos: utxos }))
+           Ok(Ok(State { ptr: ptr, lookup_struct: lookup_struct, utxos: utxos, known_addresses: known_addresses }))
         } else {
             Ok(Err(lookup_struct))
         }
     }
 
+    /// the state of the snapshot, the logs appended since the snapshot are
+    /// to be applied with `apply_logs`
+    pub fn from_snapshot(mut lookup_struct: T, snapshot: Snapshot) -> Result<Self, T::Error> {
+        let mut known_addresses = KnownAddresses::new();
+        for address in snapshot.addresses {
+            known_addresses.record(&address);
+            lookup_struct.acknowledge(address)?;
+        }
+        let utxos = snapshot.utxos.into_iter().map(|utxo| (utxo.extract_txin(), utxo)).collect();
+        Ok(State { ptr: snapshot.ptr, lookup_struct: lookup_struct, utxos: utxos, known_addresses: known_addresses })
+    }
+
+    /// the snapshot of the state, covering the given number of entries of
+    /// the wallet log
+    pub fn snapshot(&self, log_entries: u64) -> Snapshot {
+        Snapshot {
+            ptr: self.ptr.clone(),
+            log_entries: log_entries,
+            utxos: self.utxos.values().cloned().collect(),
+            addresses: self.known_addresses.addresses(),
+        }
+    }
+
+    pub fn apply_logs<I: IntoIterator<Item = Log<Address>>>(&mut self, iter: I) -> Result<(), T::Error> {
+        for log in iter {
+            self.ptr = Self::apply_log(&mut self.lookup_struct, &mut self.utxos, &mut self.known_addresses, log)?;
+        }
+        Ok(())
+    }
+
+    /// drop the UTxOs and start again from the given pointer and logs (when
+    /// the blockchain rolled back). The lookup structure is kept, it can
+    /// only know more addresses than needed.
+    pub fn reset_with_logs<I: IntoIterator<Item = Log<Address>>>(&mut self, ptr: StatePtr, iter: I) -> Result<(), T::Error> {
+        self.ptr = ptr;
+        self.utxos = UTxOs::new();
+        self.known_addresses = KnownAddresses::new();
+        self.apply_logs(iter)
+    }
+
+    fn apply_log(lookup_struct: &mut T, utxos: &mut UTxOs<Address>, known_addresses: &mut KnownAddresses, log: Log<Address>) -> Result<StatePtr, T::Error> {
+        match log {
+            Log::Checkpoint(known_ptr) => Ok(known_ptr),
+            Log::ReceivedFund(known_ptr, utxo) => {
+                known_addresses.record(&utxo.credited_addressing);
+                lookup_struct.acknowledge(utxo.credited_addressing.clone())?;
+
+                if let Some(utxo) = utxos.insert(utxo.extract_txin(), utxo) {
+                    error!("This UTxO was already in the UTxOs collection `{}'", utxo);
+                    panic!("The Wallet LOG file seems corrupted");
+                };
+                Ok(known_ptr)
+            },
+            Log::SpentFund(known_ptr, utxo) => {
+                match utxos.remove(&utxo.extract_txin()) {
+                    Some(_) => { },
+                    None    => {
+                        error!("UTxO not in the known UTxOs collection `{}'", utxo);
+                        panic!("The Wallet LOG file seems corrupted");
+                    }
+                };
+                lookup_struct.acknowledge(utxo.credited_addressing.clone())?;
+                Ok(known_ptr)
+            },
+            Log::Transaction(known_ptr, _) => Ok(known_ptr),
+        }
+    }
+
     pub fn ptr<'a>(&'a self) -> &'a StatePtr { &self.ptr }
 
     pub fn total(&self) -> coin::Result<Coin> {
@@ -82,6 +131,7 @@ impl<T: AddressLookup> State<T> {
         for (ptr, utxo) in iter {
             if let Some(utxo) = self.lookup_struct.lookup(utxo)? {
                 self.ptr = ptr.clone();
+                self.known_addresses.record(&utxo.credited_addressing);
                 events.push(Log::ReceivedFund(ptr, utxo.clone()));
                 self.utxos.insert(utxo.extract_txin(), utxo);
             }
@@ -0,0 +1,52 @@
+use cardano::{coin::{self, Coin}, tx::{TxId}};
+use std::{fmt, time::{SystemTime}};
+
+/// the effect of a transaction on the wallet, recorded in the wallet log
+/// after the credits and debits of the transaction (see
+/// `log::Log::Transaction`).
+///
+/// This is what the statements are made of (see `wallet::statement`).
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxSummary {
+    pub transaction_id: TxId,
+
+    /// the start of the slot of the transaction's block, `None` if the
+    /// blockchain does not know the start of its network
+    pub time: Option<SystemTime>,
+
+    /// the sum of the outputs of the transaction credited to the wallet
+    pub received: Coin,
+
+    /// the sum of the UTxOs of the wallet spent by the transaction
+    pub spent: Coin,
+
+    /// the fee of the transaction, if paid by the wallet: only known when
+    /// all the inputs of the transaction are the wallet's
+    pub fee: Option<Coin>,
+}
+impl TxSummary {
+    /// the fee of a transaction spending `spent` from `nr_spent` of its
+    /// `nr_inputs` inputs, to outputs of `outputs` in total
+    pub fn fee(nr_inputs: usize, nr_spent: usize, spent: Coin, outputs: Coin) -> Option<Coin> {
+        if nr_spent == 0 || nr_spent != nr_inputs { return None; }
+        (spent - outputs).ok()
+    }
+
+    /// the transaction credited the wallet (`received` is more than `spent`)
+    pub fn is_credit(&self) -> bool { self.received >= self.spent }
+
+    /// the absolute value of the change of the wallet's balance
+    pub fn delta(&self) -> coin::Result<Coin> {
+        if self.is_credit() { self.received - self.spent } else { self.spent - self.received }
+    }
+}
+impl fmt::Display for TxSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transaction `{}' received {}Ada, spent {}Ada", self.transaction_id, self.received, self.spent)?;
+        if let Some(fee) = self.fee {
+            write!(f, " (fee {}Ada)", fee)?;
+        }
+        Ok(())
+    }
+}
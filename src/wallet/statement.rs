@@ -0,0 +1,217 @@
+//! the statement of a wallet, for accounting
+//!
+//! One row per transaction of the wallet, made of the transaction
+//! summaries of the wallet log (see `state::txsummary`): the date of the
+//! block, the direction, the amount and the fee (in lovelace) and the
+//! balance of the wallet after the transaction. The balance is computed
+//! over the whole log, then the rows are filtered (see `Filter`).
+//!
+
+use std::{io, str::FromStr, time::{Duration, SystemTime}};
+use cardano::coin::{self, Coin};
+use humantime;
+
+use super::state::{log::Log, txsummary::TxSummary};
+
+/// the format of the statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// human readable, one transaction per line
+    Text,
+    /// comma separated values, with a header line
+    Csv,
+}
+impl Format {
+    /// the names accepted by `from_str`
+    pub const NAMES : &'static [&'static str] = &["text", "csv"];
+}
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv"  => Ok(Format::Csv),
+            _ => Err(format!("unknown statement format `{}', expected one of: {}", s, Format::NAMES.join(", "))),
+        }
+    }
+}
+
+const DAY : Duration = Duration::from_secs(24 * 3600);
+
+/// a date of the filter: a day (`2018-10-01`, UTC) or a time
+/// (`2018-10-01T12:00:00Z`, RFC 3339)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    start: SystemTime,
+    whole_day: bool,
+}
+impl Date {
+    /// the end of the date, excluded: the next day for a day, the next
+    /// second for a time
+    fn end(&self) -> SystemTime {
+        if self.whole_day { self.start + DAY } else { self.start + Duration::from_secs(1) }
+    }
+}
+impl FromStr for Date {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let whole_day = s.len() == 10;
+        let time = if whole_day { format!("{} 00:00:00", s) } else { s.to_owned() };
+        match humantime::parse_rfc3339_weak(&time) {
+            Ok(start) => Ok(Date { start, whole_day }),
+            Err(_) => Err(format!("invalid date `{}', expected YYYY-MM-DD or an RFC 3339 time (YYYY-MM-DDTHH:MM:SSZ)", s)),
+        }
+    }
+}
+
+/// the rows to keep in the statement, the bounds are included
+///
+/// The transactions of an unknown date (see `TxSummary::time`) are left
+/// out once filtered by date.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub from_date: Option<Date>,
+    pub to_date: Option<Date>,
+    /// the minimum amount, in lovelace
+    pub min_amount: Option<u64>,
+}
+impl Filter {
+    pub fn accepts(&self, row: &Row) -> bool {
+        if self.from_date.is_some() || self.to_date.is_some() {
+            let time = match row.time {
+                None => return false,
+                Some(time) => time,
+            };
+            if self.from_date.map(|from| time < from.start).unwrap_or(false) { return false; }
+            if self.to_date.map(|to| time >= to.end()).unwrap_or(false) { return false; }
+        }
+        self.min_amount.map(|min| row.amount >= min).unwrap_or(true)
+    }
+}
+
+/// a transaction of the statement, the amounts are in lovelace
+#[derive(Debug, Clone, Serialize)]
+pub struct Row {
+    #[serde(skip_serializing)]
+    pub time: Option<SystemTime>,
+    /// the start of the slot of the block, in RFC 3339
+    pub date: Option<String>,
+    pub transaction_id: String,
+    /// `in` if the transaction credited the wallet, `out` otherwise
+    pub direction: &'static str,
+    /// the amount moved, without the fee
+    pub amount: u64,
+    /// the fee paid by the wallet, `None` for the credits and if unknown
+    pub fee: Option<u64>,
+    /// the balance of the wallet after the transaction
+    pub balance: u64,
+}
+impl Row {
+    fn new(summary: &TxSummary, balance: Coin) -> coin::Result<Self> {
+        let delta = summary.delta()?;
+        let (direction, amount, fee) = if summary.is_credit() {
+            ("in", delta, None)
+        } else {
+            ("out", (delta - summary.fee.unwrap_or(Coin::zero()))?, summary.fee)
+        };
+        Ok(Row {
+            time: summary.time,
+            date: summary.time.map(|time| format!("{}", humantime::format_rfc3339(time))),
+            transaction_id: format!("{}", summary.transaction_id),
+            direction,
+            amount: amount.into(),
+            fee: fee.map(|fee| fee.into()),
+            balance: balance.into(),
+        })
+    }
+
+    /// the row as a line of CSV, see `write_csv`
+    pub fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{}",
+            self.date.as_ref().map(|date| date.as_str()).unwrap_or(""),
+            self.transaction_id,
+            self.direction,
+            self.amount,
+            self.fee.map(|fee| format!("{}", fee)).unwrap_or_default(),
+            self.balance
+        )
+    }
+}
+
+/// the rows of the transactions of the wallet log, in the order of the
+/// log (the order of the blockchain), accepted by the filter
+pub fn rows<A, I>(logs: I, filter: &Filter) -> coin::Result<Vec<Row>>
+    where I: IntoIterator<Item = Log<A>>
+{
+    let mut balance = Coin::zero();
+    let mut rows = Vec::new();
+    for log in logs {
+        if let Log::Transaction(_, summary) = log {
+            balance = ((balance + summary.received)? - summary.spent)?;
+            let row = Row::new(&summary, balance)?;
+            if filter.accepts(&row) { rows.push(row); }
+        }
+    }
+    Ok(rows)
+}
+
+/// write the rows in CSV, after the header line
+pub fn write_csv<W: io::Write>(writer: &mut W, rows: &[Row]) -> io::Result<()> {
+    writeln!(writer, "date,transaction_id,direction,amount,fee,balance")?;
+    for row in rows {
+        writeln!(writer, "{}", row.to_csv())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{block::HeaderHash, tx::TxId};
+    use wallet::state::{ptr::StatePtr, lookup::Address};
+
+    fn transaction(day: u64, received: u64, spent: u64, fee: Option<u64>) -> Log<Address> {
+        let summary = TxSummary {
+            transaction_id: TxId::new(&day.to_le_bytes()),
+            time: Some(SystemTime::UNIX_EPOCH + DAY * day as u32),
+            received: Coin::new(received).unwrap(),
+            spent: Coin::new(spent).unwrap(),
+            fee: fee.map(|fee| Coin::new(fee).unwrap()),
+        };
+        Log::Transaction(StatePtr::new_before_genesis(HeaderHash::from([0; 32])), summary)
+    }
+
+    fn logs() -> Vec<Log<Address>> {
+        vec![ transaction(0, 1_000_000, 0, None)
+            , transaction(1, 300_000, 500_000, Some(170_000))
+            , transaction(2, 20_000, 0, None)
+            ]
+    }
+
+    #[test]
+    fn balance_and_fees() {
+        let rows = rows(logs(), &Filter::default()).unwrap();
+        let rows : Vec<String> = rows.iter().map(|row| format!("{},{},{:?},{}", row.direction, row.amount, row.fee, row.balance)).collect();
+        assert_eq!(rows, vec![ "in,1000000,None,1000000"
+                             , "out,30000,Some(170000),800000"
+                             , "in,20000,None,820000"
+                             ]);
+    }
+
+    #[test]
+    fn filter() {
+        let filter = Filter {
+            from_date: Some("1970-01-02".parse().unwrap()),
+            to_date: Some("1970-01-02".parse().unwrap()),
+            min_amount: None,
+        };
+        let rows = rows(logs(), &filter).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].date, Some("1970-01-02T00:00:00Z".to_owned()));
+        assert_eq!(rows[0].balance, 800_000);
+
+        let filter = Filter { min_amount: Some(25_000), ..Filter::default() };
+        assert_eq!(self::rows(logs(), &filter).unwrap().len(), 2);
+        assert!("01/10/2018".parse::<Date>().is_err());
+    }
+}
@@ -4,26 +4,43 @@
 //! manipulate wallets, load filter, or even create.
 //!
 
-use super::{Wallet};
-use super::state::{log, ptr, state, lookup, iter::TransactionIterator, utxo::UTxO, ptr::{StatePtr}};
+use super::{Wallet, signer::{self, Signer}};
+use super::state::{log, ptr, state, lookup, iter::TransactionIterator, utxo::UTxO, ptr::{StatePtr}, snapshot::Snapshot, txsummary::TxSummary};
 use super::error::{Error};
-use super::config::{HDWalletModel};
 
 use std::{path::PathBuf, io::Write};
-use cardano::{address::ExtendedAddr, block::{BlockDate}, config::ProtocolMagic, tx::{TxInWitness, TxId}};
-
-use utils::{term::{Term, style::{Style}}};
-
-use blockchain::{Blockchain};
-
+use cardano::{address::ExtendedAddr, block::{BlockDate}, coin::{self, Coin}, config::ProtocolMagic, tx::{TxInWitness, TxId}};
+
+use utils::{term::{Term, style::{Style}}, cancel::CancelToken, logging};
+
+use blockchain::{self, Blockchain, iter::{Bound, RangeBounds}};
+
+/// apply the transactions of the blockchain to the wallet state
+///
+/// the sync stops between two transactions once `cancel` is cancelled,
+/// the logs written so far are kept and the next sync resumes from there.
+/// If the last block synced is no longer in the blockchain (the local tip
+/// rolled back) the logs of the abandoned blocks are dropped first (see
+/// `rollback_wallet_state`).
+///
+/// The snapshot of the state is saved at the end, for the next commands
+/// to load the state without replaying the whole log.
 pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
                                          , wallet: &Wallet
                                          , blockchain: &Blockchain
                                          , state: &mut state::State<LS>
+                                         , cancel: &CancelToken
                                          )
     where LS: lookup::AddressLookup
 {
-    let blockchain_tip = blockchain.load_tip().0;
+    let blockchain_tip = blockchain.load_tip().unwrap_or_else(|e| blockchain::commands::fail_with(term, e)).0;
+
+    if state.ptr().latest_addr.is_some() {
+        let hash = state.ptr().latest_known_hash.clone();
+        if ! blockchain.is_ancestor(&hash).unwrap_or_else(|e| term.fail_with(e)) {
+            rollback_wallet_state(term, wallet, blockchain, state);
+        }
+    }
 
     let from_ptr = state.ptr().clone();
     let from = from_ptr.latest_known_hash;
@@ -36,7 +53,14 @@ pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
     progress.set_message("loading transactions... ");
 
     let mut last_block_date = from_date;
-    for res in TransactionIterator::new(progress, blockchain.iter_to_tip(from).unwrap_or_else(|e| term.fail_with(e)) ) {
+    // the latest known block has already been processed
+    let bounds = RangeBounds::new(Bound::Excluded, Bound::Included);
+    let block_iterator = blockchain.iter_between(from, blockchain_tip.hash.clone(), bounds).unwrap_or_else(|e| term.fail_with(e));
+    for res in TransactionIterator::new(progress, block_iterator) {
+        if cancel.is_cancelled() {
+            term.warn("wallet sync cancelled\n").unwrap();
+            break;
+        }
         let (ptr, txaux) = res.unwrap_or_else(|e| term.fail_with(e));
         debug!("transactions in: {}", ptr);
 
@@ -52,36 +76,184 @@ pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
             last_block_date = addr.clone();
         }
 
-        {
-            let logs = state.forward_with_txins(
-                txaux.tx.inputs.iter().map(|txin| (ptr.clone(), txin))
-            ).unwrap_or_else(|e| term.fail_with(e));
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
-            for log in logs { writer.append(&log).unwrap_or_else(|e| term.fail_with(e)); }
+        let txid = txaux.tx.id();
+        let nr_inputs = txaux.tx.inputs.len();
+        let outputs = sum_coins(txaux.tx.outputs.iter().map(|txout| txout.value)).unwrap_or_else(|e| term.fail_with(e));
+
+        let spent_logs = state.forward_with_txins(
+            txaux.tx.inputs.iter().map(|txin| (ptr.clone(), txin))
+        ).unwrap_or_else(|e| term.fail_with(e));
+
+        let received_logs = state.forward_with_utxos(
+            txaux.tx.outputs.into_iter().enumerate().map(|(idx, txout)| {
+                ( ptr.clone()
+                , UTxO {
+                    transaction_id: txid.clone(),
+                    index_in_transaction: idx as u32,
+                    credited_address: txout.address.clone(),
+                    credited_addressing: txout.address,
+                    credited_value: txout.value
+                  }
+                )
+            })
+        ).unwrap_or_else(|e| term.fail_with(e));
+
+        if spent_logs.is_empty() && received_logs.is_empty() { continue; }
+
+        let spent = sum_coins(spent_logs.iter().filter_map(log_value)).unwrap_or_else(|e| term.fail_with(e));
+        let received = sum_coins(received_logs.iter().filter_map(log_value)).unwrap_or_else(|e| term.fail_with(e));
+        let summary = TxSummary {
+            transaction_id: txid,
+            time: ptr.latest_addr.as_ref().and_then(|date| blockchain.slot_start(date).ok()),
+            received,
+            spent,
+            fee: TxSummary::fee(nr_inputs, spent_logs.len(), spent, outputs),
+        };
+
+        // the logs of the transaction and its summary are written together,
+        // through the same lock
+        let log_lock = lock_wallet_log(&wallet);
+        let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+        for log in spent_logs.iter().chain(received_logs.iter()) {
+            writer.append(log).unwrap_or_else(|e| term.fail_with(e));
         }
+        let log : log::Log<ExtendedAddr> = log::Log::Transaction(ptr.clone(), summary);
+        writer.append(&log).unwrap_or_else(|e| term.fail_with(e));
+    }
 
-        {
-            let txid = txaux.tx.id();
-            let logs = state.forward_with_utxos(
-                txaux.tx.outputs.into_iter().enumerate().map(|(idx, txout)| {
-                    ( ptr.clone()
-                    , UTxO {
-                        transaction_id: txid.clone(),
-                        index_in_transaction: idx as u32,
-                        credited_address: txout.address.clone(),
-                        credited_addressing: txout.address,
-                        credited_value: txout.value
-                      }
-                    )
-                })
-            ).unwrap_or_else(|e| term.fail_with(e));
+    // all the blocks up to the tip are applied: the next sync starts from
+    // the tip, even if its last blocks have nothing for the wallet
+    if ! cancel.is_cancelled() && state.ptr().latest_known_hash != blockchain_tip.hash {
+        let tip_ptr = StatePtr::new(blockchain_tip.date, blockchain_tip.hash.clone());
+        let log_lock = lock_wallet_log(&wallet);
+        let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+        let log : log::Log<ExtendedAddr> = log::Log::Checkpoint(tip_ptr.clone());
+        writer.append(&log).unwrap_or_else(|e| term.fail_with(e));
+        state.ptr = tip_ptr;
+    }
+
+    logging::event(::log::Level::Info, "wallet.synced", &[
+        ("wallet", &wallet.name), ("from", &from_date), ("to", &state.ptr), ("cancelled", &cancel.is_cancelled()),
+    ]);
+    save_wallet_snapshot(wallet, state);
+}
+
+fn sum_coins<I: IntoIterator<Item = Coin>>(coins: I) -> coin::Result<Coin> {
+    coins.into_iter().fold(Ok(Coin::zero()), |acc, v| acc.and_then(|acc| acc + v))
+}
+
+/// the value of the UTxO credited or spent by the log
+fn log_value<A>(log: &log::Log<A>) -> Option<Coin> {
+    match log {
+        log::Log::ReceivedFund(_, utxo) => Some(utxo.credited_value),
+        log::Log::SpentFund(_, utxo) => Some(utxo.credited_value),
+        _ => None,
+    }
+}
+
+/// drop the logs of the blocks no longer in the blockchain, and the UTxOs
+/// they credited or spent
+///
+/// The logs are in the order of the blockchain: they are kept up to the
+/// last one of a block still in the local chain, the log is rewritten
+/// with them.
+fn rollback_wallet_state<LS>( term: &mut Term
+                            , wallet: &Wallet
+                            , blockchain: &Blockchain
+                            , state: &mut state::State<LS>
+                            )
+    where LS: lookup::AddressLookup
+{
+    let mut logs : Vec<log::Log<lookup::Address>> = {
+        let log_lock = lock_wallet_log(wallet);
+        let reader = log::LogReader::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+        reader.into_iter().map(|r| r.unwrap_or_else(|e| term.fail_with(e))).collect()
+    };
+
+    let mut keep = logs.len();
+    let mut checked = None;
+    while keep > 0 {
+        let hash = logs[keep - 1].ptr().latest_known_hash.clone();
+        if checked.as_ref() != Some(&hash) {
+            if blockchain.is_ancestor(&hash).unwrap_or_else(|e| term.fail_with(e)) { break; }
+            checked = Some(hash);
+        }
+        keep -= 1;
+    }
+    let dropped = logs.len() - keep;
+    logs.truncate(keep);
+    term.warn(&format!("the blockchain rolled back, dropping {} entries of the wallet log\n", dropped)).unwrap();
+    logging::event(::log::Level::Warn, "wallet.rollback", &[("wallet", &wallet.name), ("dropped", &dropped)]);
+
+    wallet.delete_log().unwrap_or_else(|e| term.fail_with(e));
+    {
+        let log_lock = lock_wallet_log(wallet);
+        let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+        for log in logs.iter() { writer.append(log).unwrap_or_else(|e| term.fail_with(e)); }
+    }
+
+    let initial_ptr = ptr::StatePtr::new_before_genesis(blockchain.config.genesis.clone());
+    state.reset_with_logs(initial_ptr, logs).unwrap_or_else(|e| term.fail_with(e));
+}
+
+/// save the snapshot of the state, with the number of entries of the log
+/// it covers
+///
+/// the snapshot is only a cache: failing to write it is logged and ignored
+fn save_wallet_snapshot<LS>(wallet: &Wallet, state: &state::State<LS>)
+    where LS: lookup::AddressLookup
+{
+    let log_entries = log::LogReader::open(lock_wallet_log(wallet))
+        .and_then(|mut reader| reader.skip(u64::max_value()));
+    let result = match log_entries {
+        Err(err) => Err(format!("{}", err)),
+        Ok(log_entries) => state.snapshot(log_entries).save(&wallet.directory()).map_err(|err| format!("{}", err)),
+    };
+    if let Err(err) = result {
+        warn!("cannot save the state snapshot of wallet `{}': {}", wallet.name, err);
+    }
+}
+
+/// feed the outputs of all the transactions of the blockchain to the
+/// lookup structure, without updating any wallet state or log
+///
+/// The lookup structure learns from the addresses it recognises (see
+/// `SequentialBip44Lookup::threshold_generate`): a pass finds the addresses
+/// within the gap limit of the addresses found in the previous passes.
+/// Returns `false` if the scan was cancelled.
+pub fn scan_blockchain_addresses<LS>( term: &mut Term
+                                    , blockchain: &Blockchain
+                                    , lookup_struct: &mut LS
+                                    , cancel: &CancelToken
+                                    ) -> bool
+    where LS: lookup::AddressLookup
+{
+    let blockchain_tip = blockchain.load_tip().unwrap_or_else(|e| blockchain::commands::fail_with(term, e)).0;
+    let num_blocks = blockchain_tip.date - BlockDate::Genesis(0);
 
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
-            for log in logs { writer.append(&log).unwrap_or_else(|e| term.fail_with(e)); }
+    let progress = term.progress_bar(num_blocks as u64);
+    progress.set_message("scanning addresses... ");
+
+    let bounds = RangeBounds::new(Bound::Excluded, Bound::Included);
+    let block_iterator = blockchain.iter_between(blockchain.config.genesis.clone(), blockchain_tip.hash.clone(), bounds)
+        .unwrap_or_else(|e| term.fail_with(e));
+    for res in TransactionIterator::new(progress, block_iterator) {
+        if cancel.is_cancelled() { return false; }
+        let (_, txaux) = res.unwrap_or_else(|e| term.fail_with(e));
+
+        let txid = txaux.tx.id();
+        for (idx, txout) in txaux.tx.outputs.into_iter().enumerate() {
+            let utxo = UTxO {
+                transaction_id: txid.clone(),
+                index_in_transaction: idx as u32,
+                credited_address: txout.address.clone(),
+                credited_addressing: txout.address,
+                credited_value: txout.value
+            };
+            lookup_struct.lookup(utxo).unwrap_or_else(|e| term.fail_with(e));
         }
     }
+    true
 }
 
 pub fn display_wallet_state_utxos<LS>( term: &mut Term
@@ -98,10 +270,67 @@ pub fn display_wallet_state_utxos<LS>( term: &mut Term
     }
 }
 
+/// an entry of the wallet's log, as output in JSON
+///
+/// the checkpoints have a `block`, the credits and debits have a
+/// `transaction_id`, an `index` and a `value` (in lovelace), the
+/// transactions have a `transaction_id` and the `fee` paid by the wallet.
+/// The `wallet statement` rows are made of the transactions (see
+/// `statement::Row`).
+#[derive(Serialize)]
+struct StatementEntry {
+    /// `checkpoint`, `credit` or `debit`
+    kind: &'static str,
+    date: String,
+    block: Option<String>,
+    transaction_id: Option<String>,
+    index: Option<u32>,
+    value: Option<u64>,
+    fee: Option<u64>,
+}
+impl StatementEntry {
+    fn utxo<L>(kind: &'static str, ptr: StatePtr, utxo: UTxO<L>) -> Self {
+        StatementEntry {
+            kind,
+            date: format!("{}", ptr.latest_block_date()),
+            block: None,
+            transaction_id: Some(format!("{}", utxo.transaction_id)),
+            index: Some(utxo.index_in_transaction),
+            value: Some(utxo.credited_value.into()),
+            fee: None,
+        }
+    }
+}
+impl From<log::Log<lookup::Address>> for StatementEntry {
+    fn from(log: log::Log<lookup::Address>) -> Self {
+        match log {
+            log::Log::Checkpoint(ptr) => StatementEntry {
+                kind: "checkpoint",
+                date: format!("{}", ptr.latest_block_date()),
+                block: Some(format!("{}", ptr.latest_known_hash)),
+                transaction_id: None,
+                index: None,
+                value: None,
+                fee: None,
+            },
+            log::Log::ReceivedFund(ptr, utxo) => StatementEntry::utxo("credit", ptr, utxo),
+            log::Log::SpentFund(ptr, utxo) => StatementEntry::utxo("debit", ptr, utxo),
+            log::Log::Transaction(ptr, summary) => StatementEntry {
+                kind: "transaction",
+                date: format!("{}", ptr.latest_block_date()),
+                block: None,
+                transaction_id: Some(format!("{}", summary.transaction_id)),
+                index: None,
+                value: None,
+                fee: summary.fee.map(|fee| fee.into()),
+            },
+        }
+    }
+}
+
 pub fn display_wallet_state_logs<LS>( term: &mut Term
                                     , wallet: &Wallet
                                     , _state: &mut state::State<LS>
-                                    , pretty: bool
                                     )
     where LS: lookup::AddressLookup
 {
@@ -117,61 +346,38 @@ pub fn display_wallet_state_logs<LS>( term: &mut Term
         }
     });
 
+    if term.is_json() {
+        let entries : Vec<StatementEntry> = reader.map(StatementEntry::from).collect();
+        term.json(&entries).unwrap();
+        return;
+    }
+
     for log in reader {
         match log {
             log::Log::Checkpoint(ptr) => {
-                if ! pretty {
-                    writeln!(term, "{} {} ({})",
-                        style!("checkpoint").cyan(),
-                        style!(ptr.latest_block_date()),
-                        style!(ptr.latest_known_hash)
-                    ).unwrap();
-                    writeln!(term, "").unwrap();
-                }
-            },
-            log::Log::ReceivedFund(ptr, utxo) => {
-                if pretty {
-                    display_utxo(term, ptr, utxo, false);
-                } else {
-                    dump_utxo(term, ptr, utxo, false);
-                }
+                writeln!(term, "{} {} ({})",
+                    style!("checkpoint").cyan(),
+                    style!(ptr.latest_block_date()),
+                    style!(ptr.latest_known_hash)
+                ).unwrap();
+                writeln!(term, "").unwrap();
             },
-            log::Log::SpentFund(ptr, utxo) => {
-                if pretty {
-                    display_utxo(term, ptr, utxo, true);
-                } else {
-                    dump_utxo(term, ptr, utxo, true);
+            log::Log::ReceivedFund(ptr, utxo) => dump_utxo(term, ptr, utxo, false),
+            log::Log::SpentFund(ptr, utxo) => dump_utxo(term, ptr, utxo, true),
+            log::Log::Transaction(ptr, summary) => {
+                writeln!(term, "{} {}", style!("transaction").cyan(), style!(summary.transaction_id)).unwrap();
+                writeln!(term, "Date {}", style!(ptr.latest_block_date())).unwrap();
+                writeln!(term, "Received {}", style!(format!("{}", summary.received)).green()).unwrap();
+                writeln!(term, "Spent {}", style!(format!("{}", summary.spent)).red()).unwrap();
+                if let Some(fee) = summary.fee {
+                    writeln!(term, "Fee {}", style!(format!("{}", fee)).red()).unwrap();
                 }
+                writeln!(term, "").unwrap()
             }
         }
     }
 }
 
-pub fn display_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
-    let ptr = format!("{}", style!(ptr.latest_block_date()));
-    let tid = format!("{}", style!(utxo.transaction_id));
-    let tii = format!("{:03}", utxo.index_in_transaction);
-    const WIDTH : usize = 14;
-    let credit = if debit {
-        format!("{:>width$}", " ", width = WIDTH)
-    } else {
-        format!("{:>width$}", format!("{}", utxo.credited_value), width = WIDTH)
-    };
-    let debit = if debit {
-        format!("{:>width$}", format!("{}", utxo.credited_value), width = WIDTH)
-    } else {
-        format!("{:>width$}", " ", width = WIDTH)
-    };
-
-    writeln!(term, "{:9}|{}.{}|{}|{}",
-        ::console::pad_str(&ptr, 9, ::console::Alignment::Left, None),
-        tid,
-        style!(tii).yellow(),
-        style!(credit).green(),
-        style!(debit).red()
-    ).unwrap()
-}
-
 pub fn dump_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool) {
     let title = if debit {
         style!("debit").red()
@@ -196,21 +402,56 @@ pub fn dump_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool)
 }
 
 
+/// load the wallet state from the snapshot and the logs appended since,
+/// or from the whole log when there is no valid snapshot
 pub fn create_wallet_state_from_logs<LS>(term: &mut Term, wallet: &Wallet, root_dir: PathBuf, lookup_structure: LS) -> state::State<LS>
     where LS: lookup::AddressLookup
 {
+    let snapshot = match Snapshot::load(&wallet.directory()) {
+        Err(err) => {
+            warn!("ignoring the invalid state snapshot of wallet `{}': {}", wallet.name, err);
+            None
+        },
+        Ok(snapshot) => snapshot,
+    };
+
     let log_lock = lock_wallet_log(wallet);
-    let state = state::State::from_logs(lookup_structure,
-        log::LogReader::open(log_lock).unwrap_or_else(|e| term.fail_with(e))
-            .into_iter().filter_map(|r| {
-                match r {
-                    Err(err) => {
-                        term.fail_with(err)
-                    },
-                    Ok(v) => Some(v)
-                }
-            })
-    ).unwrap_or_else(|e| term.fail_with(e));
+    let mut reader = log::LogReader::open(log_lock).unwrap_or_else(|e| term.fail_with(e));
+
+    let snapshot = match snapshot {
+        None => None,
+        Some(snapshot) => {
+            let skipped = reader.skip(snapshot.log_entries).unwrap_or_else(|e| term.fail_with(e));
+            if skipped == snapshot.log_entries { Some(snapshot) } else {
+                // the log has been rewritten since the snapshot
+                warn!("ignoring the outdated state snapshot of wallet `{}'", wallet.name);
+                reader = log::LogReader::open(reader.release_lock()).unwrap_or_else(|e| term.fail_with(e));
+                None
+            }
+        }
+    };
+
+    let state = match snapshot {
+        Some(snapshot) => {
+            let mut state = state::State::from_snapshot(lookup_structure, snapshot).unwrap_or_else(|e| term.fail_with(e));
+            state.apply_logs(
+                reader.into_iter().map(|r| r.unwrap_or_else(|err| term.fail_with(err)))
+            ).unwrap_or_else(|e| term.fail_with(e));
+            Ok(state)
+        },
+        None => {
+            state::State::from_logs(lookup_structure,
+                reader.into_iter().filter_map(|r| {
+                    match r {
+                        Err(err) => {
+                            term.fail_with(err)
+                        },
+                        Ok(v) => Some(v)
+                    }
+                })
+            ).unwrap_or_else(|e| term.fail_with(e))
+        }
+    };
     match state {
         Ok(state) => state,
         Err(lookup_structure) => {
@@ -229,8 +470,12 @@ pub fn load_bip44_lookup_structure(term: &mut Term, wallet: &Wallet) -> lookup::
     // TODO: to prevent from the need of the password, we can ask the user to create accounts ahead.
     //       if we store the wallet's account public keys in the config file we may not need for the
     //       password (and for the private key).
-    term.info("Enter the wallet password.\n").unwrap();
-    let password = term.password("wallet password: ").unwrap();
+    let gap_limit = wallet.config.gap_limit.unwrap_or(lookup::sequentialindex::DEFAULT_GAP_LIMIT);
+    if let Some(account) = wallet.config.watch_only {
+        return watch_only_bip44_lookup_structure(term, wallet, account).with_gap_limit(gap_limit);
+    }
+
+    let password = ask_wallet_password(term, wallet);
 
     let wallet = match wallet.get_wallet_bip44(password.as_bytes()) {
         Err(Error::CannotRetrievePrivateKeyInvalidPassword) => {
@@ -248,12 +493,41 @@ pub fn load_bip44_lookup_structure(term: &mut Term, wallet: &Wallet) -> lookup::
         },
         Ok(wallet) => { wallet }
     };
-    lookup::sequentialindex::SequentialBip44Lookup::new(wallet)
+    lookup::sequentialindex::SequentialBip44Lookup::new(wallet).with_gap_limit(gap_limit)
+}
+fn watch_only_bip44_lookup_structure(term: &mut Term, wallet: &Wallet, account: u32) -> lookup::sequentialindex::SequentialBip44Lookup {
+    let account = ::cardano::bip::bip44::Account::new(account).unwrap_or_else(|e| term.fail_with(e));
+    let xpub = match wallet.public_key {
+        Some(ref xpub) => xpub.clone(),
+        None => {
+            term.error(&format!("The account public key of the watch-only wallet `{}' is missing\n", wallet.name)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+    lookup::sequentialindex::SequentialBip44Lookup::watch_only(account, xpub, wallet.config.derivation_scheme)
+}
+
+/// the spending password of the wallet, not asked if the wallet is not
+/// encrypted (see `Wallet::is_encrypted`)
+pub fn ask_wallet_password(term: &mut Term, wallet: &Wallet) -> String {
+    if ! wallet.is_encrypted() { return String::new(); }
+
+    term.info("Enter the wallet password.\n").unwrap();
+    term.password("wallet password: ").unwrap()
 }
+
+/// exit if the wallet is watch-only, before asking for its password
+pub fn ensure_can_sign(term: &mut Term, wallet: &Wallet) {
+    if wallet.is_watch_only() {
+        term.error(&format!("Wallet `{}' is watch-only, it cannot sign transactions.\n", wallet.name)).unwrap();
+        term.info("Export the transaction and sign it on the device holding the wallet's private key.\n").unwrap();
+        ::std::process::exit(1);
+    }
+}
+
 pub fn load_randomindex_lookup_structure(term: &mut Term, wallet: &Wallet) -> lookup::randomindex::RandomIndexLookup {
     // in the case of the random index, we may not need the password if we have the public key
-    term.info("Enter the wallet password.\n").unwrap();
-    let password = term.password("wallet password: ").unwrap();
+    let password = ask_wallet_password(term, wallet);
 
     let wallet = match wallet.get_wallet_rindex(password.as_bytes()) {
         Err(Error::CannotRetrievePrivateKeyInvalidPassword) => {
@@ -265,6 +539,10 @@ pub fn load_randomindex_lookup_structure(term: &mut Term, wallet: &Wallet) -> lo
             term.info("The encrypted wallet password is in an invalid format. You might need to delete this wallet and recover it.").unwrap();
             ::std::process::exit(1);
         },
+        Err(Error::WatchOnlyWallet) => {
+            term.error("Watch-only wallets are only supported with the bip44 wallet scheme\n").unwrap();
+            ::std::process::exit(1);
+        },
         Err(err) => {
             term.error(IMPOSSIBLE_HAPPENED).unwrap();
             panic!("failing with an unexpected error {:#?}", err);
@@ -295,33 +573,16 @@ pub fn load_attached_blockchain(term: &mut Term, root_dir: PathBuf, name: Option
             ::std::process::exit(1);
         },
         Some(blockchain) => {
-            Blockchain::load(root_dir, blockchain)
+            blockchain::commands::load_read_only(term, root_dir, blockchain)
         }
     }
 }
 
+/// sign the input with the wallet's private key (see `signer::SoftwareSigner`)
 pub fn wallet_sign_tx(term: &mut Term, wallet: &Wallet, protocol_magic: ProtocolMagic, txid: &TxId, address: &lookup::Address) -> TxInWitness
 {
-    match wallet.config.hdwallet_model {
-        HDWalletModel::BIP44 => {
-            let wallet = load_bip44_lookup_structure(term, wallet);
-            if let lookup::Address::Bip44(addressing) = address {
-                let xprv = wallet.get_private_key(addressing);
-                TxInWitness::new(protocol_magic, &*xprv, txid)
-            } else {
-                panic!()
-            }
-        },
-        HDWalletModel::RandomIndex2Levels => {
-            let wallet = load_randomindex_lookup_structure(term, wallet);
-            if let lookup::Address::RIndex(addressing) = address {
-                let xprv = wallet.get_private_key(addressing);
-                TxInWitness::new(protocol_magic, &xprv, txid)
-            } else {
-                panic!()
-            }
-        },
-    }
+    let witness = signer::SoftwareSigner::new(wallet).sign(term, protocol_magic, txid, address);
+    witness.unwrap_or_else(|e| term.fail_with(e))
 }
 
 const IMPOSSIBLE_HAPPENED : &'static str = "The impossible happened